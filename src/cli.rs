@@ -1,2 +1,7 @@
 pub mod args;
 pub mod command;
+pub mod completions;
+pub mod history;
+pub mod man;
+pub mod render;
+pub mod schedule;
@@ -1,21 +1,136 @@
-use super::command::Command;
+use super::{command::Command, render::OutputFormat};
+use crate::{manifest::repo::Autostash, operations::backend::BackendKind};
 use clap::Parser;
-use std::path::PathBuf;
+use std::{
+    io::{stdin, IsTerminal},
+    path::PathBuf,
+    time::Duration,
+};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub struct Args {
     /// Reads from specific manifest file.
     /// Defaults to $XDG_CONFIG_DIR/repoteer/manifest.toml
-    #[clap(short, long, value_parser, value_name = "FILE")]
+    #[clap(short, long, value_parser, value_name = "FILE", global = true)]
     pub manifest: Option<PathBuf>,
 
+    /// Reads settings that apply to every repo, like hooks, from a specific config file.
+    /// Defaults to $HOME/.config/repoteer/config.toml, if it exists
+    #[clap(short, long, value_parser, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Remove worktrees of bare repos whose branches were deleted upstream.
+    /// Worktrees with local changes are never removed.
+    #[clap(long, global = true)]
+    pub prune: bool,
+
+    /// Stash local changes before pulling and restore them afterwards.
+    /// Applies to repos that do not set `autostash` in the manifest.
+    #[clap(long, value_enum, value_name = "MODE", global = true)]
+    pub autostash: Option<Autostash>,
+
+    /// Which backend is used to query and fetch repositories
+    #[clap(
+        long,
+        value_enum,
+        value_name = "BACKEND",
+        default_value = "cli",
+        global = true
+    )]
+    pub backend: BackendKind,
+
+    /// Only operate on the repo with this name.
+    /// Can be passed multiple times.
+    #[clap(long = "repo", value_name = "NAME", global = true)]
+    pub repos: Vec<String>,
+
+    /// Only operate on repos with this tag.
+    /// Can be passed multiple times, in which case repos need to have any of the tags.
+    #[clap(long = "tag", value_name = "TAG", global = true)]
+    pub tags: Vec<String>,
+
+    /// Print the git commands that would change repositories, instead of running them.
+    /// Read-only commands, like status checks and branch listings, are still run.
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+
+    /// Kill git commands that run longer than this, like `30s` or `5m`.
+    /// Commands can hang on a stalled network or a prompt for credentials.
+    #[clap(long, value_parser = humantime::parse_duration, value_name = "DURATION", global = true)]
+    pub timeout: Option<Duration>,
+
+    /// Stop operating on a repo once its git commands ran longer than this in total, like `10m`
+    #[clap(long, value_parser = humantime::parse_duration, value_name = "DURATION", global = true)]
+    pub repo_timeout: Option<Duration>,
+
+    /// How often git commands that talk to a remote are retried after network failures, like a
+    /// dropped connection or an HTTP 5xx response
+    #[clap(long, value_name = "COUNT", default_value = "2", global = true)]
+    pub retries: u32,
+
+    /// How long to wait before the first retry, which doubles with every further retry
+    #[clap(
+        long,
+        value_parser = humantime::parse_duration,
+        value_name = "DURATION",
+        default_value = "1s",
+        global = true
+    )]
+    pub retry_backoff: Duration,
+
+    /// Whether git may prompt for credentials or host key confirmations.
+    /// By default, git may only prompt when stdin is a terminal, and fails right away otherwise.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value = "auto",
+        global = true
+    )]
+    pub interactive: Interactive,
+
+    /// How progress is reported
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value = "plain",
+        global = true
+    )]
+    pub output: OutputFormat,
+
     #[clap(subcommand)]
     pub command: Option<Command>,
 }
 
+/// Enumerates when git may prompt for input
+#[derive(clap::ValueEnum, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Interactive {
+    /// Prompts are allowed when stdin is a terminal
+    #[default]
+    Auto,
+
+    /// Prompts are always allowed
+    Always,
+
+    /// Prompts are never allowed
+    Never,
+}
+
+impl Interactive {
+    /// Whether git may prompt for input
+    pub fn allows_prompts(self) -> bool {
+        match self {
+            Interactive::Auto => stdin().is_terminal(),
+            Interactive::Always => true,
+            Interactive::Never => false,
+        }
+    }
+}
+
 #[test]
 fn verify_cli() {
     use clap::CommandFactory;
-    return Args::command().debug_assert();
+    Args::command().debug_assert();
 }
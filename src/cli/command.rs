@@ -1,6 +1,7 @@
 use clap::Subcommand;
+use std::{path::PathBuf, time::Duration};
 
-#[derive(Subcommand, Debug, Copy, Clone)]
+#[derive(Subcommand, Debug, Clone)]
 /// Enumerates the different commands you can pass to repoteer
 pub enum Command {
     /// Clone (if repo is not cloned yet) or pull, then push repos
@@ -14,4 +15,202 @@ pub enum Command {
 
     /// Only push local changes to remote
     Push,
+
+    /// Show the local changes of every checkout
+    Status,
+
+    /// Fetch remote changes without changing any checkout, and show how far branches diverged
+    Fetch,
+
+    /// Record the commit every checkout is at in a lockfile next to the manifest
+    Lock,
+
+    /// Bring every checkout to a recorded commit
+    Checkout {
+        /// Check out the commits recorded by `lock`.
+        /// Checkouts with local changes are left alone.
+        #[clap(long)]
+        locked: bool,
+    },
+
+    /// Record the HEAD, branch tips, stashes and local changes of every repo, to restore them later
+    Snapshot {
+        /// Name of the snapshot, defaults to the current time
+        name: Option<String>,
+    },
+
+    /// Reset the branches and checkouts of every repo to a snapshot, where that is safe
+    Restore {
+        /// Name of the snapshot
+        name: String,
+
+        /// Also reset branches that moved away from the snapshot, instead of only those that gained
+        /// commits on top of it. The commits that are dropped are reported.
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Show the recorded results of past runs, of every repo or of a single one
+    History {
+        /// Name of the repo, whose every recorded run is shown
+        repo: Option<String>,
+    },
+
+    /// Keep running, and sync the repos periodically until stopped with SIGTERM or Ctrl-C
+    Watch {
+        /// How long to wait between syncs, like `15m`
+        #[clap(long, value_parser = humantime::parse_duration, value_name = "DURATION", default_value = "15m")]
+        interval: Duration,
+
+        /// Push a repo soon after a commit lands on one of its local branches, instead of waiting
+        /// for the next sync. Only available on Linux.
+        #[clap(long)]
+        push_on_commit: bool,
+
+        /// How long the branches of a repo need to stay unchanged before they are pushed
+        #[clap(long, value_parser = humantime::parse_duration, value_name = "DURATION", default_value = "30s")]
+        push_delay: Duration,
+
+        /// The longest a failing repo waits for its next sync, since the wait doubles with every
+        /// failure in a row
+        #[clap(long, value_parser = humantime::parse_duration, value_name = "DURATION", default_value = "6h")]
+        max_backoff: Duration,
+    },
+
+    /// Manage systemd user timers running repoteer periodically
+    Schedule {
+        /// Write and read the units in this directory, instead of the systemd user unit
+        /// directory. systemctl is only run for the systemd user unit directory.
+        #[clap(long, value_parser, value_name = "DIR", global = true)]
+        unit_dir: Option<PathBuf>,
+
+        #[clap(subcommand)]
+        action: ScheduleAction,
+    },
+
+    /// Print a completion script for a shell, which completes repo names and tags from the
+    /// manifest as well
+    Completions {
+        /// The shell the script is for
+        #[clap(value_enum)]
+        shell: CompletionShell,
+    },
+
+    /// Print the man page of repoteer, in roff format
+    Man,
+
+    /// Print the names of the repos in the manifest, one per line, for the completion scripts
+    #[clap(name = "__complete-repos", hide = true)]
+    CompleteRepos {
+        /// Print the tags of the repos instead of their names
+        #[clap(long)]
+        tags: bool,
+    },
+}
+
+/// Enumerates the shells `Command::Completions` writes scripts for
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Enumerates what `Command::Schedule` does with the systemd units
+#[derive(Subcommand, Debug, Clone)]
+pub enum ScheduleAction {
+    /// Write and enable a timer running repoteer on the selected repos periodically
+    Install {
+        /// How long to wait between runs, like `30m`
+        #[clap(long, value_parser = humantime::parse_duration, value_name = "DURATION")]
+        every: Duration,
+
+        /// The command being run
+        #[clap(long, value_enum, value_name = "COMMAND", default_value = "sync")]
+        command: ScheduledCommand,
+
+        /// Name of the schedule, defaults to the name of the command
+        #[clap(long)]
+        name: Option<String>,
+    },
+
+    /// List the installed schedules
+    Status,
+
+    /// Disable and remove a schedule
+    Remove {
+        /// Name of the schedule
+        name: String,
+    },
+}
+
+/// Enumerates the commands that can be scheduled
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScheduledCommand {
+    Sync,
+    Clone,
+    Fetch,
+    Pull,
+    Push,
+}
+
+impl ScheduledCommand {
+    /// Returns the `Command` being scheduled
+    pub fn command(self) -> Command {
+        match self {
+            ScheduledCommand::Sync => Command::Sync,
+            ScheduledCommand::Clone => Command::Clone,
+            ScheduledCommand::Fetch => Command::Fetch,
+            ScheduledCommand::Pull => Command::Pull,
+            ScheduledCommand::Push => Command::Push,
+        }
+    }
+}
+
+impl Command {
+    /// Returns the name of the command, as it is passed on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Sync => "sync",
+            Command::Clone => "clone",
+            Command::Pull => "pull",
+            Command::Push => "push",
+            Command::Status => "status",
+            Command::Fetch => "fetch",
+            Command::Lock => "lock",
+            Command::Checkout { .. } => "checkout",
+            Command::Snapshot { .. } => "snapshot",
+            Command::Restore { .. } => "restore",
+            Command::History { .. } => "history",
+            Command::Watch { .. } => "watch",
+            Command::Schedule { .. } => "schedule",
+            Command::Completions { .. } => "completions",
+            Command::Man => "man",
+            Command::CompleteRepos { .. } => "__complete-repos",
+        }
+    }
+
+    /// Whether the command runs on every repo through `run_operations`, which is not the case for
+    /// commands that only read the state of repoteer, keep running other commands, or only
+    /// describe the CLI
+    pub fn runs_on_repos(&self) -> bool {
+        !matches!(
+            self,
+            Command::History { .. }
+                | Command::Watch { .. }
+                | Command::Schedule { .. }
+                | Command::Completions { .. }
+                | Command::Man
+                | Command::CompleteRepos { .. }
+        )
+    }
+
+    /// Whether the command talks to the remotes of the repos, so that it can bring in new commits
+    /// or reveal diverged branches
+    pub fn contacts_remote(&self) -> bool {
+        matches!(
+            self,
+            Command::Sync | Command::Clone | Command::Pull | Command::Push | Command::Fetch
+        )
+    }
 }
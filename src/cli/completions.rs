@@ -0,0 +1,187 @@
+use clap::CommandFactory;
+use clap_complete::{
+    generate,
+    shells::{Bash, Fish, Zsh},
+};
+use color_eyre::eyre::{eyre, Result};
+use std::{collections::BTreeSet, io::Write};
+
+use super::{args::Args, command::CompletionShell};
+use crate::Manifest;
+
+/// Name of the executable the completion scripts complete
+const BIN_NAME: &str = "repoteer";
+
+/// Completes the values of `--repo` and `--tag` in bash by asking `repoteer __complete-repos`, and
+/// leaves everything else to the generated `_repoteer`
+const BASH_REPOS: &str = r#"
+_repoteer_with_repos() {
+    local prev="${COMP_WORDS[COMP_CWORD-1]}" manifest=() i
+    case "${prev}" in
+        --repo|--tag)
+            for ((i = 1; i < COMP_CWORD - 1; i++)); do
+                case "${COMP_WORDS[i]}" in
+                    -m|--manifest) manifest=(--manifest "${COMP_WORDS[i+1]}") ;;
+                esac
+            done
+            if [[ "${prev}" == --tag ]]; then
+                manifest+=(__complete-repos --tags)
+            else
+                manifest+=(__complete-repos)
+            fi
+            COMPREPLY=($(compgen -W "$(repoteer "${manifest[@]}" 2>/dev/null)" -- "${COMP_WORDS[COMP_CWORD]}"))
+            ;;
+        *)
+            _repoteer "$@"
+            ;;
+    esac
+}
+
+complete -F _repoteer_with_repos -o bashdefault -o default repoteer
+"#;
+
+/// Asks `repoteer __complete-repos` for the repo names or tags in zsh, passing on `--manifest`
+const ZSH_REPOS: &str = r#"
+_repoteer_repos() {
+    local -a manifest values
+    local i
+    for ((i = 2; i < CURRENT; i++)); do
+        case "${words[i]}" in
+            -m|--manifest) manifest=(--manifest "${words[i+1]}") ;;
+        esac
+    done
+    values=(${(f)"$(repoteer "${manifest[@]}" __complete-repos "$@" 2>/dev/null)"})
+    compadd -a values
+}
+"#;
+
+/// Completes the values of `--repo` and `--tag` in fish by asking `repoteer __complete-repos`,
+/// passing on `--manifest`
+const FISH_REPOS: &str = r#"
+function __repoteer_repos
+    set -l tokens (commandline -opc)
+    set -l manifest
+    for i in (seq (count $tokens))
+        if contains -- $tokens[$i] -m --manifest; and test $i -lt (count $tokens)
+            set manifest --manifest $tokens[(math $i + 1)]
+        end
+    end
+    repoteer $manifest __complete-repos $argv 2>/dev/null
+end
+
+complete -c repoteer -l repo -x -a '(__repoteer_repos)'
+complete -c repoteer -l tag -x -a '(__repoteer_repos --tags)'
+"#;
+
+/// Writes the completion script for `shell` to `out`
+///
+/// The script is generated from the clap definitions of `Args`, and completes the values of
+/// `--repo` and `--tag` with the repos of the manifest, see `print_repos`.
+///
+/// # Arguments
+///
+/// * `shell` - The `CompletionShell` the script is for
+/// * `out` - Where the script is written to
+pub fn print_completions(shell: CompletionShell, out: &mut dyn Write) -> Result<()> {
+    let mut script = vec![];
+    let mut command = visible_command();
+    match shell {
+        CompletionShell::Bash => generate(Bash, &mut command, BIN_NAME, &mut script),
+        CompletionShell::Zsh => generate(Zsh, &mut command, BIN_NAME, &mut script),
+        CompletionShell::Fish => generate(Fish, &mut command, BIN_NAME, &mut script),
+    }
+    let script = String::from_utf8_lossy(&script);
+    let script = match shell {
+        CompletionShell::Bash => format!("{}{}", script, BASH_REPOS),
+        CompletionShell::Zsh => zsh_with_repos(&script),
+        CompletionShell::Fish => format!("{}{}", script, FISH_REPOS),
+    };
+    out.write_all(script.as_bytes()).map_err(|e| {
+        eyre!(
+            "Unable to write the completions! Error: {:?}",
+            e.to_string()
+        )
+    })
+}
+
+/// Returns the clap `Command` of `Args` without its hidden subcommands
+///
+/// Hidden subcommands are not offered by the completions, and the bash generator cannot handle the
+/// `__` in `__complete-repos`, since it joins the names of nested subcommands with `__`.
+fn visible_command() -> clap::Command<'static> {
+    let command = Args::command();
+    // `Command::new` declares the help and version flags already
+    let mut visible = clap::Command::new(BIN_NAME)
+        .args(
+            command
+                .get_arguments()
+                .filter(|arg| !matches!(arg.get_id(), "help" | "version"))
+                .cloned(),
+        )
+        .subcommands(
+            command
+                .get_subcommands()
+                .filter(|subcommand| !subcommand.is_hide_set())
+                .cloned(),
+        );
+    if let Some(version) = command.get_version() {
+        visible = visible.version(version);
+    }
+    if let Some(about) = command.get_about() {
+        visible = visible.about(about);
+    }
+    visible
+}
+
+/// Returns the generated zsh `script`, with the values of `--repo` and `--tag` completed by
+/// `_repoteer_repos`
+///
+/// # Arguments
+///
+/// * `script` - The zsh script generated by clap_complete
+fn zsh_with_repos(script: &str) -> String {
+    // clap_complete leaves the values of free-form arguments uncompleted, like `:NAME: `
+    let script: String = script
+        .split_inclusive('\n')
+        .map(|line| {
+            if line.starts_with("'*--repo=[") {
+                line.replacen(":NAME: '", ":NAME:_repoteer_repos'", 1)
+            } else if line.starts_with("'*--tag=[") {
+                line.replacen(":TAG: '", ":TAG:_repoteer_repos --tags'", 1)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    // the helper has to be defined before zsh autoloads the script and calls `_repoteer`
+    match script.find("\n_repoteer() {") {
+        Some(i) => format!("{}{}{}", &script[..i], ZSH_REPOS, &script[i..]),
+        None => format!("{}{}", script, ZSH_REPOS),
+    }
+}
+
+/// Writes the names of the repos of `manifest` to `out`, one per line, or their tags if `tags` is
+/// set, for the completion scripts
+///
+/// # Arguments
+///
+/// * `manifest` - The `Manifest` holding the repos
+/// * `tags` - Whether the tags of the repos are written, instead of their names
+/// * `out` - Where the names are written to
+pub fn print_repos(manifest: &Manifest, tags: bool, out: &mut dyn Write) -> Result<()> {
+    let values: Vec<String> = match tags {
+        true => manifest
+            .repos
+            .iter()
+            .flat_map(|repo| repo.tags.iter().flatten().cloned())
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect(),
+        false => manifest.repos.iter().map(|repo| repo.name()).collect(),
+    };
+    for value in values {
+        writeln!(out, "{}", value)
+            .map_err(|e| eyre!("Unable to write the repos! Error: {:?}", e.to_string()))?;
+    }
+    Ok(())
+}
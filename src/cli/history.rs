@@ -0,0 +1,167 @@
+use color_eyre::eyre::{bail, Result};
+use colored::*;
+use std::collections::BTreeMap;
+
+use super::render::OutputFormat;
+use crate::{
+    operations::Outcome,
+    state::history::{CommitRange, History, HistoryEntry},
+};
+
+/// Prints the recorded runs of every repo, or of the repo called `repo`
+///
+/// Without `repo`, a single line per repo shows its last run, its last success and for how long it
+/// has been failing. With `repo`, every recorded run of that repo is shown, newest first. The `json`
+/// output prints the recorded runs as they are stored instead.
+///
+/// # Arguments
+///
+/// * `history` - The `History` being printed
+/// * `repo` - Name of the repo whose runs are printed
+/// * `output` - The `OutputFormat` chosen by the user
+pub fn print_history(history: &History, repo: Option<&str>, output: OutputFormat) -> Result<()> {
+    let repos: BTreeMap<&String, &Vec<HistoryEntry>> = match repo {
+        Some(name) => match history.repos.get_key_value(name) {
+            Some((name, entries)) => BTreeMap::from([(name, entries)]),
+            None => bail!("No runs of repo {} have been recorded!", name),
+        },
+        None => history.repos.iter().collect(),
+    };
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string(&repos)?);
+        return Ok(());
+    }
+    if repos.is_empty() {
+        println!("No runs have been recorded yet.");
+        return Ok(());
+    }
+    match repo {
+        Some(name) => {
+            for entry in history.repos[name].iter().rev() {
+                print_entry(entry);
+            }
+        }
+        None => {
+            for name in repos.keys() {
+                print_repo(history, name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints a single line with the last run, the last success and the failure streak of a repo
+///
+/// # Arguments
+///
+/// * `history` - The `History` holding the runs of the repo
+/// * `name` - Name of the repo
+fn print_repo(history: &History, name: &str) {
+    let last = match history.repos.get(name).and_then(|entries| entries.last()) {
+        Some(last) => last,
+        None => return,
+    };
+    let mut line = format!(
+        "{}: last {} at {} {}",
+        name.yellow(),
+        last.command,
+        last.finished,
+        describe_outcome(&last.outcome, &last.problems)
+    );
+    let failing = history.failing(name);
+    match (failing.first(), history.last_success(name)) {
+        (None, _) => {}
+        (Some(first), Some(success)) => line.push_str(&format!(
+            ", {} since {} ({} runs), last success at {}",
+            "failing".bright_red(),
+            first.finished,
+            failing.len(),
+            success.finished
+        )),
+        (Some(first), None) => line.push_str(&format!(
+            ", {} since {} ({} runs), never succeeded",
+            "failing".bright_red(),
+            first.finished,
+            failing.len()
+        )),
+    }
+    println!("{}", line);
+}
+
+/// Prints a single run of a repo, with the commits it moved and its problems
+///
+/// # Arguments
+///
+/// * `entry` - The `HistoryEntry` of the run
+fn print_entry(entry: &HistoryEntry) {
+    let duration = match (
+        humantime::parse_rfc3339(&entry.started),
+        humantime::parse_rfc3339(&entry.finished),
+    ) {
+        (Ok(started), Ok(finished)) => finished
+            .duration_since(started)
+            .map(|duration| format!(" in {}", humantime::format_duration(duration)))
+            .unwrap_or_default(),
+        _ => String::new(),
+    };
+    println!(
+        "{} {}{} {}",
+        entry.started,
+        entry.command.yellow(),
+        duration,
+        describe_outcome(&entry.outcome, &entry.problems)
+    );
+    if let Outcome::Failure(message)
+    | Outcome::Error(message)
+    | Outcome::AuthFailed(message)
+    | Outcome::TimedOut(message) = &entry.outcome
+    {
+        println!("   {}", message);
+    }
+    for range in entry.commits.iter() {
+        println!("   {}", describe_range(range));
+    }
+    for problem in entry.problems.iter() {
+        println!("   {}", problem);
+    }
+}
+
+/// Returns a short description of how a run ended
+///
+/// # Arguments
+///
+/// * `outcome` - The `Outcome` of the run
+/// * `problems` - The problems of the run
+fn describe_outcome(outcome: &Outcome, problems: &[String]) -> String {
+    match outcome {
+        Outcome::Success if problems.is_empty() => format!("{}", "Success!".bright_green()),
+        Outcome::Success => format!(
+            "{} ({} problems)",
+            "Success!".bright_green(),
+            problems.len()
+        ),
+        Outcome::Failure(_) => format!("{}", "Failure!".bright_red()),
+        Outcome::Error(_) => format!("{}", "Error!".bright_red()),
+        Outcome::AuthFailed(_) => format!("{}", "Authentication failed!".bright_red()),
+        Outcome::TimedOut(_) => format!("{}", "Timed out!".bright_red()),
+        Outcome::Cancelled => format!("{}", "Cancelled!".yellow()),
+    }
+}
+
+/// Returns how the HEAD of a checkout moved, with abbreviated commits
+///
+/// # Arguments
+///
+/// * `range` - The `CommitRange` being described
+fn describe_range(range: &CommitRange) -> String {
+    let short = |commit: &Option<String>| match commit {
+        Some(commit) => commit.chars().take(7).collect(),
+        None => "(none)".to_string(),
+    };
+    format!(
+        "{}: {}..{}",
+        range.path,
+        short(&range.before),
+        short(&range.after)
+    )
+}
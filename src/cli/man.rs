@@ -0,0 +1,206 @@
+use clap::{CommandFactory, PossibleValue};
+use roff::{bold, italic, roman, Inline, Roff};
+
+use super::args::Args;
+
+/// Returns the man page of repoteer in roff format, generated from the clap definitions of
+/// `Args`, like `repoteer man > repoteer.1`
+///
+/// Global options are only listed once, with the options of repoteer itself, and hidden commands
+/// and options are left out.
+pub fn render_man() -> String {
+    let mut command = Args::command();
+    command.build();
+    let name = command.get_name().to_string();
+    let about = command.get_about().unwrap_or_default();
+    let mut roff = Roff::new();
+    roff.control(
+        "TH",
+        [
+            name.to_uppercase().as_str(),
+            "1",
+            "",
+            &format!("{} {}", name, command.get_version().unwrap_or_default()),
+        ],
+    );
+    roff.control("SH", ["NAME"]);
+    roff.text([roman(format!("{} - {}", name, about))]);
+    roff.control("SH", ["SYNOPSIS"]);
+    roff.text([
+        bold(&name),
+        roman(" ["),
+        italic("OPTIONS"),
+        roman("] ["),
+        italic("COMMAND"),
+        roman("]"),
+    ]);
+    roff.control("SH", ["DESCRIPTION"]);
+    roff.text([roman(format!(
+        "{}. The repos are declared in a manifest, and every command runs on all of them, or \
+         on those selected with --repo and --tag. Without a command, sync is run.",
+        about
+    ))]);
+    roff.control("SH", ["OPTIONS"]);
+    options(&mut roff, &command, true);
+    roff.control("SH", ["COMMANDS"]);
+    commands(&mut roff, &command, "");
+    roff.control("SH", ["FILES"]);
+    for (path, description) in [
+        (
+            "~/.config/repoteer/manifest.toml",
+            "The manifest declaring the repos, unless --manifest is given",
+        ),
+        (
+            "~/.config/repoteer/config.toml",
+            "The settings that apply to every repo, like hooks and notifications, unless \
+             --config is given",
+        ),
+    ] {
+        roff.control("TP", []);
+        roff.text([italic(path)]);
+        roff.text([roman(description)]);
+    }
+    if let Some(author) = command.get_author() {
+        roff.control("SH", ["AUTHORS"]);
+        roff.text([roman(author)]);
+    }
+    roff.render()
+}
+
+/// Lists the options and positional arguments of `command`
+///
+/// # Arguments
+///
+/// * `roff` - The `Roff` the man page is written to
+/// * `command` - The clap `Command` whose arguments are listed
+/// * `top_level` - Whether `command` is repoteer itself, otherwise its global options and its help
+///   flag are left out, since they are listed with repoteer itself
+fn options(roff: &mut Roff, command: &clap::Command, top_level: bool) {
+    for arg in command.get_arguments().filter(|arg| {
+        !arg.is_hide_set() && (top_level || !(arg.is_global_set() || arg.get_id() == "help"))
+    }) {
+        roff.control("TP", []);
+        roff.text(arg_usage(arg));
+        let mut help = arg
+            .get_long_help()
+            .or_else(|| arg.get_help())
+            .unwrap_or_default()
+            .to_string();
+        if arg.is_takes_value_set() {
+            // the derived value enums still declare their values without a value parser
+            let values: Vec<PossibleValue> = match arg.get_value_parser().possible_values() {
+                Some(values) => values.collect(),
+                None => arg.get_possible_values().unwrap_or_default().to_vec(),
+            };
+            let values: Vec<&str> = values
+                .iter()
+                .filter(|value| !value.is_hide_set())
+                .map(|value| value.get_name())
+                .collect();
+            if !values.is_empty() {
+                help.push_str(&format!(" [possible values: {}]", values.join(", ")));
+            }
+            let defaults: Vec<String> = arg
+                .get_default_values()
+                .iter()
+                .map(|value| value.to_string_lossy().to_string())
+                .collect();
+            if !defaults.is_empty() {
+                help.push_str(&format!(" [default: {}]", defaults.join(", ")));
+            }
+        }
+        roff.text([roman(help)]);
+    }
+}
+
+/// Lists the visible subcommands of `command`, each with its own arguments and subcommands
+///
+/// # Arguments
+///
+/// * `roff` - The `Roff` the man page is written to
+/// * `command` - The clap `Command` whose subcommands are listed
+/// * `prefix` - The names of the commands leading up to `command`, like `schedule`
+fn commands(roff: &mut Roff, command: &clap::Command, prefix: &str) {
+    for subcommand in command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+    {
+        let name = format!("{} {}", prefix, subcommand.get_name())
+            .trim()
+            .to_string();
+        let mut usage = vec![bold(&name)];
+        for arg in subcommand.get_arguments().filter(|arg| arg.is_positional()) {
+            usage.push(roman(" "));
+            usage.extend(arg_usage(arg));
+        }
+        roff.control("TP", []);
+        roff.text(usage);
+        roff.text([roman(subcommand.get_about().unwrap_or_default())]);
+        let has_options = subcommand
+            .get_arguments()
+            .any(|arg| !arg.is_hide_set() && !arg.is_global_set() && arg.get_id() != "help");
+        if has_options || subcommand.has_subcommands() {
+            roff.control("RS", []);
+            options(roff, subcommand, false);
+            commands(roff, subcommand, &name);
+            roff.control("RE", []);
+        }
+    }
+}
+
+/// Returns how `arg` is passed, like `--repo <NAME>`, `<NAME>` or `[NAME]`
+///
+/// # Arguments
+///
+/// * `arg` - The clap `Arg` being described
+fn arg_usage(arg: &clap::Arg) -> Vec<Inline> {
+    let value_name = arg
+        .get_value_names()
+        .and_then(|names| names.first())
+        .map_or_else(|| arg.get_id().to_uppercase(), |name| name.to_string());
+    if arg.is_positional() {
+        return match arg.is_required_set() {
+            true => vec![italic(format!("<{}>", value_name))],
+            false => vec![roman("["), italic(value_name), roman("]")],
+        };
+    }
+    let mut usage = vec![];
+    if let Some(short) = arg.get_short() {
+        usage.push(bold(format!("-{}", short)));
+        usage.push(roman(", "));
+    }
+    if let Some(long) = arg.get_long() {
+        usage.push(bold(format!("--{}", long)));
+    }
+    if arg.is_takes_value_set() {
+        usage.push(roman(" "));
+        usage.push(italic(format!("<{}>", value_name)));
+    }
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn man_page_lists_options_and_commands() {
+        let man = render_man();
+        assert!(man.contains(".TH REPOTEER 1"), "{}", man);
+        assert!(man.contains("\\fB\\-\\-repo\\fR \\fI<NAME>\\fR"), "{}", man);
+        assert!(man.contains("\\fBschedule install\\fR"), "{}", man);
+        assert!(
+            man.contains("\\fBcompletions\\fR \\fI<SHELL>\\fR"),
+            "{}",
+            man
+        );
+        assert!(
+            man.contains("[possible values: bash, zsh, fish]"),
+            "{}",
+            man
+        );
+        assert!(!man.contains("complete\\-repos"), "{}", man);
+        // global options are only listed once
+        assert_eq!(man.matches("\\fB\\-\\-manifest\\fR").count(), 1, "{}", man);
+    }
+}
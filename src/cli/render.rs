@@ -0,0 +1,431 @@
+use colored::*;
+use std::{
+    collections::HashMap,
+    io::{stdout, IsTerminal, Write},
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::operations::{events::Event, retry::Retry, Outcome};
+
+/// Turns the `Event`s emitted by the operations into terminal output
+pub trait Renderer: Send {
+    /// Renders a single `event`
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The `Event` being rendered
+    fn render(&mut self, event: &Event);
+
+    /// Called once all events have been rendered
+    fn finish(&mut self) {}
+}
+
+/// Enumerates the available `Renderer`s
+#[derive(clap::ValueEnum, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Prints every message of every repo
+    #[default]
+    Plain,
+
+    /// Shows a single progress line, and only prints problems
+    Progress,
+
+    /// Prints every event as a JSON object on its own line
+    Json,
+
+    /// Only prints problems and failures, to stderr
+    Quiet,
+}
+
+impl OutputFormat {
+    /// Builds the `Renderer` declared by Self
+    ///
+    /// # Arguments
+    ///
+    /// * `total` - The number of repos being processed
+    pub fn renderer(self, total: usize) -> Box<dyn Renderer> {
+        match self {
+            OutputFormat::Plain => Box::<PlainRenderer>::default(),
+            OutputFormat::Progress => Box::new(ProgressRenderer::new(total)),
+            OutputFormat::Json => Box::new(JsonRenderer),
+            OutputFormat::Quiet => Box::new(QuietRenderer),
+        }
+    }
+
+    /// Whether the output is meant to be read by humans, and should get a header
+    pub fn is_human_readable(self) -> bool {
+        matches!(self, OutputFormat::Plain | OutputFormat::Progress)
+    }
+}
+
+/// Renders every event received on `receiver` with `renderer`, until the channel is closed
+///
+/// # Arguments
+///
+/// * `receiver` - The receiving half of the event channel
+/// * `renderer` - The `Renderer` rendering the events
+pub async fn render_events(
+    mut receiver: UnboundedReceiver<Event>,
+    mut renderer: Box<dyn Renderer>,
+) {
+    while let Some(event) = receiver.recv().await {
+        renderer.render(&event);
+    }
+    renderer.finish();
+}
+
+/// Returns a single line describing `outcome`, in the style of the plain output
+///
+/// # Arguments
+///
+/// * `outcome` - The `Outcome` being described
+fn describe_outcome(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Success => format!("{}", "Success!".bright_green()),
+        Outcome::Failure(stderr) => format!("{} Output: {}", "Failure!".bright_red(), stderr),
+        Outcome::Error(report) => format!("{} Report: {}", "Error!".bright_red(), report),
+        Outcome::AuthFailed(stderr) => format!(
+            "{} Output: {}",
+            "Authentication failed!".bright_red(),
+            stderr
+        ),
+        Outcome::TimedOut(timeout) => format!("{} {}", "Timed out!".bright_red(), timeout),
+        Outcome::Cancelled => format!("{}", "Cancelled!".yellow()),
+    }
+}
+
+/// Counts how the repos ended, for the summary printed once all of them finished
+#[derive(Default)]
+struct Summary {
+    finished: usize,
+    succeeded: usize,
+    auth_failed: usize,
+    timed_out: usize,
+    cancelled: usize,
+}
+
+impl Summary {
+    /// Counts a repo that ended with `outcome`
+    ///
+    /// # Arguments
+    ///
+    /// * `outcome` - The `Outcome` of the repo
+    fn record(&mut self, outcome: &Outcome) {
+        self.finished += 1;
+        match outcome {
+            Outcome::Success => self.succeeded += 1,
+            Outcome::AuthFailed(_) => self.auth_failed += 1,
+            Outcome::TimedOut(_) => self.timed_out += 1,
+            Outcome::Cancelled => self.cancelled += 1,
+            Outcome::Failure(_) | Outcome::Error(_) => {}
+        }
+    }
+
+    /// Prints the summary line, in green if every repo succeeded
+    ///
+    /// # Arguments
+    ///
+    /// * `total` - The number of repos being processed
+    fn print(&self, total: usize) {
+        let mut summary = format!(
+            "{} of {} repos finished successfully",
+            self.succeeded, total
+        );
+        if self.auth_failed > 0 {
+            summary.push_str(&format!(", {} failed to authenticate", self.auth_failed));
+        }
+        if self.timed_out > 0 {
+            summary.push_str(&format!(", {} timed out", self.timed_out));
+        }
+        if self.cancelled > 0 {
+            summary.push_str(&format!(", {} cancelled", self.cancelled));
+        }
+        if self.succeeded == total {
+            println!("{}", summary.bright_green());
+        } else {
+            println!("{}", summary.bright_red());
+        }
+    }
+}
+
+/// Returns a single line describing a git command that was skipped in a dry run
+///
+/// # Arguments
+///
+/// * `argv` - The arguments git would have been called with
+/// * `cwd` - The directory git would have been run in
+fn describe_skipped(argv: &[String], cwd: &str) -> String {
+    format!(
+        "{} {} {} {}",
+        "Would run:".yellow(),
+        join_argv(argv),
+        "in".yellow(),
+        cwd
+    )
+}
+
+/// Returns a single line describing a git command that is run again after a transient failure
+///
+/// # Arguments
+///
+/// * `retry` - The `Retry` being described
+fn describe_retry(retry: &Retry) -> String {
+    format!(
+        "{} {} in {} after attempt {} failed: {}",
+        "Retrying".yellow(),
+        join_argv(&retry.argv),
+        humantime::format_duration(retry.delay),
+        retry.attempt,
+        retry.reason
+    )
+}
+
+/// Joins `argv` into a single line, quoting arguments that are empty or contain whitespace
+///
+/// # Arguments
+///
+/// * `argv` - The arguments git is called with
+fn join_argv(argv: &[String]) -> String {
+    argv.iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                format!("'{}'", arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Prints every message under a heading naming the repo's URL
+#[derive(Default)]
+pub struct PlainRenderer {
+    urls: HashMap<String, String>,
+    summary: Summary,
+}
+
+impl PlainRenderer {
+    fn print(&self, repo: &str, message: &str) {
+        let heading = format!(
+            "Repo:  {}",
+            self.urls.get(repo).map(String::as_str).unwrap_or(repo)
+        );
+        println!(
+            "{}\n   {}\n",
+            heading.blue(),
+            message.replace('\n', "\n   ")
+        );
+    }
+}
+
+impl Renderer for PlainRenderer {
+    fn render(&mut self, event: &Event) {
+        match event {
+            Event::RepoStarted { repo, url } => {
+                self.urls.insert(repo.clone(), url.clone());
+            }
+            Event::PhaseStarted { repo, op, branch } => {
+                let message = match branch {
+                    Some(branch) => {
+                        format!(
+                            "running operation {} on branch {}",
+                            op.cyan(),
+                            branch.cyan()
+                        )
+                    }
+                    None => format!("running operation {}", op.cyan()),
+                };
+                self.print(repo, &message);
+            }
+            Event::Message { repo, message } => self.print(repo, message),
+            Event::Problem { repo, problem } => {
+                self.print(repo, &format!("{} {}", "Failure!".bright_red(), problem))
+            }
+            Event::GitCommandFinished { .. } | Event::HookFinished { .. } => {}
+            Event::CommandSkipped { repo, argv, cwd } => {
+                self.print(repo, &describe_skipped(argv, cwd))
+            }
+            Event::Retrying { repo, retry } => self.print(repo, &describe_retry(retry)),
+            Event::RepoFinished {
+                repo,
+                outcome,
+                problems,
+                retries,
+            } => {
+                self.summary.record(outcome);
+                let mut message = describe_outcome(outcome);
+                if !problems.is_empty() && *outcome == Outcome::Success {
+                    message.push_str(&format!(
+                        " ({})",
+                        format!("{} problems", problems.len()).yellow()
+                    ));
+                }
+                if !retries.is_empty() {
+                    message.push_str(&format!(
+                        " ({})",
+                        format!("{} retries", retries.len()).yellow()
+                    ));
+                }
+                self.print(repo, &message);
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.summary.print(self.urls.len());
+    }
+}
+
+/// Keeps a single line showing the overall progress up to date, and prints problems above it
+///
+/// When stdout is not a terminal, a line is printed for every finished repo instead.
+pub struct ProgressRenderer {
+    total: usize,
+    summary: Summary,
+    is_terminal: bool,
+}
+
+impl ProgressRenderer {
+    pub fn new(total: usize) -> Self {
+        ProgressRenderer {
+            total,
+            summary: Summary::default(),
+            is_terminal: stdout().is_terminal(),
+        }
+    }
+
+    fn clear_line(&self) {
+        if self.is_terminal {
+            print!("\r\x1b[2K");
+        }
+    }
+
+    fn draw(&self, status: &str) {
+        if self.is_terminal {
+            print!("[{}/{}] {}", self.summary.finished, self.total, status);
+            let _ = stdout().flush();
+        }
+    }
+}
+
+impl Renderer for ProgressRenderer {
+    fn render(&mut self, event: &Event) {
+        match event {
+            Event::PhaseStarted { repo, op, branch } => {
+                self.clear_line();
+                match branch {
+                    Some(branch) => self.draw(&format!("{}: {} {}", repo, op, branch)),
+                    None => self.draw(&format!("{}: {}", repo, op)),
+                }
+            }
+            Event::Problem { repo, problem } => {
+                self.clear_line();
+                println!("{}: {} {}", repo, "Failure!".bright_red(), problem);
+                self.draw(repo);
+            }
+            Event::CommandSkipped { repo, argv, cwd } => {
+                self.clear_line();
+                println!("{}: {}", repo, describe_skipped(argv, cwd));
+                self.draw(repo);
+            }
+            Event::Retrying { repo, retry } => {
+                self.clear_line();
+                println!("{}: {}", repo, describe_retry(retry));
+                self.draw(repo);
+            }
+            Event::RepoFinished { repo, outcome, .. } => {
+                self.summary.record(outcome);
+                self.clear_line();
+                if *outcome != Outcome::Success {
+                    println!("{}: {}", repo, describe_outcome(outcome));
+                } else if !self.is_terminal {
+                    println!("[{}/{}] {}: done", self.summary.finished, self.total, repo);
+                }
+                self.draw(repo);
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self) {
+        self.clear_line();
+        self.summary.print(self.total);
+    }
+}
+
+/// Prints every event as a JSON object on its own line
+pub struct JsonRenderer;
+
+impl Renderer for JsonRenderer {
+    fn render(&mut self, event: &Event) {
+        // the output is usually piped into another program, which may exit before reading all of
+        // it, and `println!` would panic in that case
+        if let Ok(line) = serde_json::to_string(event) {
+            let _ = writeln!(stdout(), "{}", line);
+        }
+    }
+}
+
+/// Only prints problems and failed repos to stderr
+pub struct QuietRenderer;
+
+impl Renderer for QuietRenderer {
+    fn render(&mut self, event: &Event) {
+        match event {
+            Event::Problem { repo, problem } => eprintln!("{}: {}", repo, problem),
+            Event::RepoFinished { repo, outcome, .. } if *outcome != Outcome::Success => {
+                eprintln!("{}: {}", repo, describe_outcome(outcome))
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn events_serialize_to_json() {
+        let finished = Event::GitCommandFinished {
+            repo: "repoteer".to_string(),
+            argv: vec!["git".to_string(), "fetch".to_string()],
+            cwd: "/home/foo/repoteer".to_string(),
+            status: Some(0),
+            stdout: "".to_string(),
+            stderr: "".to_string(),
+            duration: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            serde_json::to_string(&finished).unwrap(),
+            r#"{"event":"git_command_finished","repo":"repoteer","argv":["git","fetch"],"cwd":"/home/foo/repoteer","status":0,"stdout":"","stderr":"","duration":1.5}"#
+        );
+
+        let retrying = Event::Retrying {
+            repo: "repoteer".to_string(),
+            retry: Retry {
+                argv: vec!["git".to_string(), "fetch".to_string()],
+                attempt: 1,
+                delay: Duration::from_secs(2),
+                reason: "fatal: early EOF".to_string(),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&retrying).unwrap(),
+            r#"{"event":"retrying","repo":"repoteer","argv":["git","fetch"],"attempt":1,"delay":2.0,"reason":"fatal: early EOF"}"#
+        );
+
+        let failed = Event::RepoFinished {
+            repo: "repoteer".to_string(),
+            outcome: Outcome::Failure("rejected".to_string()),
+            problems: vec![],
+            retries: vec![],
+        };
+        assert_eq!(
+            serde_json::to_string(&failed).unwrap(),
+            r#"{"event":"repo_finished","repo":"repoteer","outcome":{"kind":"failure","message":"rejected"},"problems":[],"retries":[]}"#
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use clap::ValueEnum;
+use color_eyre::eyre::{bail, eyre, Result};
+use colored::*;
+use std::{env, fs, path::PathBuf};
+
+use super::{
+    args::Args,
+    command::{ScheduleAction, ScheduledCommand},
+};
+use crate::{
+    schedule::{systemctl, Schedule},
+    Manifest, RepoFilter,
+};
+
+/// Installs, lists or removes the schedules in `unit_dir`, or in the systemd user unit directory
+///
+/// systemctl is only run to reload, enable and disable the units if `unit_dir` is not given.
+///
+/// # Arguments
+///
+/// * `action` - The `ScheduleAction` being run
+/// * `unit_dir` - The directory holding the units, if not the systemd user unit directory
+/// * `args` - The `Args` repoteer was called with, whose manifest, config and filters are passed
+///   on to the scheduled runs
+pub fn run_schedule(action: &ScheduleAction, unit_dir: Option<PathBuf>, args: &Args) -> Result<()> {
+    let use_systemctl = unit_dir.is_none();
+    let dir = match unit_dir {
+        Some(dir) => dir,
+        None => Schedule::unit_dir()?,
+    };
+    match action {
+        ScheduleAction::Install {
+            every,
+            command,
+            name,
+        } => {
+            let schedule = Schedule {
+                name: name
+                    .clone()
+                    .unwrap_or_else(|| command.command().name().to_string()),
+                every: *every,
+                argv: scheduled_argv(*command, args)?,
+            };
+            if !schedule
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                bail!(
+                    "{:?} is not a valid schedule name, it may only contain letters, digits, '-' and '_'!",
+                    schedule.name
+                );
+            }
+            let timer = schedule.write(&dir)?;
+            println!("Wrote {:?} and its service", timer);
+            if use_systemctl {
+                let timer = Schedule::unit_name(&schedule.name, "timer");
+                systemctl(&["daemon-reload"])?;
+                systemctl(&["enable", "--now", &timer])?;
+                println!("Enabled {}", timer);
+            }
+        }
+        ScheduleAction::Status => {
+            let schedules = Schedule::installed(&dir)?;
+            if schedules.is_empty() {
+                println!("No schedules are installed in {:?}.", dir);
+            }
+            for schedule in schedules.iter() {
+                let mut line = format!(
+                    "{}: every {}, runs `{}`",
+                    schedule.name.yellow(),
+                    humantime::format_duration(schedule.every),
+                    schedule.argv.join(" ")
+                );
+                if use_systemctl {
+                    let timer = Schedule::unit_name(&schedule.name, "timer");
+                    let show = |property: &str| {
+                        systemctl(&["show", "--value", "--property", property, &timer])
+                            .unwrap_or_default()
+                    };
+                    line.push_str(&format!(
+                        ", timer is {}, next run at {}",
+                        show("ActiveState"),
+                        show("NextElapseUSecRealtime")
+                    ));
+                }
+                println!("{}", line);
+            }
+        }
+        ScheduleAction::Remove { name } => {
+            if use_systemctl {
+                let timer = Schedule::unit_name(name, "timer");
+                if let Err(e) = systemctl(&["disable", "--now", &timer]) {
+                    println!("Unable to disable {}: {}", timer, e);
+                }
+            }
+            Schedule::remove(&dir, name)?;
+            if use_systemctl {
+                systemctl(&["daemon-reload"])?;
+            }
+            println!("Removed schedule {} from {:?}", name, dir);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the arguments the scheduled runs of repoteer are called with: the manifest, the config,
+/// the repo and tag filters, the timeouts, the backend, the autostash mode, pruning and the retries
+/// of `args`, without ever prompting for input
+///
+/// The selected repos are checked against the manifest, so that typos are noticed right away
+/// instead of in the logs of the service. `--dry-run` is rejected, since a scheduled run that never
+/// changes anything is most likely a mistake.
+///
+/// # Arguments
+///
+/// * `command` - The `ScheduledCommand` being run
+/// * `args` - The `Args` repoteer was called with
+fn scheduled_argv(command: ScheduledCommand, args: &Args) -> Result<Vec<String>> {
+    if args.dry_run {
+        bail!("--dry-run cannot be scheduled, run the command with --dry-run directly instead!");
+    }
+    let absolute = |path: PathBuf| {
+        fs::canonicalize(&path)
+            .map_err(|e| eyre!("Unable to resolve {:?}! Error: {:?}", path, e.to_string()))
+    };
+    let manifest_path = absolute(Manifest::path(&args.manifest)?)?;
+    Manifest::from_toml_file(&manifest_path)?.filter(&RepoFilter {
+        names: args.repos.clone(),
+        tags: args.tags.clone(),
+    })?;
+
+    let exe = env::current_exe().map_err(|e| {
+        eyre!(
+            "Unable to find the repoteer executable! Error: {:?}",
+            e.to_string()
+        )
+    })?;
+    let mut argv = vec![
+        exe.to_string_lossy().to_string(),
+        "--manifest".to_string(),
+        manifest_path.to_string_lossy().to_string(),
+    ];
+    if let Some(config) = &args.config {
+        argv.push("--config".to_string());
+        argv.push(absolute(config.clone())?.to_string_lossy().to_string());
+    }
+    for name in args.repos.iter() {
+        argv.push("--repo".to_string());
+        argv.push(name.clone());
+    }
+    for tag in args.tags.iter() {
+        argv.push("--tag".to_string());
+        argv.push(tag.clone());
+    }
+    for (flag, timeout) in [
+        ("--timeout", args.timeout),
+        ("--repo-timeout", args.repo_timeout),
+    ] {
+        if let Some(timeout) = timeout {
+            argv.push(flag.to_string());
+            argv.push(humantime::format_duration(timeout).to_string());
+        }
+    }
+    argv.push("--backend".to_string());
+    argv.push(value_name(args.backend));
+    if let Some(autostash) = args.autostash {
+        argv.push("--autostash".to_string());
+        argv.push(value_name(autostash));
+    }
+    if args.prune {
+        argv.push("--prune".to_string());
+    }
+    argv.push("--retries".to_string());
+    argv.push(args.retries.to_string());
+    argv.push("--retry-backoff".to_string());
+    argv.push(humantime::format_duration(args.retry_backoff).to_string());
+    argv.push("--interactive".to_string());
+    argv.push("never".to_string());
+    argv.push(command.command().name().to_string());
+    Ok(argv)
+}
+
+/// Returns how `value` is passed on the command line, like `libgit2`
+///
+/// # Arguments
+///
+/// * `value` - The value of a clap value enum
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,149 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{eyre, Report};
+use serde::Deserialize;
+
+use crate::{manifest::hooks::Hooks, notify::Notification};
+
+/// Settings that apply to every repo, no matter which manifest they are declared in
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Hooks that run for every repo that does not declare the same hook itself
+    #[serde(default)]
+    pub hooks: Hooks,
+
+    /// Notifications sent when a run fails, leaves branches diverged or brings in new commits
+    #[serde(default)]
+    pub notifications: Vec<Notification>,
+}
+
+impl Config {
+    /// Returns a `Result<config::Config, Report>` from an `Option<PathBuf>`
+    ///
+    /// If no path is given, the config is read from `$HOME/.config/repoteer/config.toml`, and
+    /// the default `Config` is returned if that file does not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `opt_toml_path` - Optional path to a toml file
+    pub fn new(opt_toml_path: &Option<PathBuf>) -> Result<Self, Report> {
+        match opt_toml_path {
+            Some(toml_path) => Self::from_toml_file(toml_path),
+            None => match env::var("HOME") {
+                Ok(home) => {
+                    let standard_config_path =
+                        Path::new(&home).join(".config/repoteer/config.toml");
+                    if standard_config_path.exists() {
+                        Self::from_toml_file(&standard_config_path)
+                    } else {
+                        Ok(Config::default())
+                    }
+                }
+                Err(_) => Ok(Config::default()),
+            },
+        }
+    }
+
+    /// Returns a `Result<config::Config, Report>` from a toml file
+    ///
+    /// # Arguments
+    ///
+    /// * `toml_path` - Path pointing to the config file
+    pub fn from_toml_file(toml_path: impl AsRef<Path>) -> Result<Self, Report> {
+        let toml_path = toml_path.as_ref();
+        match fs::read_to_string(toml_path) {
+            Ok(s) => Self::from_toml_str(&s),
+            Err(e) => Err(eyre!(
+                "Unable to read from file {:?}! Error: {:?}",
+                toml_path,
+                e.to_string()
+            )),
+        }
+    }
+
+    /// Returns a `Result<config::Config, Report>` from a toml formatted string
+    ///
+    /// # Arguments
+    ///
+    /// * `toml_str` - A toml formatted string
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use repoteer::config::Config;
+    ///
+    /// let config = Config::from_toml_str(r#"
+    ///     [hooks]
+    ///     post_clone = "pre-commit install"
+    /// "#).unwrap();
+    /// assert_eq!(config.hooks.post_clone.as_deref(), Some("pre-commit install"));
+    /// ```
+    pub fn from_toml_str(toml_str: &str) -> Result<Self, Report> {
+        toml::from_str(toml_str).map_err(|e| {
+            eyre!(
+                "Unable to parse toml string to Config instance! Error: {:?}",
+                e.to_string()
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::Trigger;
+
+    #[test]
+    fn empty_config_is_default() {
+        assert_eq!(Config::from_toml_str("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn notifications_are_parsed() {
+        let config = Config::from_toml_str(
+            r#"
+            [[notifications]]
+            kind = "webhook"
+            url = "https://hooks.example.com/repoteer"
+            on = ["failure"]
+
+            [[notifications]]
+            kind = "command"
+            command = 'notify-send "$REPOTEER_SUBJECT" "$REPOTEER_SUMMARY"'
+
+            [[notifications]]
+            kind = "email"
+            to = "foo@example.com"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.notifications,
+            vec![
+                Notification::Webhook {
+                    url: "https://hooks.example.com/repoteer".to_string(),
+                    on: vec![Trigger::Failure],
+                },
+                Notification::Command {
+                    command: "notify-send \"$REPOTEER_SUBJECT\" \"$REPOTEER_SUMMARY\"".to_string(),
+                    on: Trigger::all(),
+                },
+                Notification::Email {
+                    to: "foo@example.com".to_string(),
+                    sendmail: None,
+                    on: Trigger::all(),
+                },
+            ]
+        );
+        assert!(Config::from_toml_str("[[notifications]]\nkind = \"pager\"\n").is_err());
+    }
+
+    #[test]
+    fn unknown_hook_fails() {
+        assert!(Config::from_toml_str("[hooks]\npost_checkout = \"make\"\n").is_err());
+    }
+}
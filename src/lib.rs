@@ -0,0 +1,38 @@
+//! Library behind the `repoteer` CLI, which keeps multiple git repositories across a machine in
+//! sync with their remotes
+//!
+//! A [`Manifest`] declares the [`Repo`]s being managed, a [`RepoFilter`] narrows them down, and
+//! [`run_operations`] runs a [`Command`] on all of them, returning a [`RepoReport`] for each repo.
+//!
+//! ```no_run
+//! use repoteer::{run_operations, Command, Manifest, Options, RepoFilter};
+//!
+//! # async fn example() -> color_eyre::Result<()> {
+//! let manifest = Manifest::from_toml_file("/home/foo/.config/repoteer/manifest.toml")?;
+//! let filter = RepoFilter {
+//!     tags: vec!["work".to_string()],
+//!     ..Default::default()
+//! };
+//! let reports = run_operations(Command::Pull, manifest.filter(&filter)?, Options::default()).await?;
+//! for report in reports.iter().filter(|report| !report.is_success()) {
+//!     eprintln!("{} did not pull cleanly: {:?}", report.name, report.problems);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod cli;
+pub mod config;
+pub mod manifest;
+pub mod notify;
+pub mod operations;
+pub mod schedule;
+pub mod state;
+pub mod watch;
+
+pub use crate::{
+    cli::command::Command,
+    config::Config,
+    manifest::{filter::RepoFilter, repo::Repo, Manifest},
+    operations::{interrupt::CancelToken, run_operations, Options, Outcome, RepoReport},
+};
@@ -1,14 +1,30 @@
 use clap::Parser;
-use cli::command::Command;
-use color_eyre::{eyre::Result, owo_colors::OwoColorize};
+use color_eyre::{
+    eyre::{bail, Result},
+    owo_colors::OwoColorize,
+};
 use colored::*;
-use operations::run_operations;
+use repoteer::{
+    cli::{
+        self,
+        completions::{print_completions, print_repos},
+        history::print_history,
+        man::render_man,
+        render::render_events,
+        render::OutputFormat,
+        schedule::run_schedule,
+    },
+    manifest::lock::Lockfile,
+    notify::notify,
+    operations::{retry::RetryPolicy, run_operations_with_events},
+    state::{history::History, snapshot::Snapshot, state_dir},
+    watch::{watch, WatchOptions},
+    CancelToken, Command, Config, Manifest, Options, Outcome, RepoFilter, RepoReport,
+};
+use std::{io, path::Path, time::SystemTime};
+use tokio::sync::mpsc::unbounded_channel;
 use tracing::instrument;
 
-mod cli;
-mod manifest;
-mod operations;
-
 #[instrument]
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -16,27 +32,253 @@ async fn main() -> Result<()> {
     install_tracing();
     color_eyre::install()?;
 
-    let (manifest, command) = {
+    let (manifest, command, options, output, lockfile_path, snapshot_path) = {
         let cli = cli::args::Args::parse();
+        if let Some(Command::History { repo }) = &cli.command {
+            let history = History::load(History::path(state_dir()?))?;
+            return print_history(&history, repo.as_deref(), cli.output);
+        }
+        if let Some(Command::Schedule { unit_dir, action }) = &cli.command {
+            return run_schedule(action, unit_dir.clone(), &cli);
+        }
+        match &cli.command {
+            Some(Command::Completions { shell }) => {
+                return print_completions(*shell, &mut io::stdout())
+            }
+            Some(Command::Man) => {
+                print!("{}", render_man());
+                return Ok(());
+            }
+            Some(Command::CompleteRepos { tags }) => {
+                return print_repos(&Manifest::new(&cli.manifest)?, *tags, &mut io::stdout())
+            }
+            _ => {}
+        }
+        let filter = RepoFilter {
+            names: cli.repos,
+            tags: cli.tags,
+        };
+        let manifest_path = Manifest::path(&cli.manifest)?;
+        let lockfile_path = Lockfile::path(&manifest_path);
+        let command = cli.command.unwrap_or(Command::Sync);
+        let lockfile = match command {
+            Command::Checkout { locked: false } => {
+                bail!("Nothing to check out, checkout needs --locked!")
+            }
+            Command::Checkout { locked: true } => {
+                if !lockfile_path.exists() {
+                    bail!(
+                        "There is no lockfile at {:?}, create it with `repoteer lock` first!",
+                        lockfile_path
+                    );
+                }
+                Some(Lockfile::from_toml_file(&lockfile_path)?)
+            }
+            _ => None,
+        };
+        let snapshot_path = match &command {
+            Command::Snapshot { name } => {
+                let name = name.clone().unwrap_or_else(|| {
+                    humantime::format_rfc3339_seconds(SystemTime::now())
+                        .to_string()
+                        .replace(':', "-")
+                });
+                let path = Snapshot::path(state_dir()?, &name)?;
+                if path.exists() {
+                    bail!("There already is a snapshot called {} at {:?}!", name, path);
+                }
+                Some(path)
+            }
+            Command::Restore { name, .. } => Some(Snapshot::path(state_dir()?, name)?),
+            _ => None,
+        };
+        let snapshot = match (&command, &snapshot_path) {
+            (Command::Restore { name, .. }, Some(path)) => {
+                if !path.exists() {
+                    bail!("There is no snapshot called {} at {:?}!", name, path);
+                }
+                Some(Snapshot::from_json_file(path)?)
+            }
+            _ => None,
+        };
+        let config = Config::new(&cli.config)?;
         (
-            manifest::Manifest::new(&cli.manifest)?,
-            cli.command.unwrap_or(cli::command::Command::Sync),
+            Manifest::from_toml_file(&manifest_path)?.filter(&filter)?,
+            command,
+            Options {
+                prune: cli.prune,
+                autostash: cli.autostash,
+                backend: cli.backend,
+                dry_run: cli.dry_run,
+                timeout: cli.timeout,
+                repo_timeout: cli.repo_timeout,
+                cancel: CancelToken::default(),
+                retry: RetryPolicy {
+                    retries: cli.retries,
+                    backoff: cli.retry_backoff,
+                },
+                interactive: cli.interactive.allows_prompts(),
+                hooks: config.hooks,
+                lockfile,
+                snapshot,
+                state_dir: Some(state_dir()?),
+                notifications: config.notifications,
+            },
+            cli.output,
+            lockfile_path,
+            snapshot_path,
         )
     };
 
-    print_header(&command);
-    run_operations(command, manifest).await
+    if output.is_human_readable() {
+        print_header(&command, options.dry_run);
+    }
+    if let Command::Watch {
+        interval,
+        push_on_commit,
+        push_delay,
+        max_backoff,
+    } = command
+    {
+        let watch_options = WatchOptions {
+            interval,
+            push_on_commit,
+            push_delay,
+            max_backoff,
+        };
+        return watch(manifest, options, watch_options, output).await;
+    }
+    let cancel = options.cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            // running git commands are killed, and the repos still end up in the summary
+            cancel.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
+    let (events, receiver) = unbounded_channel();
+    let renderer = tokio::spawn(render_events(
+        receiver,
+        output.renderer(manifest.repos.len()),
+    ));
+    let cancel = options.cancel.clone();
+    let dry_run = options.dry_run;
+    let notifications = options.notifications.clone();
+    let reports = run_operations_with_events(command.clone(), manifest, options, events).await?;
+    renderer.await?;
+    if !dry_run {
+        if let Err(e) = notify(&notifications, &command, &reports) {
+            eprintln!("{} {}", "Failed to notify!".bright_red(), e);
+        }
+    }
+    // schedulers like systemd and cron only notice failed runs by their exit code, while problems
+    // like skipped pulls are only reported
+    let failed = reports
+        .iter()
+        .any(|report| !matches!(report.outcome, Outcome::Success | Outcome::Cancelled));
+    match (&command, snapshot_path) {
+        (Command::Lock, _) => write_lockfile(&lockfile_path, reports, dry_run, output)?,
+        (Command::Snapshot { .. }, Some(path)) => write_snapshot(&path, reports, dry_run, output)?,
+        _ => {}
+    }
+    if cancel.is_cancelled() {
+        std::process::exit(130);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Records the locked repos of `reports` in the lockfile at `path`
+///
+/// Repos that could not be locked keep the commits they were locked at before.
+///
+/// # Arguments
+///
+/// * `path` - Path of the lockfile
+/// * `reports` - The `RepoReport`s of the `lock` command
+/// * `dry_run` - Whether only the repos are locked, without writing the lockfile
+/// * `output` - The `OutputFormat` deciding whether the result is printed
+fn write_lockfile(
+    path: &Path,
+    reports: Vec<RepoReport>,
+    dry_run: bool,
+    output: OutputFormat,
+) -> Result<()> {
+    let mut lockfile = if path.exists() {
+        Lockfile::from_toml_file(path)?
+    } else {
+        Lockfile::default()
+    };
+    lockfile.update(reports.into_iter().filter_map(|report| report.lock));
+    if !dry_run {
+        lockfile.write(path)?;
+    }
+    if output.is_human_readable() {
+        if dry_run {
+            println!("Dry run, not writing the lockfile {:?}", path);
+        } else {
+            println!("Wrote lockfile {:?}", path);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the repos recorded in `reports` to the snapshot at `path`
+///
+/// # Arguments
+///
+/// * `path` - Path of the snapshot
+/// * `reports` - The `RepoReport`s of the `snapshot` command
+/// * `dry_run` - Whether only the repos are recorded, without writing the snapshot
+/// * `output` - The `OutputFormat` deciding whether the result is printed
+fn write_snapshot(
+    path: &Path,
+    reports: Vec<RepoReport>,
+    dry_run: bool,
+    output: OutputFormat,
+) -> Result<()> {
+    let snapshot = Snapshot {
+        name: path
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        created: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        repos: reports
+            .into_iter()
+            .filter_map(|report| report.snapshot)
+            .collect(),
+    };
+    if !dry_run {
+        snapshot.write(path)?;
+    }
+    if output.is_human_readable() {
+        if dry_run {
+            println!("Dry run, not writing the snapshot {:?}", path);
+        } else {
+            println!("Wrote snapshot {} to {:?}", snapshot.name, path);
+        }
+    }
+    Ok(())
 }
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-fn print_header(command: &Command) {
+fn print_header(command: &Command, dry_run: bool) {
     println!(
         "{} {}
 Copyright (c) 2022-2023  Tommy Breslein <github.com/tbreslein>
 
-Running command: {:?}\n",
+Running command: {:?}{}\n",
         "repoteer".green(),
         VERSION,
-        command.yellow()
+        command.yellow(),
+        if dry_run {
+            " (dry run, no repositories are changed)"
+        } else {
+            ""
+        }
     );
 }
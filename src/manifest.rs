@@ -1,33 +1,83 @@
-use std::{env, fs, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
 
-use self::repo::Repo;
+use self::{filter::RepoFilter, group::Group, repo::Repo};
 use color_eyre::eyre::{eyre, Report};
 use serde::Deserialize;
 use tracing::instrument;
 
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
 /// The record of which Repos should be managed by Repoteer
 pub struct Manifest {
     /// Vector of the Repository declarations
     pub repos: Vec<Repo>,
 }
 
+/// The manifest as it is written, before the settings of groups are applied to their repos
+#[derive(Deserialize)]
+struct ManifestFile {
+    repos: Vec<Repo>,
+
+    #[serde(default)]
+    groups: BTreeMap<String, Group>,
+}
+
+impl ManifestFile {
+    /// Applies the settings of every group to the repos joining it, and returns the `Manifest`
+    fn into_manifest(self) -> Result<Manifest, Report> {
+        let mut repos = self.repos;
+        for repo in repos.iter_mut() {
+            if let Some(name) = &repo.group {
+                match self.groups.get(name) {
+                    Some(group) => group.apply_to(repo),
+                    None => {
+                        return Err(eyre!(
+                            "Repo {} joins group {}, but there is no such group!",
+                            repo.url,
+                            name
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(Manifest { repos })
+    }
+}
+
 impl Manifest {
     #[instrument]
     /// Returns a `Result<manifest::Manifest, Report>` from an `Option<PathBuf>`
     ///
+    /// If no path is given, the manifest is read from `$HOME/.config/repoteer/manifest.toml`.
+    ///
     /// # Arguments
     ///
-    /// * `toml_path` - string slice containing the path to a toml file
+    /// * `opt_toml_path` - Optional path to a toml file
     ///
     /// # Examples
     ///
-    /// ```
-    /// let manifest = Manifest::from_toml_file("/path/to/some/toml/file.toml");
+    /// ```no_run
+    /// use repoteer::Manifest;
+    ///
+    /// let manifest = Manifest::new(&None).unwrap();
     /// ```
     pub fn new(opt_toml_path: &Option<PathBuf>) -> Result<Self, Report> {
+        Self::from_toml_file(Self::path(opt_toml_path)?)
+    }
+
+    /// Returns the path the manifest is read from, given an `Option<PathBuf>` like `Manifest::new`
+    ///
+    /// Fails if no path is given and the manifest does not exist at the standard location.
+    ///
+    /// # Arguments
+    ///
+    /// * `opt_toml_path` - Optional path to a toml file
+    pub fn path(opt_toml_path: &Option<PathBuf>) -> Result<PathBuf, Report> {
         match opt_toml_path {
-            Some(toml_path) => Self::from_toml_file(toml_path),
+            Some(toml_path) => Ok(toml_path.clone()),
             None => match env::var("HOME") {
                 Ok(home_path_str) => {
                     let standard_manifest_path = PathBuf::from(
@@ -40,7 +90,7 @@ impl Manifest {
                         .concat(),
                     );
                     if standard_manifest_path.exists() {
-                        Self::from_toml_file(&standard_manifest_path)
+                        Ok(standard_manifest_path)
                     } else {
                         Err(eyre!(
                                 "Global manifest file does not exist, and you did not pass a path to one. Global manifest was looked for at {:?}",
@@ -55,28 +105,30 @@ impl Manifest {
         }
     }
 
-    #[instrument]
-    /// Returns a `Result<manifest::Manifest, Report>` from a `PathBuf`
-    /// file
+    #[instrument(skip(toml_path))]
+    /// Returns a `Result<manifest::Manifest, Report>` from a toml file
     ///
     /// # Arguments
     ///
-    /// * `toml_path` - `PathBuf` pointing to the manifest file
+    /// * `toml_path` - Path pointing to the manifest file
     ///
     /// # Examples
     ///
+    /// ```no_run
+    /// use repoteer::Manifest;
+    ///
+    /// let manifest = Manifest::from_toml_file("/path/to/some/toml/file.toml").unwrap();
     /// ```
-    /// let manifest = Manifest::from_toml_file(PathBuf::from("/path/to/some/toml/file.toml"));
-    /// ```
-    fn from_toml_file(toml_path: &PathBuf) -> Result<Manifest, Report> {
-        return match fs::read_to_string(&toml_path) {
+    pub fn from_toml_file(toml_path: impl AsRef<Path>) -> Result<Manifest, Report> {
+        let toml_path = toml_path.as_ref();
+        match fs::read_to_string(toml_path) {
             Ok(s) => Self::from_toml_str(s.as_str()),
             Err(e) => Err(eyre!(
                 "Unable to read from file {:?}! Error: {:?}",
                 toml_path,
                 e.to_string()
             )),
-        };
+        }
     }
 
     #[instrument]
@@ -89,35 +141,179 @@ impl Manifest {
     /// # Examples
     ///
     /// ```
+    /// use repoteer::Manifest;
+    ///
     /// let s = r#"
     ///     [[repos]]
     ///     url = "git@github.com:testuser/testrepo.git"
-    ///     service = "Git"
     ///     path = "/home/foo/testrepo"
     ///
     ///     [[repos]]
     ///     url = "git@bitbucket.com:bbuser/somerepo.git"
-    ///     service = "Git"
     ///     path = "/home/bar/somerepo"
     /// "#;
-    /// let manifest = Manifest::from_toml_str(s);
+    /// let manifest = Manifest::from_toml_str(s).unwrap();
+    /// assert_eq!(manifest.repos.len(), 2);
     /// ```
-    fn from_toml_str(toml_str: &str) -> Result<Manifest, Report> {
-        match toml::from_str(toml_str) {
-            Ok(man) => Ok(man),
+    pub fn from_toml_str(toml_str: &str) -> Result<Manifest, Report> {
+        match toml::from_str::<ManifestFile>(toml_str) {
+            Ok(file) => file
+                .into_manifest()
+                .map(Manifest::name_repos)
+                .and_then(Manifest::validate),
             Err(e) => Err(eyre!(
                 "Unable to parse toml string to Manifesto instance! Error: {:?}",
                 e.to_string()
             )),
         }
     }
+
+    /// Names the repos that do not set a `name`, and whose default names collide with the name of
+    /// another repo, after as many trailing components of their paths as it takes to tell them
+    /// apart, like `work/api` and `oss/api`
+    ///
+    /// `--repo`, the lockfile, the snapshots and the history all identify repos by their name.
+    fn name_repos(mut self) -> Manifest {
+        let components: Vec<Vec<String>> = self
+            .repos
+            .iter()
+            .map(|repo| {
+                Path::new(&repo.path)
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+                    .filter(|c| c != "/")
+                    .collect()
+            })
+            .collect();
+        let mut depths = vec![1; self.repos.len()];
+        loop {
+            let names: Vec<String> = self
+                .repos
+                .iter()
+                .zip(components.iter().zip(depths.iter()))
+                .map(|(repo, (components, depth))| match &repo.name {
+                    Some(name) => name.clone(),
+                    None => components[components.len().saturating_sub(*depth)..].join("/"),
+                })
+                .collect();
+            let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+            for name in names.iter() {
+                *counts.entry(name).or_default() += 1;
+            }
+            let mut extended = false;
+            for (i, repo) in self.repos.iter().enumerate() {
+                if repo.name.is_none()
+                    && counts[names[i].as_str()] > 1
+                    && depths[i] < components[i].len()
+                {
+                    depths[i] += 1;
+                    extended = true;
+                }
+            }
+            if !extended {
+                for (i, repo) in self.repos.iter_mut().enumerate() {
+                    if repo.name.is_none() && depths[i] > 1 {
+                        repo.name = Some(names[i].clone());
+                    }
+                }
+                return self;
+            }
+        }
+    }
+
+    /// Checks the repo declarations for combinations of settings that cannot work, and returns
+    /// the `Manifest` if there are none
+    ///
+    /// Repo names have to be unique, which only fails for repos that set the same `name`, or are
+    /// declared with the same path, see `Manifest::name_repos`.
+    fn validate(self) -> Result<Manifest, Report> {
+        let mut paths: BTreeMap<String, &str> = BTreeMap::new();
+        for repo in self.repos.iter() {
+            if let Some(other) = paths.insert(repo.name(), &repo.path) {
+                return Err(eyre!(
+                    "Repos {} and {} are both named {}! Set a different name for one of them.",
+                    other,
+                    repo.path,
+                    repo.name()
+                ));
+            }
+            if repo.worktrees.is_some() && !repo.is_bare() {
+                return Err(eyre!(
+                    "Repo {} declares worktrees, but worktrees are only supported for bare repos!",
+                    repo.url
+                ));
+            }
+            if repo.is_mirror() && (repo.worktrees.is_some() || repo.sparse_checkout.is_some()) {
+                return Err(eyre!(
+                    "Repo {} is a mirror, but mirrors cannot have worktrees or sparse checkouts!",
+                    repo.url
+                ));
+            }
+            if repo.push_mirror.is_some() && !repo.is_mirror() {
+                return Err(eyre!(
+                    "Repo {} declares a push_mirror, but it is not a mirror!",
+                    repo.url
+                ));
+            }
+            if repo.revision.is_some() && (repo.is_bare() || repo.is_mirror()) {
+                return Err(eyre!(
+                    "Repo {} declares a revision, but bare repos and mirrors have no checkout!",
+                    repo.url
+                ));
+            }
+            if repo.sparse_checkout.is_some() && repo.is_bare() {
+                return Err(eyre!(
+                    "Repo {} declares a sparse checkout, but bare repos have no checkout!",
+                    repo.url
+                ));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns a `Result<manifest::Manifest, Report>` that only contains the repos matched by
+    /// `filter`
+    ///
+    /// Fails if `filter` names a repo that is not part of the manifest, so that typos do not
+    /// silently result in doing nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The `RepoFilter` selecting the repos
+    pub fn filter(self, filter: &RepoFilter) -> Result<Manifest, Report> {
+        if let Some(unknown) = filter
+            .names
+            .iter()
+            .find(|name| !self.repos.iter().any(|repo| &repo.name() == *name))
+        {
+            return Err(eyre!("There is no repo named {} in the manifest!", unknown));
+        }
+        Ok(Manifest {
+            repos: self
+                .repos
+                .into_iter()
+                .filter(|repo| filter.matches(repo))
+                .collect(),
+        })
+    }
 }
 
+pub mod filter;
+pub mod group;
+pub mod hooks;
+pub mod lock;
+
 pub mod repo {
+    use super::hooks::Hooks;
     use serde::Deserialize;
+    use std::{
+        collections::BTreeMap,
+        env, fmt,
+        path::{Path, PathBuf},
+    };
 
     /// Models a single repository declaration
-    #[derive(Deserialize, Debug, PartialEq, Eq)]
+    #[derive(Deserialize, Debug, PartialEq, Eq, Default, Clone)]
     pub struct Repo {
         /// URL of the remote repository
         pub url: String,
@@ -125,13 +321,271 @@ pub mod repo {
         /// Where the repository should be cloned to on the local filesystem
         pub path: String,
 
+        /// Name used to select the repo with `--repo`, defaults to the last component of `path`
+        pub name: Option<String>,
+
+        /// Tags used to select groups of repos with `--tag`
+        pub tags: Option<Vec<String>>,
+
         /// Whether the repo is supposed to be bare
         pub is_bare: Option<bool>,
+
+        /// Worktrees that should be checked out for a bare repository
+        pub worktrees: Option<Vec<Worktree>>,
+
+        /// Which local changes may be stashed before pulling, and restored afterwards
+        pub autostash: Option<Autostash>,
+
+        /// Which kinds of local changes prevent a checkout from being pulled
+        pub pull_blockers: Option<Vec<ChangeKind>>,
+
+        /// How submodules are handled when cloning, pulling and checking the status
+        pub submodules: Option<Submodules>,
+
+        /// Creates a shallow clone with this many commits
+        pub depth: Option<u32>,
+
+        /// Object filter for a partial clone, like `"blob:none"`
+        pub filter: Option<String>,
+
+        /// Whether only a single branch should be cloned
+        pub single_branch: Option<bool>,
+
+        /// The branch that is checked out after cloning, instead of the remote's HEAD
+        pub branch: Option<String>,
+
+        /// The branch, tag or commit the checkout is kept at. Branches are fast-forwarded instead
+        /// of pulling every branch, while tags and commits are checked out on a detached HEAD.
+        pub revision: Option<String>,
+
+        /// Paths that are checked out in a sparse checkout
+        pub sparse_checkout: Option<Vec<String>>,
+
+        /// Whether the repo is a working checkout or a mirror of the remote
+        pub mode: Option<RepoMode>,
+
+        /// Secondary remote that a mirror is pushed to with `--mirror`
+        pub push_mirror: Option<String>,
+
+        /// Name of the group whose settings apply to the repo, see `Group`
+        pub group: Option<String>,
+
+        /// Environment variables git is run with for this repo
+        pub env: Option<BTreeMap<String, String>>,
+
+        /// Private key ssh uses for this repo, instead of the default ones
+        pub ssh_key: Option<String>,
+
+        /// Git config entries passed with `-c` to every git command of this repo, and written to
+        /// its local config when it is cloned
+        pub git_config: Option<BTreeMap<String, String>>,
+
+        /// Shell commands that run before and after the operations on this repo
+        #[serde(default)]
+        pub hooks: Hooks,
+    }
+
+    impl Repo {
+        /// Returns the name of the repo, which defaults to the last component of its path
+        pub fn name(&self) -> String {
+            match &self.name {
+                Some(name) => name.clone(),
+                None => Path::new(&self.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| self.path.clone()),
+            }
+        }
+
+        /// Whether the repo is declared as a bare repository
+        pub fn is_bare(&self) -> bool {
+            self.is_bare.unwrap_or(false)
+        }
+
+        /// Whether the repo is declared as a mirror of its remote
+        pub fn is_mirror(&self) -> bool {
+            self.mode == Some(RepoMode::Mirror)
+        }
+
+        /// Returns how submodules are handled, which defaults to `Submodules::None`
+        pub fn submodules(&self) -> Submodules {
+            self.submodules.unwrap_or_default()
+        }
+
+        /// Returns the `-c key=value` arguments for the repo's `git_config`, which go in front of
+        /// the git subcommand
+        pub fn config_args(&self) -> Vec<String> {
+            self.git_config
+                .iter()
+                .flatten()
+                .flat_map(|(key, value)| ["-c".to_string(), format!("{}={}", key, value)])
+                .collect()
+        }
+
+        /// Returns the path of the repo's `ssh_key`, with a leading `~` expanded to the home
+        /// directory
+        pub fn ssh_key_path(&self) -> Option<String> {
+            let key = self.ssh_key.as_ref()?;
+            match (key.strip_prefix("~/"), env::var("HOME")) {
+                (Some(rest), Ok(home)) => Some(Path::new(&home).join(rest).display().to_string()),
+                _ => Some(key.clone()),
+            }
+        }
+
+        /// Returns the kinds of local changes that prevent a checkout from being pulled
+        ///
+        /// Unless the repo declares `pull_blockers`, every kind of change except untracked files
+        /// blocks a pull.
+        pub fn pull_blockers(&self) -> Vec<ChangeKind> {
+            match &self.pull_blockers {
+                Some(blockers) => blockers.clone(),
+                None => ChangeKind::ALL
+                    .into_iter()
+                    .filter(|kind| *kind != ChangeKind::Untracked)
+                    .collect(),
+            }
+        }
+    }
+
+    /// Enumerates the ways a repo can be kept locally
+    #[derive(Deserialize, Debug, PartialEq, Eq, Copy, Clone, Default)]
+    #[serde(rename_all = "lowercase")]
+    pub enum RepoMode {
+        /// A regular clone that is pulled and pushed
+        #[default]
+        Checkout,
+
+        /// A `--mirror` clone that is updated with `git remote update --prune` and never pushed,
+        /// except to its `push_mirror`
+        Mirror,
+    }
+
+    /// Enumerates how the submodules of a repo are handled
+    #[derive(Deserialize, Debug, PartialEq, Eq, Copy, Clone, Default)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Submodules {
+        /// Submodules are not touched
+        #[default]
+        None,
+
+        /// Top level submodules are initialised and updated
+        Init,
+
+        /// Submodules are initialised and updated recursively
+        Recursive,
+    }
+
+    /// Enumerates the categories of local changes reported by `git status`
+    #[derive(Deserialize, Debug, PartialEq, Eq, Copy, Clone)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum ChangeKind {
+        /// Changes that have been added to the index
+        Staged,
+
+        /// Changes to tracked files that have not been added to the index
+        Unstaged,
+
+        /// Files that are not tracked by git
+        Untracked,
+
+        /// Files with unresolved merge conflicts
+        Conflicted,
+
+        /// Submodules with new commits, modifications or untracked files
+        SubmoduleDirty,
+    }
+
+    impl ChangeKind {
+        /// Every `ChangeKind`, in the order they are reported in
+        pub const ALL: [ChangeKind; 5] = [
+            ChangeKind::Staged,
+            ChangeKind::Unstaged,
+            ChangeKind::Untracked,
+            ChangeKind::Conflicted,
+            ChangeKind::SubmoduleDirty,
+        ];
+    }
+
+    impl fmt::Display for ChangeKind {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{}",
+                match self {
+                    ChangeKind::Staged => "staged",
+                    ChangeKind::Unstaged => "unstaged",
+                    ChangeKind::Untracked => "untracked",
+                    ChangeKind::Conflicted => "conflicted",
+                    ChangeKind::SubmoduleDirty => "submodule-dirty",
+                }
+            )
+        }
+    }
+
+    /// Enumerates which local changes are stashed around a pull
+    #[derive(Deserialize, clap::ValueEnum, Debug, PartialEq, Eq, Copy, Clone, Default)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Autostash {
+        /// Never stash, pulls are aborted when there are local changes
+        #[default]
+        Off,
+
+        /// Stash changes to tracked files
+        Tracked,
+
+        /// Stash changes to tracked files, as well as untracked files
+        All,
+    }
+
+    /// Models a single worktree declaration of a bare repository
+    ///
+    /// Worktrees can either be declared as a plain branch name, in which case the worktree is
+    /// placed at `<repo path>/<branch>`, or as a table with an explicit `path`, which is
+    /// interpreted relative to the repo path unless it is absolute.
+    #[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+    #[serde(untagged)]
+    pub enum Worktree {
+        /// Worktree that is only declared through its branch name
+        Branch(String),
+
+        /// Worktree with an explicit location
+        Detailed {
+            /// The branch being checked out in the worktree
+            branch: String,
+
+            /// Where the worktree should be placed
+            path: Option<String>,
+        },
+    }
+
+    impl Worktree {
+        /// Returns the name of the branch checked out in this worktree
+        pub fn branch(&self) -> &str {
+            match self {
+                Worktree::Branch(branch) => branch,
+                Worktree::Detailed { branch, .. } => branch,
+            }
+        }
+
+        /// Returns the location of this worktree on the filesystem
+        ///
+        /// # Arguments
+        ///
+        /// * `repo_path` - The path of the bare repository this worktree belongs to
+        pub fn path(&self, repo_path: &str) -> PathBuf {
+            match self {
+                Worktree::Detailed {
+                    path: Some(path), ..
+                } => Path::new(repo_path).join(path),
+                _ => Path::new(repo_path).join(self.branch()),
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::repo::{Autostash, ChangeKind, Submodules, Worktree};
     use super::*;
 
     #[test]
@@ -145,7 +599,7 @@ mod tests {
             repos: vec![Repo {
                 url: "git@github.com:testuser/testrepo.git".to_string(),
                 path: "/home/foo/testrepo".to_string(),
-                is_bare: None,
+                ..Default::default()
             }],
         };
         assert_eq!(Manifest::from_toml_str(s).unwrap(), should_be);
@@ -158,7 +612,7 @@ mod tests {
             repos: vec![Repo {
                 url: "git@github.com:testuser/testrepo.git".to_string(),
                 path: "/home/foo/testrepo".to_string(),
-                is_bare: None,
+                ..Default::default()
             }],
         };
         assert_eq!(Manifest::from_toml_file(&path).unwrap(), should_be);
@@ -184,17 +638,17 @@ mod tests {
                 Repo {
                     url: "git@github.com:testuser/testrepo.git".to_string(),
                     path: "/home/foo/testrepo".to_string(),
-                    is_bare: None,
+                    ..Default::default()
                 },
                 Repo {
                     url: "git@bitbucket.com:bbuser/somerepo.git".to_string(),
                     path: "/home/bar/somerepo".to_string(),
-                    is_bare: None,
+                    ..Default::default()
                 },
                 Repo {
                     url: "git@gitlab.com:gitlabuser/gitlabrepo.git".to_string(),
                     path: "/root/gitlabrepo".to_string(),
-                    is_bare: None,
+                    ..Default::default()
                 },
             ],
         };
@@ -209,17 +663,17 @@ mod tests {
                 Repo {
                     url: "git@github.com:testuser/testrepo.git".to_string(),
                     path: "/home/foo/testrepo".to_string(),
-                    is_bare: None,
+                    ..Default::default()
                 },
                 Repo {
                     url: "git@bitbucket.com:bbuser/somerepo.git".to_string(),
                     path: "/home/bar/somerepo".to_string(),
-                    is_bare: None,
+                    ..Default::default()
                 },
                 Repo {
                     url: "git@gitlab.com:gitlabuser/gitlabrepo.git".to_string(),
                     path: "/root/gitlabrepo".to_string(),
-                    is_bare: None,
+                    ..Default::default()
                 },
             ],
         };
@@ -251,4 +705,243 @@ mod tests {
         let path = PathBuf::from(r"test/tomlfiles/emptyfile.toml");
         assert!(Manifest::from_toml_file(&path).is_err());
     }
+
+    #[test]
+    fn from_toml_str_worktrees() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/home/foo/testrepo"
+            is_bare = true
+            worktrees = ["main", "dev", { branch = "release", path = "rel" }]
+        "#;
+        let should_be = Manifest {
+            repos: vec![Repo {
+                url: "git@github.com:testuser/testrepo.git".to_string(),
+                path: "/home/foo/testrepo".to_string(),
+                is_bare: Some(true),
+                worktrees: Some(vec![
+                    Worktree::Branch("main".to_string()),
+                    Worktree::Branch("dev".to_string()),
+                    Worktree::Detailed {
+                        branch: "release".to_string(),
+                        path: Some("rel".to_string()),
+                    },
+                ]),
+                ..Default::default()
+            }],
+        };
+        let manifest = Manifest::from_toml_str(s).unwrap();
+        assert_eq!(manifest, should_be);
+        let worktrees = manifest.repos[0].worktrees.as_ref().unwrap();
+        assert_eq!(
+            worktrees[1].path("/home/foo/testrepo"),
+            PathBuf::from("/home/foo/testrepo/dev")
+        );
+        assert_eq!(
+            worktrees[2].path("/home/foo/testrepo"),
+            PathBuf::from("/home/foo/testrepo/rel")
+        );
+    }
+
+    #[test]
+    fn from_toml_str_autostash() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/home/foo/testrepo"
+            autostash = "tracked"
+        "#;
+        let manifest = Manifest::from_toml_str(s).unwrap();
+        assert_eq!(manifest.repos[0].autostash, Some(Autostash::Tracked));
+
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/home/foo/testrepo"
+            autostash = "everything"
+        "#;
+        assert!(Manifest::from_toml_str(s).is_err());
+    }
+
+    #[test]
+    fn from_toml_str_pull_blockers() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/home/foo/testrepo"
+            pull_blockers = ["conflicted", "submodule-dirty"]
+        "#;
+        let manifest = Manifest::from_toml_str(s).unwrap();
+        assert_eq!(
+            manifest.repos[0].pull_blockers(),
+            vec![ChangeKind::Conflicted, ChangeKind::SubmoduleDirty]
+        );
+
+        // untracked files do not block pulls by default
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/home/foo/testrepo"
+        "#;
+        let manifest = Manifest::from_toml_str(s).unwrap();
+        assert!(!manifest.repos[0]
+            .pull_blockers()
+            .contains(&ChangeKind::Untracked));
+    }
+
+    #[test]
+    fn from_toml_str_submodules() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/home/foo/testrepo"
+            submodules = "recursive"
+
+            [[repos]]
+            url = "git@github.com:testuser/other.git"
+            path = "/home/foo/other"
+        "#;
+        let manifest = Manifest::from_toml_str(s).unwrap();
+        assert_eq!(manifest.repos[0].submodules(), Submodules::Recursive);
+        assert_eq!(manifest.repos[1].submodules(), Submodules::None);
+    }
+
+    #[test]
+    fn from_toml_str_worktrees_non_bare() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/home/foo/testrepo"
+            worktrees = ["main"]
+        "#;
+        assert!(Manifest::from_toml_str(s).is_err());
+    }
+
+    #[test]
+    fn from_toml_str_clone_options() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/monorepo.git"
+            path = "/home/foo/monorepo"
+            depth = 1
+            filter = "blob:none"
+            single_branch = true
+            branch = "main"
+            sparse_checkout = ["services/api", "libs"]
+        "#;
+        let should_be = Manifest {
+            repos: vec![Repo {
+                url: "git@github.com:testuser/monorepo.git".to_string(),
+                path: "/home/foo/monorepo".to_string(),
+                depth: Some(1),
+                filter: Some("blob:none".to_string()),
+                single_branch: Some(true),
+                branch: Some("main".to_string()),
+                sparse_checkout: Some(vec!["services/api".to_string(), "libs".to_string()]),
+                ..Default::default()
+            }],
+        };
+        assert_eq!(Manifest::from_toml_str(s).unwrap(), should_be);
+    }
+
+    #[test]
+    fn from_toml_str_sparse_checkout_bare() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/monorepo.git"
+            path = "/home/foo/monorepo"
+            is_bare = true
+            sparse_checkout = ["libs"]
+        "#;
+        assert!(Manifest::from_toml_str(s).is_err());
+    }
+
+    #[test]
+    fn from_toml_str_mirror() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/backup/testrepo.git"
+            mode = "mirror"
+            push_mirror = "git@backup.example.com:testuser/testrepo.git"
+        "#;
+        let manifest = Manifest::from_toml_str(s).unwrap();
+        assert!(manifest.repos[0].is_mirror());
+        assert_eq!(
+            manifest.repos[0].push_mirror.as_deref(),
+            Some("git@backup.example.com:testuser/testrepo.git")
+        );
+    }
+
+    #[test]
+    fn from_toml_str_push_mirror_without_mirror() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/home/foo/testrepo"
+            push_mirror = "git@backup.example.com:testuser/testrepo.git"
+        "#;
+        assert!(Manifest::from_toml_str(s).is_err());
+    }
+
+    #[test]
+    fn from_toml_str_revision_bare() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:testuser/testrepo.git"
+            path = "/home/foo/testrepo"
+            is_bare = true
+            revision = "v1.0.0"
+        "#;
+        assert!(Manifest::from_toml_str(s).is_err());
+    }
+
+    #[test]
+    fn from_toml_str_colliding_names() {
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:work/api.git"
+            path = "/home/foo/work/api"
+
+            [[repos]]
+            url = "git@github.com:oss/api.git"
+            path = "/home/foo/oss/api"
+
+            [[repos]]
+            url = "git@github.com:oss/web.git"
+            path = "/home/foo/oss/web"
+        "#;
+        let manifest = Manifest::from_toml_str(s).unwrap();
+        let names: Vec<String> = manifest.repos.iter().map(|repo| repo.name()).collect();
+        assert_eq!(names, vec!["work/api", "oss/api", "web"]);
+
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:work/api.git"
+            path = "/home/foo/work/api"
+            name = "api"
+
+            [[repos]]
+            url = "git@github.com:oss/api.git"
+            path = "/home/foo/oss/api"
+        "#;
+        let manifest = Manifest::from_toml_str(s).unwrap();
+        assert_eq!(manifest.repos[1].name(), "oss/api");
+
+        let s = r#"
+            [[repos]]
+            url = "git@github.com:work/api.git"
+            path = "/home/foo/work/api"
+            name = "api"
+
+            [[repos]]
+            url = "git@github.com:oss/api.git"
+            path = "/home/foo/oss/api"
+            name = "api"
+        "#;
+        let error = Manifest::from_toml_str(s).unwrap_err().to_string();
+        assert!(error.contains("/home/foo/work/api"), "{}", error);
+        assert!(error.contains("/home/foo/oss/api"), "{}", error);
+    }
 }
@@ -0,0 +1,107 @@
+use super::repo::Repo;
+
+/// Selects a subset of the repos in a `Manifest`
+///
+/// An empty list of names or tags does not restrict the selection, so `RepoFilter::default()`
+/// matches every repo.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RepoFilter {
+    /// Names of the repos being selected, see `Repo::name`
+    pub names: Vec<String>,
+
+    /// Tags of the repos being selected; a repo needs to have at least one of them
+    pub tags: Vec<String>,
+}
+
+impl RepoFilter {
+    /// Whether `repo` is selected by this filter
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being checked
+    pub fn matches(&self, repo: &Repo) -> bool {
+        let name_matches = self.names.is_empty() || self.names.contains(&repo.name());
+        let tag_matches = self.tags.is_empty()
+            || repo
+                .tags
+                .iter()
+                .flatten()
+                .any(|tag| self.tags.contains(tag));
+        name_matches && tag_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+
+    fn manifest() -> Manifest {
+        Manifest::from_toml_str(
+            r#"
+            [[repos]]
+            url = "git@github.com:testuser/dotfiles.git"
+            path = "/home/foo/dotfiles"
+            tags = ["personal"]
+
+            [[repos]]
+            url = "git@github.com:company/api.git"
+            path = "/home/foo/work/api"
+            name = "company-api"
+            tags = ["work", "backend"]
+
+            [[repos]]
+            url = "git@github.com:company/web.git"
+            path = "/home/foo/work/web"
+            tags = ["work"]
+        "#,
+        )
+        .unwrap()
+    }
+
+    fn names(manifest: Manifest) -> Vec<String> {
+        manifest.repos.iter().map(|repo| repo.name()).collect()
+    }
+
+    #[test]
+    fn default_filter_matches_everything() {
+        let filtered = manifest().filter(&RepoFilter::default()).unwrap();
+        assert_eq!(names(filtered), vec!["dotfiles", "company-api", "web"]);
+    }
+
+    #[test]
+    fn filter_by_name_and_tag() {
+        let by_name = RepoFilter {
+            names: vec!["dotfiles".to_string(), "company-api".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            names(manifest().filter(&by_name).unwrap()),
+            vec!["dotfiles", "company-api"]
+        );
+
+        let by_tag = RepoFilter {
+            tags: vec!["backend".to_string(), "personal".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            names(manifest().filter(&by_tag).unwrap()),
+            vec!["dotfiles", "company-api"]
+        );
+
+        let both = RepoFilter {
+            names: vec!["dotfiles".to_string(), "web".to_string()],
+            tags: vec!["work".to_string()],
+        };
+        assert_eq!(names(manifest().filter(&both).unwrap()), vec!["web"]);
+    }
+
+    #[test]
+    fn filter_unknown_name() {
+        let filter = RepoFilter {
+            names: vec!["nope".to_string()],
+            ..Default::default()
+        };
+        assert!(manifest().filter(&filter).is_err());
+    }
+}
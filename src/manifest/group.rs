@@ -0,0 +1,113 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use super::repo::Repo;
+
+/// Settings shared by the repos that join the group with `group = "<name>"`
+///
+/// Settings declared by a repo take precedence over the ones of its group. For `env` and
+/// `git_config`, this applies to every single entry.
+#[derive(Deserialize, Debug, PartialEq, Eq, Default, Clone)]
+pub struct Group {
+    /// Environment variables git is run with for the repos of the group
+    pub env: Option<BTreeMap<String, String>>,
+
+    /// Private key ssh uses for the repos of the group
+    pub ssh_key: Option<String>,
+
+    /// Git config entries of the repos of the group
+    pub git_config: Option<BTreeMap<String, String>>,
+}
+
+impl Group {
+    /// Fills in the settings `repo` does not declare itself with the ones of this group
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` joining this group
+    pub fn apply_to(&self, repo: &mut Repo) {
+        repo.env = merge(&self.env, repo.env.take());
+        repo.ssh_key = repo.ssh_key.take().or_else(|| self.ssh_key.clone());
+        repo.git_config = merge(&self.git_config, repo.git_config.take());
+    }
+}
+
+/// Returns the entries of `group`, overridden by the entries of `repo`
+///
+/// # Arguments
+///
+/// * `group` - The entries declared by the group
+/// * `repo` - The entries declared by the repo
+fn merge(
+    group: &Option<BTreeMap<String, String>>,
+    repo: Option<BTreeMap<String, String>>,
+) -> Option<BTreeMap<String, String>> {
+    match (group, repo) {
+        (None, repo) => repo,
+        (Some(group), None) => Some(group.clone()),
+        (Some(group), Some(repo)) => {
+            let mut merged = group.clone();
+            merged.extend(repo);
+            Some(merged)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::manifest::Manifest;
+
+    #[test]
+    fn repo_settings_take_precedence_over_group() {
+        let manifest = Manifest::from_toml_str(
+            r#"
+            [groups.work]
+            ssh_key = "~/.ssh/id_work"
+            env = { HTTPS_PROXY = "http://proxy.example.com:3128" }
+            git_config = { "user.email" = "foo@company.com", "user.name" = "Foo" }
+
+            [[repos]]
+            url = "git@github.com:company/api.git"
+            path = "/home/foo/work/api"
+            group = "work"
+            git_config = { "user.email" = "foo@api.company.com" }
+
+            [[repos]]
+            url = "git@github.com:testuser/dotfiles.git"
+            path = "/home/foo/dotfiles"
+        "#,
+        )
+        .unwrap();
+        let api = &manifest.repos[0];
+        assert_eq!(api.ssh_key.as_deref(), Some("~/.ssh/id_work"));
+        assert_eq!(
+            api.config_args(),
+            vec![
+                "-c",
+                "user.email=foo@api.company.com",
+                "-c",
+                "user.name=Foo"
+            ]
+        );
+        assert_eq!(
+            api.env.as_ref().unwrap()["HTTPS_PROXY"],
+            "http://proxy.example.com:3128"
+        );
+        let dotfiles = &manifest.repos[1];
+        assert_eq!(dotfiles.ssh_key, None);
+        assert!(dotfiles.config_args().is_empty());
+    }
+
+    #[test]
+    fn unknown_group_fails() {
+        let manifest = Manifest::from_toml_str(
+            r#"
+            [[repos]]
+            url = "git@github.com:company/api.git"
+            path = "/home/foo/work/api"
+            group = "work"
+        "#,
+        );
+        assert!(manifest.is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use serde::Deserialize;
+use std::fmt;
+
+/// Shell commands that run before and after the operations on a repo
+///
+/// Hooks run through `sh -c` in the repo's directory, with environment variables describing the
+/// repo, see `Hook::env`. A failing pre hook aborts the operation on the repo.
+#[derive(Deserialize, Debug, PartialEq, Eq, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Runs before a repo is cloned, in the directory the repo is cloned into
+    pub pre_clone: Option<String>,
+
+    /// Runs after a repo was cloned
+    pub post_clone: Option<String>,
+
+    /// Runs before a repo is pulled
+    pub pre_pull: Option<String>,
+
+    /// Runs after a repo was pulled
+    pub post_pull: Option<String>,
+
+    /// Runs before a repo is pushed
+    pub pre_push: Option<String>,
+
+    /// Runs after a repo was pushed
+    pub post_push: Option<String>,
+
+    /// Runs before a repo is synced, around the hooks of the clone, pull and push
+    pub pre_sync: Option<String>,
+
+    /// Runs after a repo was synced
+    pub post_sync: Option<String>,
+}
+
+impl Hooks {
+    /// Returns the command declared for `hook`, if there is one
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The `Hook` being looked up
+    pub fn get(&self, hook: Hook) -> Option<&str> {
+        match hook {
+            Hook::PreClone => self.pre_clone.as_deref(),
+            Hook::PostClone => self.post_clone.as_deref(),
+            Hook::PrePull => self.pre_pull.as_deref(),
+            Hook::PostPull => self.post_pull.as_deref(),
+            Hook::PrePush => self.pre_push.as_deref(),
+            Hook::PostPush => self.post_push.as_deref(),
+            Hook::PreSync => self.pre_sync.as_deref(),
+            Hook::PostSync => self.post_sync.as_deref(),
+        }
+    }
+
+    /// Returns these hooks, with the ones that are not declared taken from `fallback`
+    ///
+    /// # Arguments
+    ///
+    /// * `fallback` - The `Hooks` used where Self does not declare a hook, like the global ones
+    pub fn or(&self, fallback: &Hooks) -> Hooks {
+        Hooks {
+            pre_clone: self.pre_clone.clone().or(fallback.pre_clone.clone()),
+            post_clone: self.post_clone.clone().or(fallback.post_clone.clone()),
+            pre_pull: self.pre_pull.clone().or(fallback.pre_pull.clone()),
+            post_pull: self.post_pull.clone().or(fallback.post_pull.clone()),
+            pre_push: self.pre_push.clone().or(fallback.pre_push.clone()),
+            post_push: self.post_push.clone().or(fallback.post_push.clone()),
+            pre_sync: self.pre_sync.clone().or(fallback.pre_sync.clone()),
+            post_sync: self.post_sync.clone().or(fallback.post_sync.clone()),
+        }
+    }
+}
+
+/// Enumerates the points in the operations where hooks run
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Hook {
+    PreClone,
+    PostClone,
+    PrePull,
+    PostPull,
+    PrePush,
+    PostPush,
+    PreSync,
+    PostSync,
+}
+
+impl Hook {
+    /// Whether the hook runs before its operation, and aborts it when it fails
+    pub fn is_pre(&self) -> bool {
+        matches!(
+            self,
+            Hook::PreClone | Hook::PrePull | Hook::PrePush | Hook::PreSync
+        )
+    }
+
+    /// Returns the name of the operation the hook belongs to
+    pub fn operation(&self) -> &'static str {
+        match self {
+            Hook::PreClone | Hook::PostClone => "clone",
+            Hook::PrePull | Hook::PostPull => "pull",
+            Hook::PrePush | Hook::PostPush => "push",
+            Hook::PreSync | Hook::PostSync => "sync",
+        }
+    }
+
+    /// Returns the environment variables the hook runs with
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the repo, see `Repo::name`
+    /// * `url` - URL of the repo's remote
+    /// * `path` - Path of the repo
+    /// * `branch` - The branch checked out in the repo, if there is one
+    pub fn env(
+        &self,
+        name: &str,
+        url: &str,
+        path: &str,
+        branch: Option<&str>,
+    ) -> Vec<(&'static str, String)> {
+        vec![
+            ("REPOTEER_HOOK", self.to_string()),
+            ("REPOTEER_OPERATION", self.operation().to_string()),
+            ("REPOTEER_REPO", name.to_string()),
+            ("REPOTEER_URL", url.to_string()),
+            ("REPOTEER_PATH", path.to_string()),
+            ("REPOTEER_BRANCH", branch.unwrap_or_default().to_string()),
+        ]
+    }
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let timing = if self.is_pre() { "pre" } else { "post" };
+        write!(f, "{}_{}", timing, self.operation())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declared_hooks_take_precedence() {
+        let repo = Hooks {
+            post_pull: Some("make".to_string()),
+            ..Default::default()
+        };
+        let global = Hooks {
+            post_clone: Some("pre-commit install".to_string()),
+            post_pull: Some("true".to_string()),
+            ..Default::default()
+        };
+        let hooks = repo.or(&global);
+        assert_eq!(hooks.get(Hook::PostPull), Some("make"));
+        assert_eq!(hooks.get(Hook::PostClone), Some("pre-commit install"));
+        assert_eq!(hooks.get(Hook::PrePush), None);
+        assert_eq!(Hook::PostClone.to_string(), "post_clone");
+    }
+}
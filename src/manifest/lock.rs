@@ -0,0 +1,195 @@
+use color_eyre::eyre::{eyre, Report};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The commits the checkouts of the repos were at, written by `repoteer lock` next to the manifest
+/// and restored by `repoteer checkout --locked`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Lockfile {
+    /// The locked repos, in the order they were first locked in
+    #[serde(default)]
+    pub repos: Vec<LockedRepo>,
+}
+
+/// The locked state of a single repo
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockedRepo {
+    /// Name of the repo, see `Repo::name`
+    pub name: String,
+
+    /// URL of the repo's remote at the time it was locked
+    pub url: String,
+
+    /// Every checkout of the repo, which is the repo itself, or the worktrees of a bare repo
+    #[serde(default)]
+    pub checkouts: Vec<LockedCheckout>,
+}
+
+/// The commit a single checkout was at
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockedCheckout {
+    /// Location of the checkout, as reported by `git worktree list`
+    pub path: String,
+
+    /// The branch that was checked out, `None` if HEAD was detached
+    pub branch: Option<String>,
+
+    /// The commit HEAD pointed to
+    pub commit: String,
+}
+
+impl Lockfile {
+    /// Returns the path of the lockfile belonging to the manifest at `manifest_path`, which is the
+    /// manifest's path with the extension `lock`, like `manifest.lock`
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest_path` - Path of the manifest
+    pub fn path(manifest_path: impl AsRef<Path>) -> PathBuf {
+        manifest_path.as_ref().with_extension("lock")
+    }
+
+    /// Returns a `Result<lock::Lockfile, Report>` from a toml file
+    ///
+    /// # Arguments
+    ///
+    /// * `toml_path` - Path pointing to the lockfile
+    pub fn from_toml_file(toml_path: impl AsRef<Path>) -> Result<Self, Report> {
+        let toml_path = toml_path.as_ref();
+        match fs::read_to_string(toml_path) {
+            Ok(s) => Self::from_toml_str(&s),
+            Err(e) => Err(eyre!(
+                "Unable to read from file {:?}! Error: {:?}",
+                toml_path,
+                e.to_string()
+            )),
+        }
+    }
+
+    /// Returns a `Result<lock::Lockfile, Report>` from a toml formatted string
+    ///
+    /// # Arguments
+    ///
+    /// * `toml_str` - A toml formatted string
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use repoteer::manifest::lock::Lockfile;
+    ///
+    /// let lockfile = Lockfile::from_toml_str(r#"
+    ///     [[repos]]
+    ///     name = "testrepo"
+    ///     url = "git@github.com:testuser/testrepo.git"
+    ///
+    ///     [[repos.checkouts]]
+    ///     path = "/home/foo/testrepo"
+    ///     branch = "main"
+    ///     commit = "0123456789abcdef0123456789abcdef01234567"
+    /// "#).unwrap();
+    /// assert_eq!(lockfile.get("testrepo").unwrap().checkouts.len(), 1);
+    /// ```
+    pub fn from_toml_str(toml_str: &str) -> Result<Self, Report> {
+        toml::from_str(toml_str).map_err(|e| {
+            eyre!(
+                "Unable to parse toml string to Lockfile instance! Error: {:?}",
+                e.to_string()
+            )
+        })
+    }
+
+    /// Writes the lockfile to `toml_path`
+    ///
+    /// # Arguments
+    ///
+    /// * `toml_path` - Path the lockfile is written to
+    pub fn write(&self, toml_path: impl AsRef<Path>) -> Result<(), Report> {
+        let toml_path = toml_path.as_ref();
+        let toml_str = toml::to_string(self).map_err(|e| {
+            eyre!(
+                "Unable to serialize Lockfile instance to toml! Error: {:?}",
+                e.to_string()
+            )
+        })?;
+        fs::write(toml_path, toml_str).map_err(|e| {
+            eyre!(
+                "Unable to write to file {:?}! Error: {:?}",
+                toml_path,
+                e.to_string()
+            )
+        })
+    }
+
+    /// Returns the locked state of the repo called `name`, if it has been locked
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the repo, see `Repo::name`
+    pub fn get(&self, name: &str) -> Option<&LockedRepo> {
+        self.repos.iter().find(|repo| repo.name == name)
+    }
+
+    /// Replaces the locked state of the repos in `locked`, and appends the ones that were not
+    /// locked before, so that locking a few repos keeps the state of all others
+    ///
+    /// # Arguments
+    ///
+    /// * `locked` - The freshly locked repos
+    pub fn update(&mut self, locked: impl IntoIterator<Item = LockedRepo>) {
+        for repo in locked {
+            match self.repos.iter_mut().find(|r| r.name == repo.name) {
+                Some(existing) => *existing = repo,
+                None => self.repos.push(repo),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked(name: &str, commit: &str) -> LockedRepo {
+        LockedRepo {
+            name: name.to_string(),
+            url: format!("git@github.com:testuser/{}.git", name),
+            checkouts: vec![LockedCheckout {
+                path: format!("/home/foo/{}", name),
+                branch: Some("main".to_string()),
+                commit: commit.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn lockfile_round_trips_through_toml() {
+        let lockfile = Lockfile {
+            repos: vec![locked("api", "abc"), locked("dotfiles", "def")],
+        };
+        let toml_str = toml::to_string(&lockfile).unwrap();
+        assert_eq!(Lockfile::from_toml_str(&toml_str).unwrap(), lockfile);
+    }
+
+    #[test]
+    fn update_keeps_other_repos() {
+        let mut lockfile = Lockfile {
+            repos: vec![locked("api", "abc"), locked("dotfiles", "def")],
+        };
+        lockfile.update(vec![locked("dotfiles", "123"), locked("web", "456")]);
+        assert_eq!(
+            lockfile.repos,
+            vec![
+                locked("api", "abc"),
+                locked("dotfiles", "123"),
+                locked("web", "456")
+            ]
+        );
+        assert_eq!(
+            Lockfile::path("/home/foo/.config/repoteer/manifest.toml"),
+            PathBuf::from("/home/foo/.config/repoteer/manifest.lock")
+        );
+    }
+}
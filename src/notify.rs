@@ -0,0 +1,573 @@
+use color_eyre::eyre::{bail, eyre, Report, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    io::Write,
+    path::Path,
+    process::{self, Stdio},
+    time::SystemTime,
+};
+
+use crate::{
+    operations::{Divergence, Outcome, RepoReport},
+    Command,
+};
+
+/// A way of telling the user about the repos that need attention after a run, declared in the
+/// `notifications` array of the global config
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Notification {
+    /// POSTs the `RunSummary` as JSON to `url`, using curl
+    Webhook {
+        url: String,
+
+        /// The triggers the notification fires on, every trigger by default
+        #[serde(default = "Trigger::all")]
+        on: Vec<Trigger>,
+    },
+
+    /// Runs `command` with `sh -c`, passing the `RunSummary` as JSON on stdin and as text in the
+    /// environment variables `REPOTEER_SUBJECT` and `REPOTEER_SUMMARY`, like
+    /// `notify-send "$REPOTEER_SUBJECT" "$REPOTEER_SUMMARY"`
+    Command {
+        command: String,
+
+        /// The triggers the notification fires on, every trigger by default
+        #[serde(default = "Trigger::all")]
+        on: Vec<Trigger>,
+    },
+
+    /// Mails the `RunSummary` as text to `to`, using `sendmail -t`
+    Email {
+        to: String,
+
+        /// The sendmail executable, `sendmail` by default
+        sendmail: Option<String>,
+
+        /// The triggers the notification fires on, every trigger by default
+        #[serde(default = "Trigger::all")]
+        on: Vec<Trigger>,
+    },
+}
+
+/// Enumerates why a repo needs attention after a run
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// The command failed on the repo, or ran into problems
+    Failure,
+
+    /// A local branch and its remote branch both have commits the other one does not have
+    Divergence,
+
+    /// Branches of the remote received new commits
+    Incoming,
+}
+
+impl Trigger {
+    /// Returns every `Trigger`
+    pub fn all() -> Vec<Trigger> {
+        vec![Trigger::Failure, Trigger::Divergence, Trigger::Incoming]
+    }
+
+    /// Returns the triggers `report` fires
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - The `RepoReport` of a repo
+    pub fn fired_by(report: &RepoReport) -> Vec<Trigger> {
+        let mut triggers = vec![];
+        // cancelled repos say nothing about the repo itself
+        if !matches!(report.outcome, Outcome::Success | Outcome::Cancelled)
+            || !report.problems.is_empty()
+        {
+            triggers.push(Trigger::Failure);
+        }
+        if !report.diverged.is_empty() {
+            triggers.push(Trigger::Divergence);
+        }
+        if !report.incoming.is_empty() {
+            triggers.push(Trigger::Incoming);
+        }
+        triggers
+    }
+}
+
+/// The summary of a run that notifications are sent with, holding only the repos that need
+/// attention
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RunSummary {
+    /// Name of the command that ran, see `Command::name`
+    pub command: String,
+
+    /// When the run finished, in RFC 3339 format
+    pub finished: String,
+
+    /// Every trigger fired by the repos
+    pub triggers: Vec<Trigger>,
+
+    /// The repos that fired at least one trigger
+    pub repos: Vec<RepoSummary>,
+}
+
+/// A repo that needs attention after a run
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RepoSummary {
+    /// Name of the repo, see `Repo::name`
+    pub name: String,
+
+    /// URL of the repo's remote
+    pub url: String,
+
+    /// Where the repo is located on the local filesystem
+    pub path: String,
+
+    /// How the command ended on the repo
+    pub outcome: Outcome,
+
+    /// Failures of single steps that did not stop the command as a whole
+    pub problems: Vec<String>,
+
+    /// The remote branches that received new commits
+    pub incoming: Vec<String>,
+
+    /// The local branches that diverged from their remote branch
+    pub diverged: Vec<Divergence>,
+
+    /// The triggers the repo fired
+    pub triggers: Vec<Trigger>,
+}
+
+impl RunSummary {
+    /// Returns the summary of running `command`, holding the repos of `reports` that fired a
+    /// trigger
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The `Command` that ran
+    /// * `reports` - The `RepoReport`s of the run
+    pub fn new(command: &Command, reports: &[RepoReport]) -> Self {
+        let repos: Vec<RepoSummary> = reports
+            .iter()
+            .filter_map(|report| {
+                let triggers = Trigger::fired_by(report);
+                (!triggers.is_empty()).then(|| RepoSummary {
+                    name: report.name.clone(),
+                    url: report.url.clone(),
+                    path: report.path.clone(),
+                    outcome: report.outcome.clone(),
+                    problems: report.problems.clone(),
+                    incoming: report.incoming.clone(),
+                    diverged: report.diverged.clone(),
+                    triggers,
+                })
+            })
+            .collect();
+        let mut triggers: Vec<Trigger> = repos
+            .iter()
+            .flat_map(|repo| repo.triggers.iter().copied())
+            .collect();
+        triggers.sort();
+        triggers.dedup();
+        RunSummary {
+            command: command.name().to_string(),
+            finished: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            triggers,
+            repos,
+        }
+    }
+
+    /// Returns the summary narrowed down to the repos firing one of the triggers in `on`, or
+    /// `None` if there are none
+    ///
+    /// # Arguments
+    ///
+    /// * `on` - The triggers a notification fires on
+    pub fn only(&self, on: &[Trigger]) -> Option<RunSummary> {
+        let repos: Vec<RepoSummary> = self
+            .repos
+            .iter()
+            .filter_map(|repo| {
+                let triggers: Vec<Trigger> = repo
+                    .triggers
+                    .iter()
+                    .filter(|trigger| on.contains(trigger))
+                    .copied()
+                    .collect();
+                (!triggers.is_empty()).then(|| RepoSummary {
+                    triggers,
+                    ..repo.clone()
+                })
+            })
+            .collect();
+        if repos.is_empty() {
+            return None;
+        }
+        Some(RunSummary {
+            command: self.command.clone(),
+            finished: self.finished.clone(),
+            triggers: self
+                .triggers
+                .iter()
+                .filter(|trigger| on.contains(trigger))
+                .copied()
+                .collect(),
+            repos,
+        })
+    }
+
+    /// Returns a single line saying how many repos fired which trigger
+    pub fn subject(&self) -> String {
+        let count = |trigger: Trigger| {
+            self.repos
+                .iter()
+                .filter(|repo| repo.triggers.contains(&trigger))
+                .count()
+        };
+        let counts: Vec<String> = [
+            (Trigger::Failure, "failed"),
+            (Trigger::Divergence, "diverged"),
+            (Trigger::Incoming, "with new commits"),
+        ]
+        .iter()
+        .map(|(trigger, label)| (count(*trigger), label))
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+        format!("repoteer {}: {}", self.command, counts.join(", "))
+    }
+
+    /// Returns a line per repo, saying why it needs attention
+    pub fn text(&self) -> String {
+        let mut lines = vec![];
+        for repo in self.repos.iter() {
+            if repo.triggers.contains(&Trigger::Failure) {
+                let message = match &repo.outcome {
+                    Outcome::Success | Outcome::Cancelled => repo.problems.join("; "),
+                    Outcome::Failure(message)
+                    | Outcome::Error(message)
+                    | Outcome::AuthFailed(message) => message.clone(),
+                    Outcome::TimedOut(what) => format!("{} timed out", what),
+                };
+                lines.push(format!("{}: failed: {}", repo.name, message.trim()));
+            }
+            if repo.triggers.contains(&Trigger::Divergence) {
+                let branches: Vec<String> = repo
+                    .diverged
+                    .iter()
+                    .map(|divergence| {
+                        format!(
+                            "{} ({} ahead, {} behind)",
+                            divergence.branch, divergence.ahead, divergence.behind
+                        )
+                    })
+                    .collect();
+                lines.push(format!("{}: diverged: {}", repo.name, branches.join(", ")));
+            }
+            if repo.triggers.contains(&Trigger::Incoming) {
+                lines.push(format!(
+                    "{}: new commits on {}",
+                    repo.name,
+                    repo.incoming.join(", ")
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl Notification {
+    /// Returns the triggers the notification fires on
+    pub fn on(&self) -> &[Trigger] {
+        match self {
+            Notification::Webhook { on, .. }
+            | Notification::Command { on, .. }
+            | Notification::Email { on, .. } => on,
+        }
+    }
+
+    /// Fails if the program the notification is sent with cannot be found, so that a missing curl
+    /// or sendmail is reported by name, instead of as a failure to spawn it
+    pub fn check(&self) -> Result<()> {
+        let (kind, program) = match self {
+            Notification::Webhook { .. } => ("Webhook", "curl"),
+            Notification::Email { sendmail, .. } => {
+                ("Email", sendmail.as_deref().unwrap_or("sendmail"))
+            }
+            Notification::Command { .. } => return Ok(()),
+        };
+        if !is_installed(program) {
+            bail!(
+                "{} notifications need {}, but it cannot be found on PATH!",
+                kind,
+                program
+            );
+        }
+        Ok(())
+    }
+
+    /// Sends `summary`, narrowed down to the triggers of the notification, and returns whether
+    /// anything was sent
+    ///
+    /// # Arguments
+    ///
+    /// * `summary` - The `RunSummary` of the run
+    pub fn send(&self, summary: &RunSummary) -> Result<bool> {
+        let summary = match summary.only(self.on()) {
+            Some(summary) => summary,
+            None => return Ok(false),
+        };
+        self.check()?;
+        let json = serde_json::to_string(&summary).map_err(|e| {
+            eyre!(
+                "Unable to serialize the run summary! Error: {:?}",
+                e.to_string()
+            )
+        })?;
+        match self {
+            Notification::Webhook { url, .. } => run(
+                process::Command::new("curl")
+                    .args(["--silent", "--show-error", "--fail", "--max-time", "30"])
+                    .args(["--request", "POST"])
+                    .args(["--header", "Content-Type: application/json"])
+                    .args(["--data-binary", "@-", "--", url]),
+                &json,
+                &format!("POST the run summary to {}", url),
+            )?,
+            Notification::Command { command, .. } => run(
+                process::Command::new("sh")
+                    .args(["-c", command])
+                    .env("REPOTEER_SUBJECT", summary.subject())
+                    .env("REPOTEER_SUMMARY", summary.text()),
+                &json,
+                &format!("run the notification command {:?}", command),
+            )?,
+            Notification::Email { to, sendmail, .. } => run(
+                process::Command::new(sendmail.as_deref().unwrap_or("sendmail")).arg("-t"),
+                &format!(
+                    "To: {}\nSubject: {}\nContent-Type: text/plain; charset=utf-8\n\n{}\n",
+                    to,
+                    summary.subject(),
+                    summary.text()
+                ),
+                &format!("mail the run summary to {}", to),
+            )?,
+        }
+        Ok(true)
+    }
+}
+
+/// Sends the summary of running `command` through every notification whose triggers were fired
+/// by `reports`
+///
+/// Every notification is tried, even if an earlier one failed.
+///
+/// # Arguments
+///
+/// * `notifications` - The `Notification`s of the global config
+/// * `command` - The `Command` that ran
+/// * `reports` - The `RepoReport`s of the run
+pub fn notify(
+    notifications: &[Notification],
+    command: &Command,
+    reports: &[RepoReport],
+) -> Result<()> {
+    if notifications.is_empty() {
+        return Ok(());
+    }
+    let summary = RunSummary::new(command, reports);
+    if summary.repos.is_empty() {
+        return Ok(());
+    }
+    let errors: Vec<Report> = notifications
+        .iter()
+        .filter_map(|notification| notification.send(&summary).err())
+        .collect();
+    if !errors.is_empty() {
+        bail!(errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+    Ok(())
+}
+
+/// Whether `program` is an executable file, looked up on PATH unless it is a path itself
+///
+/// # Arguments
+///
+/// * `program` - The name or path of the program
+fn is_installed(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+/// Runs `command`, writing `stdin` to its stdin, and fails if it does not succeed
+///
+/// # Arguments
+///
+/// * `command` - The command being run
+/// * `stdin` - What the command reads from its stdin
+/// * `what` - What the command does, for the error messages
+fn run(command: &mut process::Command, stdin: &str, what: &str) -> Result<()> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| eyre!("Unable to {}! Error: {:?}", what, e.to_string()))?;
+    if let Some(mut child_stdin) = child.stdin.take() {
+        // a command that does not read its stdin closes the pipe early, which is fine
+        let _ = child_stdin.write_all(stdin.as_bytes());
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| eyre!("Unable to {}! Error: {:?}", what, e.to_string()))?;
+    if !output.status.success() {
+        bail!(
+            "Unable to {}: {}",
+            what,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(name: &str, outcome: Outcome, incoming: &[&str]) -> RepoReport {
+        RepoReport {
+            name: name.to_string(),
+            url: format!("git@github.com:company/{}.git", name),
+            path: format!("/home/foo/work/{}", name),
+            outcome,
+            problems: vec![],
+            retries: vec![],
+            lock: None,
+            snapshot: None,
+            started: SystemTime::UNIX_EPOCH,
+            finished: SystemTime::UNIX_EPOCH,
+            commits: vec![],
+            incoming: incoming.iter().map(|branch| branch.to_string()).collect(),
+            diverged: vec![],
+        }
+    }
+
+    #[test]
+    fn missing_programs_are_reported() {
+        assert!(is_installed("sh"));
+        let email = Notification::Email {
+            to: "me@example.com".to_string(),
+            sendmail: Some("/nonexistent/sendmail".to_string()),
+            on: Trigger::all(),
+        };
+        let error = email.check().unwrap_err().to_string();
+        assert!(error.contains("/nonexistent/sendmail"), "{}", error);
+        let failed = [report("api", Outcome::Error("no".to_string()), &[])];
+        let error = notify(&[email], &Command::Sync, &failed).unwrap_err();
+        assert!(error.to_string().contains("/nonexistent/sendmail"));
+        let command = Notification::Command {
+            command: "true".to_string(),
+            on: Trigger::all(),
+        };
+        assert!(command.check().is_ok());
+    }
+
+    #[test]
+    fn summary_holds_repos_needing_attention() {
+        let mut diverged = report("web", Outcome::Success, &[]);
+        diverged.diverged = vec![Divergence {
+            branch: "main".to_string(),
+            ahead: 1,
+            behind: 2,
+        }];
+        let reports = vec![
+            report("api", Outcome::Failure("remote hung up".to_string()), &[]),
+            report("docs", Outcome::Success, &[]),
+            report("cli", Outcome::Cancelled, &[]),
+            diverged,
+            report("infra", Outcome::Success, &["main", "dev"]),
+        ];
+        let summary = RunSummary::new(&Command::Sync, &reports);
+        assert_eq!(summary.triggers, Trigger::all());
+        assert_eq!(
+            summary
+                .repos
+                .iter()
+                .map(|repo| repo.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["api", "web", "infra"]
+        );
+        assert_eq!(
+            summary.subject(),
+            "repoteer sync: 1 failed, 1 diverged, 1 with new commits"
+        );
+        assert_eq!(
+            summary.text(),
+            "api: failed: remote hung up\n\
+             web: diverged: main (1 ahead, 2 behind)\n\
+             infra: new commits on main, dev"
+        );
+
+        let changes = summary
+            .only(&[Trigger::Divergence, Trigger::Incoming])
+            .unwrap();
+        assert_eq!(
+            changes.triggers,
+            vec![Trigger::Divergence, Trigger::Incoming]
+        );
+        assert_eq!(
+            changes.subject(),
+            "repoteer sync: 1 diverged, 1 with new commits"
+        );
+        let quiet = RunSummary::new(&Command::Sync, &reports[1..3]);
+        assert!(quiet.only(&Trigger::all()).is_none());
+    }
+
+    #[test]
+    fn command_receives_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let notification = Notification::Command {
+            command: format!(
+                "{{ echo \"$REPOTEER_SUBJECT\"; cat; }} > {:?}",
+                out.to_string_lossy()
+            ),
+            on: vec![Trigger::Incoming],
+        };
+        let failed = [report("api", Outcome::Error("no".to_string()), &[])];
+        assert!(!notification
+            .send(&RunSummary::new(&Command::Fetch, &failed))
+            .unwrap());
+        assert!(!out.exists());
+
+        let incoming = [report("api", Outcome::Success, &["main"])];
+        assert!(notification
+            .send(&RunSummary::new(&Command::Fetch, &incoming))
+            .unwrap());
+        let written = std::fs::read_to_string(&out).unwrap();
+        let (subject, json) = written.split_once('\n').unwrap();
+        assert_eq!(subject, "repoteer fetch: 1 with new commits");
+        let json: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(json["repos"][0]["incoming"][0], "main");
+        assert_eq!(json["triggers"][0], "incoming");
+    }
+
+    #[test]
+    fn failing_command_fails() {
+        let notification = Notification::Command {
+            command: "echo broken >&2; exit 1".to_string(),
+            on: Trigger::all(),
+        };
+        let failed = [report("api", Outcome::Error("no".to_string()), &[])];
+        let error = notify(&[notification], &Command::Sync, &failed).unwrap_err();
+        assert!(error.to_string().contains("broken"));
+    }
+}
@@ -1,78 +1,1022 @@
-use color_eyre::{eyre::bail, Result};
-use colored::*;
-use std::process::Output;
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    process::{ExitStatus, Output, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::mpsc::unbounded_channel;
 
 use crate::{
     cli::command::Command,
-    manifest::{repo::Repo, Manifest},
+    manifest::{
+        hooks::{Hook, Hooks},
+        lock::{LockedCheckout, LockedRepo, Lockfile},
+        repo::{Autostash, ChangeKind, Repo, Submodules},
+        Manifest,
+    },
+    notify::Notification,
+    state::{
+        history::{CommitRange, History},
+        snapshot::{RepoSnapshot, Snapshot},
+    },
+};
+
+use self::{
+    backend::{BackendKind, GitBackend},
+    events::{Event, EventSender},
+    interrupt::{CancelToken, Deadlines, Interruption},
+    retry::{ErrorKind, Retry, RetryPolicy},
+    status::SubmoduleProblem,
 };
 
-/// Runs the operation given throught the CLI `command` field
+pub mod backend;
+pub mod events;
+pub mod interrupt;
+pub mod retry;
+pub mod revision;
+mod snapshot;
+pub mod status;
+
+/// Runs the operation given throught the CLI `command` field, and returns a `RepoReport` for
+/// every repo in the `manifest`, in the order they are declared in
 ///
 /// # Arguments
 ///
 /// * `command` - The `Command` the user gave when calling `repoteer`
 /// * `manifest` - The `Manifest` holding info about the repositories being managed
+/// * `options` - The `Options` modifying how the `command` is run
 ///
 /// # Examples
 ///
+/// ```no_run
+/// use repoteer::{run_operations, Command, Manifest, Options};
+///
+/// # async fn example() -> color_eyre::Result<()> {
+/// let manifest = Manifest::new(&None)?;
+/// let reports = run_operations(Command::Clone, manifest, Options::default()).await?;
+/// assert!(reports.iter().all(|report| report.is_success()));
+/// # Ok(())
+/// # }
 /// ```
-/// let command = Command::clone;
-/// let manifest = Manifest { ... };
-/// run_operations(command, manifest);
+pub async fn run_operations(
+    command: Command,
+    manifest: Manifest,
+    options: Options,
+) -> Result<Vec<RepoReport>> {
+    // nobody listens to the events, so sending them fails silently
+    let (events, _) = unbounded_channel();
+    run_operations_with_events(command, manifest, options, events).await
+}
+
+/// Runs the operation like `run_operations`, and emits `Event`s about its progress on `events`
+///
+/// The channel is closed once every repo has been processed and the last `GitRunner` holding the
+/// sender has been dropped.
+///
+/// # Arguments
+///
+/// * `command` - The `Command` the user gave when calling `repoteer`
+/// * `manifest` - The `Manifest` holding info about the repositories being managed
+/// * `options` - The `Options` modifying how the `command` is run
+/// * `events` - The `EventSender` the `Event`s are emitted on
+///
+/// # Examples
+///
+/// ```no_run
+/// use repoteer::{operations::run_operations_with_events, Command, Manifest, Options};
+///
+/// # async fn example() -> color_eyre::Result<()> {
+/// let (events, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+/// let printer = tokio::spawn(async move {
+///     while let Some(event) = receiver.recv().await {
+///         println!("{:?}", event);
+///     }
+/// });
+/// let manifest = Manifest::new(&None)?;
+/// run_operations_with_events(Command::Pull, manifest, Options::default(), events).await?;
+/// printer.await?;
+/// # Ok(())
+/// # }
 /// ```
-pub async fn run_operations(command: Command, manifest: Manifest) -> Result<()> {
+pub async fn run_operations_with_events(
+    command: Command,
+    manifest: Manifest,
+    options: Options,
+    events: EventSender,
+) -> Result<Vec<RepoReport>> {
+    if !command.runs_on_repos() {
+        bail!(
+            "The {} command does not run on repos directly!",
+            command.name()
+        );
+    }
+    let git = GitRunner::new(events, &options);
+    let backend = options.backend.build(git.clone());
     let futures: Vec<_> = manifest
         .repos
         .into_iter()
-        .map(|repo| tokio::spawn(handle_repo(RepoTask::new(repo), command)))
+        .map(|repo| {
+            let task = RepoTask::new(repo, backend.clone(), git.clone(), &options.hooks);
+            let options = options.clone();
+            // git is run synchronously, so every repo gets its own thread, to keep the executor free
+            // for whoever receives the events
+            let command = command.clone();
+            tokio::task::spawn_blocking(move || handle_repo(task, command, options))
+        })
         .collect();
+    drop((git, backend));
+    let mut reports = vec![];
     for f in futures.into_iter() {
-        f.await?;
+        reports.push(f.await?);
+    }
+    if let Some(state_dir) = options.state_dir.as_ref().filter(|_| !options.dry_run) {
+        History::record_all(History::path(state_dir), command.name(), &reports)?;
+    }
+    Ok(reports)
+}
+
+/// The result of running a command on a single repo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoReport {
+    /// Name of the repo, see `Repo::name`
+    pub name: String,
+
+    /// URL of the repo's remote
+    pub url: String,
+
+    /// Where the repo is located on the local filesystem
+    pub path: String,
+
+    /// How the command as a whole ended
+    pub outcome: Outcome,
+
+    /// Failures of single steps, like pulling one of several branches, that did not stop the
+    /// command as a whole
+    pub problems: Vec<String>,
+
+    /// The git commands that were run again after transient failures
+    pub retries: Vec<Retry>,
+
+    /// The commits the checkouts of the repo are at, if it was locked with `Command::Lock`
+    pub lock: Option<LockedRepo>,
+
+    /// The state of the repo, if it was recorded with `Command::Snapshot`
+    pub snapshot: Option<RepoSnapshot>,
+
+    /// When the command started on the repo
+    pub started: SystemTime,
+
+    /// When the command finished on the repo
+    pub finished: SystemTime,
+
+    /// The checkouts whose HEAD the command moved, which are only determined if
+    /// `Options::state_dir` is set
+    pub commits: Vec<CommitRange>,
+
+    /// The remote branches that received new commits, which are only determined if
+    /// `Options::notifications` are configured
+    pub incoming: Vec<String>,
+
+    /// The local branches that diverged from their remote branch, which are only determined if
+    /// `Options::notifications` are configured
+    pub diverged: Vec<Divergence>,
+}
+
+/// A local branch that has commits its remote branch does not have, and the other way around
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Name of the branch
+    pub branch: String,
+
+    /// How many commits only the local branch has
+    pub ahead: usize,
+
+    /// How many commits only the remote branch has
+    pub behind: usize,
+}
+
+impl RepoReport {
+    /// Whether the command succeeded without any problems
+    pub fn is_success(&self) -> bool {
+        self.outcome == Outcome::Success && self.problems.is_empty()
+    }
+}
+
+/// Enumerates how running a command on a repo can end
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The command ran to completion
+    Success,
+
+    /// A git command failed, carrying its stderr
+    Failure(String),
+
+    /// repoteer ran into an error, like a git command that could not be started or output that
+    /// could not be parsed
+    Error(String),
+
+    /// A git command failed because the remote rejected the credentials or the host key, or
+    /// because git would have had to prompt for them, carrying its stderr
+    AuthFailed(String),
+
+    /// A git command or the repo as a whole ran longer than its timeout, carrying which one
+    TimedOut(String),
+
+    /// The operations were cancelled through their `CancelToken`
+    Cancelled,
+}
+
+/// Flags that modify how the operations are being run
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Whether worktrees of branches that were deleted upstream should be removed during `sync`
+    pub prune: bool,
+
+    /// Which local changes may be stashed before pulling, for repos that do not set `autostash`
+    pub autostash: Option<Autostash>,
+
+    /// Which `GitBackend` is used to query and fetch repositories
+    pub backend: BackendKind,
+
+    /// Whether git commands that change repositories are only reported instead of being run
+    pub dry_run: bool,
+
+    /// How long a single git command may run before it is killed
+    pub timeout: Option<Duration>,
+
+    /// How long the git commands of a single repo may run in total before they are killed
+    pub repo_timeout: Option<Duration>,
+
+    /// Cancels the operations of every repo once it is cancelled
+    pub cancel: CancelToken,
+
+    /// How git commands that talk to a remote are retried after transient failures
+    pub retry: RetryPolicy,
+
+    /// Whether git may prompt for credentials or host key confirmations. Otherwise, git fails
+    /// right away when it would have to ask, see `NON_INTERACTIVE_ENV`.
+    pub interactive: bool,
+
+    /// Hooks that run for every repo that does not declare the same hook itself
+    pub hooks: Hooks,
+
+    /// The lockfile `Command::Checkout` brings the checkouts to
+    pub lockfile: Option<Lockfile>,
+
+    /// The snapshot `Command::Restore` restores
+    pub snapshot: Option<Snapshot>,
+
+    /// The directory the `History` of the runs is recorded in. Nothing is recorded if this is not
+    /// set, or in a dry run.
+    pub state_dir: Option<PathBuf>,
+
+    /// The notifications sent after commands that contact the remotes. Incoming and diverged
+    /// branches are only determined if there are any.
+    pub notifications: Vec<Notification>,
+}
+
+/// Environment variables git is run with, unless `Options::interactive` is set, so that neither
+/// git, nor credential managers, nor ssh wait for input that never comes
+const NON_INTERACTIVE_ENV: [(&str, &str); 3] = [
+    ("GIT_TERMINAL_PROMPT", "0"),
+    ("GCM_INTERACTIVE", "never"),
+    ("GIT_ASKPASS", "true"),
+];
+
+/// Returns the ssh command git runs for `repo`, if it needs one other than the default
+///
+/// The command uses the repo's `ssh_key`, and unless `interactive` is set, ssh fails instead of
+/// asking for a password, a passphrase or a host key confirmation.
+///
+/// # Arguments
+///
+/// * `repo` - The `Repo` being operated on
+/// * `interactive` - Whether ssh may prompt for input
+fn ssh_command(repo: &Repo, interactive: bool) -> Option<String> {
+    let mut options = vec![];
+    if let Some(key) = repo.ssh_key_path() {
+        // git runs the command through the shell
+        options.push(format!(
+            "-i '{}' -o IdentitiesOnly=yes",
+            key.replace('\'', "'\\''")
+        ));
+    }
+    if !interactive {
+        options.push("-o BatchMode=yes".to_string());
+    }
+    if options.is_empty() {
+        return None;
+    }
+    let base = repo
+        .env
+        .as_ref()
+        .and_then(|env| env.get("GIT_SSH_COMMAND").cloned())
+        .or_else(|| std::env::var("GIT_SSH_COMMAND").ok())
+        .unwrap_or_else(|| "ssh".to_string());
+    Some(format!("{} {}", base, options.join(" ")))
+}
+
+/// Runs the git commands of all repos, and emits a `Event::GitCommandFinished` for each of them
+///
+/// In a dry run, commands that would change a repository are not run, but reported with a
+/// `Event::CommandSkipped`, and treated as if they succeeded.
+///
+/// Commands that run into a timeout, or are cancelled, are killed and fail with an `Interruption`.
+/// Commands that talk to a remote are retried after transient failures, see `RetryPolicy`.
+#[derive(Clone)]
+pub struct GitRunner {
+    events: EventSender,
+    dry_run: bool,
+    deadlines: Deadlines,
+    retry: RetryPolicy,
+    retries: Arc<Mutex<HashMap<String, Vec<Retry>>>>,
+    interactive: bool,
+}
+
+impl Default for GitRunner {
+    /// Returns a `GitRunner` whose events are not received by anyone
+    fn default() -> Self {
+        GitRunner::new(unbounded_channel().0, &Options::default())
+    }
+}
+
+impl GitRunner {
+    pub fn new(events: EventSender, options: &Options) -> Self {
+        GitRunner {
+            events,
+            dry_run: options.dry_run,
+            deadlines: Deadlines::new(
+                options.cancel.clone(),
+                options.timeout,
+                options.repo_timeout,
+            ),
+            retry: options.retry,
+            retries: Arc::default(),
+            interactive: options.interactive,
+        }
+    }
+
+    /// Whether commands that change a repository are only reported instead of being run
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Starts the repo timeout of `repo`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    fn start_repo(&self, repo: &Repo) {
+        self.deadlines.start_repo(&repo.name());
+    }
+
+    /// Returns the `Interruption` that stops the commands of `repo` at this point, if there is one
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `command_started` - When the current command was started
+    pub fn interruption(&self, repo: &Repo, command_started: Instant) -> Option<Interruption> {
+        self.deadlines.check(&repo.name(), command_started)
+    }
+
+    /// Returns the first `Interruption` that stopped a command of `repo`, if there was one
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    fn interrupted(&self, repo: &Repo) -> Option<Interruption> {
+        self.deadlines.interrupted(&repo.name())
+    }
+
+    /// Returns the git commands of `repo` that were run again after transient failures
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    fn retries(&self, repo: &Repo) -> Vec<Retry> {
+        self.retries
+            .lock()
+            .ok()
+            .and_then(|retries| retries.get(&repo.name()).cloned())
+            .unwrap_or_default()
+    }
+
+    /// Emits `event`, unless nobody is listening anymore
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The `Event` being emitted
+    pub fn emit(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+
+    /// Runs `command`, which was built for `repo`, and returns its `Output`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `mutating` - Whether `command` changes the repository, and is skipped in a dry run
+    /// * `retryable` - Whether `command` talks to a remote, and is retried after transient
+    ///   failures
+    /// * `command` - The git `std::process::Command` being run
+    fn execute(
+        &self,
+        repo: &Repo,
+        mutating: bool,
+        retryable: bool,
+        command: &mut std::process::Command,
+    ) -> Result<Output> {
+        let argv: Vec<String> = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        let cwd = command
+            .get_current_dir()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_else(|| ".".to_string());
+        if let Some(interruption) = self.interruption(repo, Instant::now()) {
+            return Err(interruption.into());
+        }
+        if self.dry_run && mutating {
+            self.emit(Event::CommandSkipped {
+                repo: repo.name(),
+                argv,
+                cwd,
+            });
+            return Ok(skipped_output());
+        }
+        self.with_retries(repo, &argv, retryable, || {
+            let output = self.spawn(repo, &argv, &cwd, command)?;
+            let stderr = (!output.status.success())
+                .then(|| String::from_utf8_lossy(&output.stderr).to_string());
+            Ok((output, stderr))
+        })
+    }
+
+    /// Runs `transfer`, which talks to a remote in-process in place of the git command `argv`,
+    /// the way `execute` runs git itself
+    ///
+    /// Every attempt emits a `Event::GitCommandFinished` with the status git would have exited
+    /// with, and failed attempts are retried like git commands, going by the message of their
+    /// error. An `Interruption` is returned right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `argv` - The git command `transfer` stands in for, starting with `git`
+    /// * `cwd` - The directory `transfer` operates on
+    /// * `transfer` - Runs the transfer once
+    pub fn transfer(
+        &self,
+        repo: &Repo,
+        argv: Vec<String>,
+        cwd: &str,
+        mut transfer: impl FnMut() -> Result<()>,
+    ) -> Result<()> {
+        if let Some(interruption) = self.interruption(repo, Instant::now()) {
+            return Err(interruption.into());
+        }
+        self.with_retries(repo, &argv, true, || {
+            let start = Instant::now();
+            let result = transfer();
+            let interrupted = result
+                .as_ref()
+                .is_err_and(|report| report.downcast_ref::<Interruption>().is_some());
+            let stderr = result.as_ref().err().map(|report| report.to_string());
+            self.emit(Event::GitCommandFinished {
+                repo: repo.name(),
+                argv: argv.clone(),
+                cwd: cwd.to_string(),
+                // git exits with 128 on fatal errors, and is killed when it is interrupted
+                status: match (&result, interrupted) {
+                    (Ok(()), _) => Some(0),
+                    (Err(_), false) => Some(128),
+                    (Err(_), true) => None,
+                },
+                stdout: String::new(),
+                stderr: stderr.clone().unwrap_or_default(),
+                duration: start.elapsed(),
+            });
+            match result {
+                Err(report) if interrupted => Err(report),
+                result => Ok((result, stderr)),
+            }
+        })?
+    }
+
+    /// Runs `attempt` and returns its result, running it again after transient failures if it is
+    /// `retryable`, see `RetryPolicy`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `argv` - The arguments of the git command being attempted, starting with `git`
+    /// * `retryable` - Whether the command talks to a remote, and is retried after transient
+    ///   failures
+    /// * `attempt` - Runs the command once, and returns its result together with its stderr if
+    ///   it failed
+    fn with_retries<T>(
+        &self,
+        repo: &Repo,
+        argv: &[String],
+        retryable: bool,
+        mut attempt: impl FnMut() -> Result<(T, Option<String>)>,
+    ) -> Result<T> {
+        let mut number = 1;
+        loop {
+            let (result, stderr) = attempt()?;
+            let stderr = match stderr {
+                Some(stderr) if retryable && number <= self.retry.retries => stderr,
+                _ => return Ok(result),
+            };
+            // rejected credentials are rejected again
+            if ErrorKind::classify(&stderr) != ErrorKind::Transient {
+                return Ok(result);
+            }
+            let retry = Retry {
+                argv: argv.to_vec(),
+                attempt: number,
+                delay: self.retry.backoff(number),
+                reason: stderr.trim().lines().last().unwrap_or_default().to_string(),
+            };
+            self.emit(Event::Retrying {
+                repo: repo.name(),
+                retry: retry.clone(),
+            });
+            if let Ok(mut retries) = self.retries.lock() {
+                retries.entry(repo.name()).or_default().push(retry.clone());
+            }
+            if let Some(interruption) = self.deadlines.sleep(&repo.name(), retry.delay) {
+                return Err(interruption.into());
+            }
+            number += 1;
+        }
+    }
+
+    /// Runs the `script` of `hook` for `repo` through `sh`, and emits a `Event::HookFinished`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `hook` - The `Hook` being run
+    /// * `script` - The shell command of the hook
+    /// * `cwd` - The directory the hook is run in
+    /// * `branch` - The branch checked out in the repo, if there is one
+    fn run_hook(
+        &self,
+        repo: &Repo,
+        hook: Hook,
+        script: &str,
+        cwd: &Path,
+        branch: Option<&str>,
+    ) -> Result<Output> {
+        if let Some(interruption) = self.interruption(repo, Instant::now()) {
+            return Err(interruption.into());
+        }
+        if self.dry_run {
+            self.emit(Event::CommandSkipped {
+                repo: repo.name(),
+                argv: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+                cwd: cwd.display().to_string(),
+            });
+            return Ok(skipped_output());
+        }
+        let start = Instant::now();
+        let mut command = std::process::Command::new("sh");
+        command
+            .arg("-c")
+            .arg(script)
+            .current_dir(cwd)
+            .envs(repo.env.iter().flatten())
+            .envs(hook.env(&repo.name(), &repo.url, &repo.path, branch));
+        if !self.interactive {
+            interrupt::isolate(&mut command);
+        }
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let (output, interruption) = self.deadlines.wait(&repo.name(), child)?;
+        self.emit(Event::HookFinished {
+            repo: repo.name(),
+            hook: hook.to_string(),
+            command: script.to_string(),
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            duration: start.elapsed(),
+        });
+        match interruption {
+            Some(interruption) => Err(interruption.into()),
+            None => Ok(output),
+        }
+    }
+
+    /// Runs `command` once, and emits a `Event::GitCommandFinished` for it
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `argv` - The arguments of `command`, starting with `git`
+    /// * `cwd` - The directory `command` is run in
+    /// * `command` - The git `std::process::Command` being run
+    fn spawn(
+        &self,
+        repo: &Repo,
+        argv: &[String],
+        cwd: &str,
+        command: &mut std::process::Command,
+    ) -> Result<Output> {
+        if !self.interactive {
+            command.envs(NON_INTERACTIVE_ENV);
+            interrupt::isolate(command);
+        }
+        command.envs(repo.env.iter().flatten());
+        if let Some(ssh_command) = ssh_command(repo, self.interactive) {
+            command.env("GIT_SSH_COMMAND", ssh_command);
+        }
+        let start = Instant::now();
+        // `Command::output` would wait for git indefinitely, so git is polled until it exits, or
+        // it is killed once it runs into a timeout
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let (output, interruption) = self.deadlines.wait(&repo.name(), child)?;
+        self.emit(Event::GitCommandFinished {
+            repo: repo.name(),
+            argv: argv.to_vec(),
+            cwd: cwd.to_string(),
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            duration: start.elapsed(),
+        });
+        match interruption {
+            Some(interruption) => Err(interruption.into()),
+            None => Ok(output),
+        }
+    }
+}
+
+/// Returns the `Output` of a command that was skipped in a dry run, which counts as a success
+fn skipped_output() -> Output {
+    Output {
+        status: ExitStatus::default(),
+        stdout: vec![],
+        stderr: vec![],
     }
-    Ok(())
 }
 
 struct RepoTask {
     pub repo: Repo,
     pub state: String,
-    repo_name_string: String,
+    pub backend: Arc<dyn GitBackend>,
+    pub git: GitRunner,
+    pub problems: Vec<String>,
+    pub lock: Option<LockedRepo>,
+    pub snapshot: Option<RepoSnapshot>,
+    name: String,
+    hooks: Hooks,
 }
 
 impl RepoTask {
-    pub fn new(repo: Repo) -> Self {
-        let repo_name_string = format!("Repo:  {}", &repo.url);
+    pub fn new(
+        repo: Repo,
+        backend: Arc<dyn GitBackend>,
+        git: GitRunner,
+        global_hooks: &Hooks,
+    ) -> Self {
+        let name = repo.name();
+        let hooks = repo.hooks.or(global_hooks);
         RepoTask {
             repo,
             state: "".to_string(),
-            repo_name_string,
+            backend,
+            git,
+            problems: vec![],
+            lock: None,
+            snapshot: None,
+            name,
+            hooks,
         }
     }
+    /// Runs `hook`, if the repo or the global config declare it
+    ///
+    /// A failing pre hook fails with an error, which aborts the operation, while a failing post
+    /// hook is reported as a problem.
+    pub fn run_hook(&mut self, hook: Hook) -> Result<()> {
+        let script = match self.hooks.get(hook) {
+            Some(script) => script.to_string(),
+            None => return Ok(()),
+        };
+        self.start_phase(&format!("{} hook", hook), None);
+        let path = Path::new(&self.repo.path);
+        let (cwd, branch) = if path.exists() {
+            let output =
+                GitCommand::CurrentBranch.run(&self.git, &self.repo, &self.repo.path, "")?;
+            let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (path, (!branch.is_empty()).then_some(branch))
+        } else {
+            // the repo is not cloned yet, so the hook runs where it is going to be cloned into
+            let parent = path.ancestors().skip(1).find(|dir| dir.is_dir());
+            (parent.unwrap_or(Path::new("/")), self.repo.branch.clone())
+        };
+        let output = self
+            .git
+            .run_hook(&self.repo, hook, &script, cwd, branch.as_deref())?;
+        if output.status.success() {
+            return Ok(());
+        }
+        let message = format!("The {} hook failed: {}", hook, get_output_message(&output));
+        if hook.is_pre() {
+            bail!(message);
+        }
+        self.report_problem(message);
+        Ok(())
+    }
+    /// Sets the state of the task to `new_state_string`, and emits it as a `Event::Message`
     pub fn update_state(&mut self, new_state_string: String) {
-        self.state = format!(
-            "{}\n   {}",
-            self.repo_name_string.blue(),
-            new_state_string
-        );
+        self.state = new_state_string;
+        self.git.emit(Event::Message {
+            repo: self.name.clone(),
+            message: self.state.clone(),
+        });
+    }
+    /// Emits a `Event::PhaseStarted` for the operation `op` on `branch`
+    pub fn start_phase(&self, op: &str, branch: Option<&str>) {
+        self.git.emit(Event::PhaseStarted {
+            repo: self.name.clone(),
+            op: op.to_string(),
+            branch: branch.map(|b| b.to_string()),
+        });
     }
-    pub fn print_state(&self) {
-        println!("{}\n", self.state);
+    /// Records `problem` for the `RepoReport`, and emits it as a `Event::Problem`
+    pub fn report_problem(&mut self, problem: String) {
+        self.git.emit(Event::Problem {
+            repo: self.name.clone(),
+            problem: problem.clone(),
+        });
+        self.problems.push(problem);
     }
 }
 
-/// Async function to run the CLI `command` on a single `Repo`
+/// Runs the CLI `command` on a single `Repo`, and returns its `RepoReport`
 ///
 /// # Arguments
 ///
 /// * `repo` - The repository the `command` is being run on
 /// * `command` - The `Command` the user gave when calling `repoteer`
-async fn handle_repo(mut task: RepoTask, command: Command) {
-    process(match command {
+/// * `options` - The `Options` modifying how the `command` is run
+fn handle_repo(mut task: RepoTask, command: Command, options: Options) -> RepoReport {
+    task.git.emit(Event::RepoStarted {
+        repo: task.name.clone(),
+        url: task.repo.url.clone(),
+    });
+    task.git.start_repo(&task.repo);
+    let started = SystemTime::now();
+    let recording = options.state_dir.is_some() && !options.dry_run;
+    let heads_before = recording.then(|| checkout_heads(&task)).flatten();
+    let detecting =
+        !options.notifications.is_empty() && !options.dry_run && command.contacts_remote();
+    let tips_before = detecting.then(|| tips(&task)).flatten();
+    let outcome = process(match command {
         Command::Clone => run_clone(&mut task),
-        Command::Pull => run_pull(&mut task),
+        Command::Pull => run_pull(&mut task, options),
         Command::Push => run_push(&mut task),
-        Command::Sync => run_sync(&mut task),
+        Command::Sync => run_sync(&mut task, options),
+        Command::Status => run_status(&mut task),
+        Command::Fetch => run_fetch(&mut task),
+        Command::Lock => run_lock(&mut task),
+        Command::Checkout { locked } => run_checkout(&mut task, locked, &options),
+        Command::Snapshot { .. } => snapshot::run_snapshot(&mut task),
+        Command::Restore { force, .. } => match &options.snapshot {
+            Some(saved) => snapshot::run_restore(&mut task, saved, force),
+            None => Err(eyre!("No snapshot was given to restore!")),
+        },
+        Command::History { .. }
+        | Command::Watch { .. }
+        | Command::Schedule { .. }
+        | Command::Completions { .. }
+        | Command::Man
+        | Command::CompleteRepos { .. } => Err(eyre!(
+            "The {} command does not run on repos directly!",
+            command.name()
+        )),
     });
+    // an interrupted step, like pulling one of several branches, is only reported as a problem, but
+    // the repo as a whole still ended with the interruption
+    let outcome = match task.git.interrupted(&task.repo) {
+        Some(interruption) => interruption.into(),
+        None => outcome,
+    };
+    let commits = match heads_before {
+        Some(before) => checkout_heads(&task)
+            .map(|after| commit_ranges(before, after))
+            .unwrap_or_default(),
+        None => vec![],
+    };
+    let (incoming, diverged) = match (tips_before, detecting.then(|| tips(&task)).flatten()) {
+        (Some(before), Some(after)) => (
+            incoming_branches(&before, &after),
+            diverged_branches(&task, &after),
+        ),
+        _ => (vec![], vec![]),
+    };
+    let retries = task.git.retries(&task.repo);
+    task.git.emit(Event::RepoFinished {
+        repo: task.name.clone(),
+        outcome: outcome.clone(),
+        problems: task.problems.clone(),
+        retries: retries.clone(),
+    });
+    RepoReport {
+        name: task.name,
+        url: task.repo.url,
+        path: task.repo.path,
+        outcome,
+        problems: task.problems,
+        retries,
+        lock: task.lock,
+        snapshot: task.snapshot,
+        started,
+        finished: SystemTime::now(),
+        commits,
+        incoming,
+        diverged,
+    }
+}
+
+/// Returns the commit HEAD points to in every checkout of the repo, keyed by the path of the
+/// checkout, or `None` if they cannot be determined, like after an interruption
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+fn checkout_heads(task: &RepoTask) -> Option<Vec<(String, String)>> {
+    if !Path::new(&task.repo.path).exists() {
+        return Some(vec![]);
+    }
+    let mut heads = vec![];
+    for entry in task
+        .backend
+        .worktrees(&task.repo, &task.repo.path)
+        .ok()?
+        .into_iter()
+        .filter(|entry| !entry.is_bare)
+    {
+        let output = GitCommand::VerifyRef
+            .run(&task.git, &task.repo, &entry.path, "HEAD")
+            .ok()?;
+        // checkouts without any commit have no HEAD to record
+        if let Some(head) = get_output_lines(output).ok()?.into_iter().next() {
+            heads.push((entry.path, head));
+        }
+    }
+    Some(heads)
+}
+
+/// Returns how the HEADs of the checkouts moved from `before` to `after`, leaving out the
+/// checkouts whose HEAD did not move
+///
+/// # Arguments
+///
+/// * `before` - The HEADs before the command, see `checkout_heads`
+/// * `after` - The HEADs after the command, see `checkout_heads`
+fn commit_ranges(before: Vec<(String, String)>, after: Vec<(String, String)>) -> Vec<CommitRange> {
+    let find = |heads: &[(String, String)], path: &str| {
+        heads
+            .iter()
+            .find(|(p, _)| p == path)
+            .map(|(_, head)| head.clone())
+    };
+    let mut ranges: Vec<CommitRange> = after
+        .iter()
+        .filter_map(|(path, head)| {
+            let previous = find(&before, path);
+            (previous.as_ref() != Some(head)).then(|| CommitRange {
+                path: path.clone(),
+                before: previous,
+                after: Some(head.clone()),
+            })
+        })
+        .collect();
+    ranges.extend(
+        before
+            .iter()
+            .filter(|(path, _)| find(&after, path).is_none())
+            .map(|(path, head)| CommitRange {
+                path: path.clone(),
+                before: Some(head.clone()),
+                after: None,
+            }),
+    );
+    ranges
+}
+
+/// The commits the local branches of a repo and the branches of its remote, as last fetched,
+/// point to, keyed by the name of the branch
+struct Tips {
+    local: BTreeMap<String, String>,
+    remote: BTreeMap<String, String>,
+}
+
+/// Returns the `Tips` of the repo, or `None` if they cannot be determined, like for repos that
+/// are not cloned yet, or mirrors, which have no branches of their own
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+fn tips(task: &RepoTask) -> Option<Tips> {
+    if !Path::new(&task.repo.path).exists() || task.repo.is_mirror() {
+        return None;
+    }
+    let mut remote = ref_tips(task, GitCommand::RemoteTips).ok()?;
+    remote.remove("HEAD");
+    Some(Tips {
+        local: ref_tips(task, GitCommand::BranchTips).ok()?,
+        remote,
+    })
+}
+
+/// Returns the commit every ref listed by `command` points to, keyed by the name of the ref
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+/// * `command` - Either `GitCommand::BranchTips` or `GitCommand::RemoteTips`
+fn ref_tips(task: &RepoTask, command: GitCommand) -> Result<BTreeMap<String, String>> {
+    let output = command.run(&task.git, &task.repo, &task.repo.path, "")?;
+    if !output.status.success() {
+        bail!("Unable to list branches: {}", get_output_message(&output));
+    }
+    Ok(get_output_lines(output)?
+        .into_iter()
+        .filter_map(|line| {
+            let (branch, commit) = line.rsplit_once(' ')?;
+            Some((branch.to_string(), commit.to_string()))
+        })
+        .collect())
+}
+
+/// Returns the remote branches that received commits between `before` and `after`
+///
+/// Remote branches moved to the commit their local branch was at before, like by pushing it,
+/// did not receive anything new.
+///
+/// # Arguments
+///
+/// * `before` - The `Tips` before the command
+/// * `after` - The `Tips` after the command
+fn incoming_branches(before: &Tips, after: &Tips) -> Vec<String> {
+    after
+        .remote
+        .iter()
+        .filter(|(branch, commit)| {
+            before.remote.get(*branch) != Some(commit) && before.local.get(*branch) != Some(commit)
+        })
+        .map(|(branch, _)| branch.clone())
+        .collect()
+}
+
+/// Returns the local branches of `tips` that diverged from their remote branch
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+/// * `tips` - The `Tips` after the command
+fn diverged_branches(task: &RepoTask, tips: &Tips) -> Vec<Divergence> {
+    tips.local
+        .iter()
+        .filter(|(branch, commit)| {
+            tips.remote
+                .get(*branch)
+                .is_some_and(|remote| remote != *commit)
+        })
+        .filter_map(|(branch, _)| {
+            match task
+                .backend
+                .ahead_behind(&task.repo, &task.repo.path, branch)
+            {
+                Ok(Some((ahead, behind))) if ahead > 0 && behind > 0 => Some(Divergence {
+                    branch: branch.clone(),
+                    ahead,
+                    behind,
+                }),
+                _ => None,
+            }
+        })
+        .collect()
 }
 
 /// Enumerates the different git commands used throughout this module
@@ -83,280 +1027,1201 @@ enum GitCommand {
     Clone,
     Pull,
     Push,
+    Fetch,
     StatusPorcelain,
+    SubmoduleUpdate,
+    SubmoduleStatus,
+    SparseCheckoutSet,
+    RemoteUpdate,
+    PushMirror,
+    BranchList,
+    WorktreeList,
+    WorktreeAdd,
+    WorktreeRemove,
+    LsRemoteHeads,
+    VerifyRef,
+    AheadBehind,
+    StashPush { include_untracked: bool },
+    StashPop,
+    StashHead,
+    ConfigSet { key: String, value: String },
+    CurrentBranch,
+    CommitExists,
+    Checkout,
+    CheckoutDetach,
+    CheckoutTrack,
+    MergeFastForward,
+    RefsContaining,
+    BranchTips,
+    RemoteTips,
+    StashList,
+    FetchRefspecs,
+    IsAncestor { descendant: String },
+    CommitsBetween { from: String },
+    UpstreamMerge,
+    UnpushedCount,
+    ResetKeep,
+    UpdateRef { new: String, old: String },
 }
 
 impl GitCommand {
+    /// Whether the git command declared by Self changes the repository or its remote, as opposed
+    /// to only querying them
+    fn is_mutating(&self) -> bool {
+        !matches!(
+            self,
+            GitCommand::StatusPorcelain
+                | GitCommand::SubmoduleStatus
+                | GitCommand::BranchList
+                | GitCommand::WorktreeList
+                | GitCommand::LsRemoteHeads
+                | GitCommand::VerifyRef
+                | GitCommand::AheadBehind
+                | GitCommand::StashHead
+                | GitCommand::CurrentBranch
+                | GitCommand::CommitExists
+                | GitCommand::RefsContaining
+                | GitCommand::BranchTips
+                | GitCommand::RemoteTips
+                | GitCommand::StashList
+                | GitCommand::FetchRefspecs
+                | GitCommand::IsAncestor { .. }
+                | GitCommand::CommitsBetween { .. }
+                | GitCommand::UpstreamMerge
+                | GitCommand::UnpushedCount
+        )
+    }
+
+    /// Whether the git command declared by Self talks to a remote, and may fail because of the
+    /// network
+    fn uses_network(&self) -> bool {
+        matches!(
+            self,
+            GitCommand::Clone
+                | GitCommand::Pull
+                | GitCommand::Push
+                | GitCommand::Fetch
+                | GitCommand::SubmoduleUpdate
+                | GitCommand::RemoteUpdate
+                | GitCommand::PushMirror
+                | GitCommand::LsRemoteHeads
+        )
+    }
+
     /// Runs the git command declared by Self and returns a `eyre::Result<Output>`
     ///
     /// # Arguments
     ///
     /// * `self` - The `GitCommand` that called this method
+    /// * `git` - The `GitRunner` running the command
     /// * `repo` - The `Repo` being operated on
-    /// * `path` - The `path` where the command is being run
-    /// * `branch` - The branch being operated on
-    fn run(&self, repo: &Repo, path: &str, branch: &str) -> Result<Output> {
+    /// * `path` - The `path` where the command is being run, or the path of the worktree being
+    ///   added or removed
+    /// * `branch` - The branch or ref being operated on
+    fn run(&self, git: &GitRunner, repo: &Repo, path: &str, branch: &str) -> Result<Output> {
         let mut git_command_stump = std::process::Command::new("git");
-        Ok(match self {
-            GitCommand::Clone => git_command_stump
-                // this is a bit ugly,  but unfortunately just setting the last arg to an empty string
-                // in the case of passing --bare does not work, because the process still reads it as
-                // an argument and then complains about receiving too many arguments.
-                // basically, if I were to pass ["clone", &repo.url, &repo.path, ""], the command would
-                // be `git clone <url> <dir> ""`, and then it would complain about that last "".
-                .args(if repo.is_bare.is_some() && repo.is_bare.unwrap() {
-                    vec!["clone", &repo.url, &repo.path, "--bare"]
-                } else {
-                    vec!["clone", &repo.url, &repo.path]
-                }),
-            GitCommand::Pull => {
-                if has_unstaged_changes(repo, &repo.path)? {
-                    bail!(
-                        "Repo has unstaged changes on branch {} pull aborted!",
-                        get_current_branch(&repo.path)?
-                    );
-                } else {
-                    git_command_stump
-                        .args(["pull", "origin", branch])
-                        .current_dir(path)
+        git_command_stump.args(repo.config_args());
+        git.execute(
+            repo,
+            self.is_mutating(),
+            self.uses_network(),
+            match self {
+                GitCommand::Clone => {
+                    // optional flags are pushed onto the args, because passing an empty string in
+                    // their place would make git complain about receiving too many arguments.
+                    let mut args = vec!["clone".to_string()];
+                    if repo.is_mirror() {
+                        args.push("--mirror".to_string());
+                    } else if repo.is_bare() {
+                        args.push("--bare".to_string());
+                    } else if repo.submodules() == Submodules::Recursive {
+                        args.push("--recurse-submodules".to_string());
+                    }
+                    if let Some(depth) = repo.depth {
+                        args.push(format!("--depth={}", depth));
+                    }
+                    if let Some(filter) = &repo.filter {
+                        args.push(format!("--filter={}", filter));
+                    }
+                    if repo.single_branch.unwrap_or(false) {
+                        args.push("--single-branch".to_string());
+                    }
+                    if let Some(branch) = &repo.branch {
+                        args.push(format!("--branch={}", branch));
+                    }
+                    if repo.sparse_checkout.is_some() {
+                        args.push("--sparse".to_string());
+                    }
+                    args.push("--".to_string());
+                    args.push(repo.url.to_string());
+                    args.push(repo.path.to_string());
+                    git_command_stump.args(args)
                 }
-            }
-            GitCommand::Push => git_command_stump
-                .args(["push", "origin", branch])
-                .current_dir(path),
-            GitCommand::StatusPorcelain => git_command_stump
-                .args(["status", "--porcelain"])
-                .current_dir(path),
-        }
-        .output()?)
+                // shallow and partial clones are not deepened by plain pulls and fetches, so they do
+                // not need to pass `--depth` or `--filter` again. Passing `--depth` would even cut the
+                // fetched history off from the local branch, so git could not fast-forward anymore.
+                GitCommand::Pull => git_command_stump
+                    .args(["pull", "origin", branch])
+                    .current_dir(path),
+                GitCommand::Push => git_command_stump
+                    .args(["push", "origin", branch])
+                    .current_dir(path),
+                GitCommand::Fetch => git_command_stump
+                    .args(["fetch", "origin"])
+                    .current_dir(path),
+                GitCommand::RemoteUpdate => git_command_stump
+                    .args(["remote", "update", "--prune"])
+                    .current_dir(path),
+                GitCommand::PushMirror => git_command_stump
+                    .args(["push", "--mirror", "--"])
+                    .args(repo.push_mirror.as_deref())
+                    .current_dir(path),
+                GitCommand::SparseCheckoutSet => git_command_stump
+                    .args(["sparse-checkout", "set", "--"])
+                    .args(repo.sparse_checkout.iter().flatten())
+                    .current_dir(path),
+                GitCommand::StatusPorcelain => git_command_stump
+                    .args(["status", "--porcelain=v2"])
+                    .current_dir(path),
+                GitCommand::SubmoduleUpdate => git_command_stump
+                    .args(if repo.submodules() == Submodules::Recursive {
+                        vec!["submodule", "update", "--init", "--recursive"]
+                    } else {
+                        vec!["submodule", "update", "--init"]
+                    })
+                    .current_dir(path),
+                GitCommand::BranchList => git_command_stump
+                    .args(["for-each-ref", "--format=%(refname:short)", "refs/heads"])
+                    .current_dir(path),
+                GitCommand::WorktreeList => git_command_stump
+                    .args(["worktree", "list", "--porcelain"])
+                    .current_dir(path),
+                GitCommand::WorktreeAdd => git_command_stump
+                    .args(["worktree", "add", "--", path, branch])
+                    .current_dir(&repo.path),
+                GitCommand::WorktreeRemove => git_command_stump
+                    .args(["worktree", "remove", "--", path])
+                    .current_dir(&repo.path),
+                GitCommand::LsRemoteHeads => git_command_stump
+                    .args(["ls-remote", "--heads", "origin"])
+                    .current_dir(path),
+                GitCommand::VerifyRef => git_command_stump
+                    .args(["rev-parse", "--quiet", "--verify", branch])
+                    .current_dir(path),
+                GitCommand::AheadBehind => git_command_stump
+                    .args(["rev-list", "--left-right", "--count"])
+                    .arg(format!(
+                        "refs/heads/{}...refs/remotes/origin/{}",
+                        branch, branch
+                    ))
+                    .current_dir(path),
+                GitCommand::StashPush { include_untracked } => git_command_stump
+                    .args(["stash", "push", "--message", "repoteer autostash"])
+                    .args(include_untracked.then_some("--include-untracked"))
+                    .current_dir(path),
+                GitCommand::StashPop => git_command_stump.args(["stash", "pop"]).current_dir(path),
+                GitCommand::StashHead => git_command_stump
+                    .args(["rev-parse", "--quiet", "--verify", "refs/stash"])
+                    .current_dir(path),
+                GitCommand::SubmoduleStatus => git_command_stump
+                    .args(if repo.submodules() == Submodules::Recursive {
+                        vec!["submodule", "status", "--recursive"]
+                    } else {
+                        vec!["submodule", "status"]
+                    })
+                    .current_dir(path),
+                GitCommand::ConfigSet { key, value } => git_command_stump
+                    .args(["config", "--local", key, value])
+                    .current_dir(path),
+                GitCommand::CurrentBranch => git_command_stump
+                    .args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+                    .current_dir(path),
+                GitCommand::CommitExists => git_command_stump
+                    .args(["cat-file", "-e"])
+                    .arg(format!("{}^{{commit}}", branch))
+                    .current_dir(path),
+                GitCommand::Checkout => git_command_stump
+                    .args(["checkout", "--quiet", branch, "--"])
+                    .current_dir(path),
+                GitCommand::CheckoutDetach => git_command_stump
+                    .args(["checkout", "--quiet", "--detach", branch, "--"])
+                    .current_dir(path),
+                GitCommand::CheckoutTrack => git_command_stump
+                    .args(["checkout", "--quiet", "--track", "-b", branch])
+                    .arg(format!("refs/remotes/origin/{}", branch))
+                    .arg("--")
+                    .current_dir(path),
+                GitCommand::MergeFastForward => git_command_stump
+                    .args(["merge", "--quiet", "--ff-only"])
+                    .arg(format!("refs/remotes/origin/{}", branch))
+                    .current_dir(path),
+                GitCommand::BranchTips => git_command_stump
+                    .args([
+                        "for-each-ref",
+                        "--format=%(refname:short) %(objectname)",
+                        "refs/heads",
+                    ])
+                    .current_dir(path),
+                GitCommand::RemoteTips => git_command_stump
+                    .args([
+                        "for-each-ref",
+                        "--format=%(refname:lstrip=3) %(objectname)",
+                        "refs/remotes/origin",
+                    ])
+                    .current_dir(path),
+                GitCommand::StashList => git_command_stump
+                    .args(["stash", "list", "--format=%H %gs"])
+                    .current_dir(path),
+                GitCommand::FetchRefspecs => git_command_stump
+                    .args(["config", "--get-all", "remote.origin.fetch"])
+                    .current_dir(path),
+                GitCommand::IsAncestor { descendant } => git_command_stump
+                    .args(["merge-base", "--is-ancestor", branch, descendant])
+                    .current_dir(path),
+                GitCommand::CommitsBetween { from } => git_command_stump
+                    .args(["log", "--format=%h %s"])
+                    .arg(format!("{}..refs/heads/{}", from, branch))
+                    .current_dir(path),
+                GitCommand::UpstreamMerge => git_command_stump
+                    .args(["config", "--get"])
+                    .arg(format!("branch.{}.merge", branch))
+                    .current_dir(path),
+                GitCommand::UnpushedCount => git_command_stump
+                    .args(["rev-list", "--count"])
+                    .arg(format!("refs/heads/{}", branch))
+                    .args(["--not", "--remotes"])
+                    .current_dir(path),
+                GitCommand::ResetKeep => git_command_stump
+                    .args(["reset", "--quiet", "--keep", branch, "--"])
+                    .current_dir(path),
+                GitCommand::UpdateRef { new, old } => git_command_stump
+                    .args(["update-ref", "-m", "repoteer restore"])
+                    .arg(format!("refs/heads/{}", branch))
+                    .args([new, old])
+                    .current_dir(path),
+                GitCommand::RefsContaining => git_command_stump
+                    .args([
+                        "for-each-ref",
+                        "--count=1",
+                        "--format=%(refname)",
+                        "--contains",
+                    ])
+                    .arg(branch)
+                    .current_dir(path),
+            },
+        )
     }
 }
 
 /// Runs a `git clone` operation, defined in GitCommand::run(...) and returns a `eyre::Result<Output>`
 ///
+/// Afterwards, the sparse checkout is set up and submodules are initialised if the repo asks for
+/// it, and the worktrees declared for the repo are created, if they are missing.
+///
 /// # Arguments
 ///
 /// * `repo` - The `Repo` being operated on
-fn run_clone(task: &RepoTask) -> Result<Output> {
-    GitCommand::Clone.run(&task.repo, &task.repo.path, "")
+fn run_clone(task: &mut RepoTask) -> Result<Output> {
+    with_hooks(task, Hook::PreClone, Hook::PostClone, |task| {
+        let cloned = task.backend.clone_repo(&task.repo);
+        if cloned.is_ok() {
+            for (key, value) in task.repo.git_config.clone().into_iter().flatten() {
+                let result = GitCommand::ConfigSet { key, value }.run(
+                    &task.git,
+                    &task.repo,
+                    &task.repo.path,
+                    "",
+                );
+                report_failure(task, result);
+            }
+        }
+        if cloned.is_ok() && task.repo.is_bare() && !task.repo.is_mirror() {
+            track_remote_branches(task)?;
+            // the bare clone did not create any remote-tracking branches yet
+            if let Err(e) = task.backend.fetch(&task.repo, &task.repo.path) {
+                task.report_problem(e.to_string());
+            }
+        }
+        if cloned.is_ok() && task.repo.sparse_checkout.is_some() {
+            let result =
+                GitCommand::SparseCheckoutSet.run(&task.git, &task.repo, &task.repo.path, "");
+            report_failure(task, result);
+        }
+        if cloned.is_ok() && task.repo.revision.is_some() && Path::new(&task.repo.path).exists() {
+            revision::checkout_revision(task);
+        }
+        if cloned.is_ok() && !task.repo.is_bare() && task.repo.submodules() == Submodules::Init {
+            let result =
+                GitCommand::SubmoduleUpdate.run(&task.git, &task.repo, &task.repo.path, "");
+            report_failure(task, result);
+        }
+        if Path::new(&task.repo.path).exists() || task.git.is_dry_run() {
+            add_missing_worktrees(task)?;
+        }
+        cloned?;
+        task.update_state("Clone complete!".to_string());
+        Ok(std::process::Command::new("echo")
+            .arg(task.state.clone())
+            .output()?)
+    })
 }
 
 /// Runs a `git pull` operation, defined in GitCommand::run(...) and returns a `eyre::Result<Output>`
 ///
+/// Mirrors are updated with `git remote update --prune` instead.
+///
+/// # Arguments
+///
+/// * `repo` - The `Repo` being operated on
+/// * `options` - The `Options` modifying how the pull is run
+fn run_pull(task: &mut RepoTask, options: Options) -> Result<Output> {
+    with_hooks(task, Hook::PrePull, Hook::PostPull, |task| {
+        if task.repo.is_mirror() {
+            return run_mirror_operation(task, GitCommand::RemoteUpdate, "Remote update");
+        }
+        if task.repo.revision.is_some() {
+            return revision::run_revision_pull(task);
+        }
+        let autostash = task
+            .repo
+            .autostash
+            .or(options.autostash)
+            .unwrap_or_default();
+        let backend = task.backend.clone();
+        let pull = |git: &GitRunner, repo: &Repo, path: &str, branch: &str| {
+            pull_branch(git, backend.as_ref(), repo, path, branch, autostash)
+        };
+        run_operation_with_worktrees(task, pull, "Pull", true)
+    })
+}
+
+/// Pulls `branch` in the checkout at `path`, and returns a `eyre::Result<Output>`
+///
+/// If the checkout has local changes of a kind listed in the repo's `pull_blockers`, the pull is
+/// skipped, unless `autostash` allows stashing all of those changes. In that case the changes are
+/// stashed before pulling and restored afterwards. If they cannot be restored cleanly, the stash
+/// is kept and an error is returned.
+///
+/// After a successful pull, submodules are updated according to the repo's `submodules` setting.
+///
 /// # Arguments
 ///
+/// * `git` - The `GitRunner` running the git commands
+/// * `backend` - The `GitBackend` used to check the status of the checkout
 /// * `repo` - The `Repo` being operated on
-fn run_pull(task: &mut RepoTask) -> Result<Output> {
-    let pull = |repo: &Repo, path: &str, branch: &str| GitCommand::Pull.run(repo, path, branch);
-    run_operation_with_worktrees(task, pull, "Pull")
+/// * `path` - The path of the checkout being pulled
+/// * `branch` - The branch being pulled
+/// * `autostash` - Which changes may be stashed before pulling
+fn pull_branch(
+    git: &GitRunner,
+    backend: &dyn GitBackend,
+    repo: &Repo,
+    path: &str,
+    branch: &str,
+    autostash: Autostash,
+) -> Result<Output> {
+    let output = pull_branch_with_autostash(git, backend, repo, path, branch, autostash)?;
+    if output.status.success() && repo.submodules() != Submodules::None {
+        let submodule_output = GitCommand::SubmoduleUpdate.run(git, repo, path, "")?;
+        if !submodule_output.status.success() {
+            bail!(
+                "Pulled branch {}, but updating its submodules failed! {}",
+                branch,
+                get_output_message(&submodule_output)
+            );
+        }
+    }
+    Ok(output)
+}
+
+/// Pulls `branch` in the checkout at `path` like `pull_branch`, but without touching submodules
+///
+/// # Arguments
+///
+/// * `git` - The `GitRunner` running the git commands
+/// * `backend` - The `GitBackend` used to check the status of the checkout
+/// * `repo` - The `Repo` being operated on
+/// * `path` - The path of the checkout being pulled
+/// * `branch` - The branch being pulled
+/// * `autostash` - Which changes may be stashed before pulling
+fn pull_branch_with_autostash(
+    git: &GitRunner,
+    backend: &dyn GitBackend,
+    repo: &Repo,
+    path: &str,
+    branch: &str,
+    autostash: Autostash,
+) -> Result<Output> {
+    let blocking = backend.status(repo, path)?.only(&repo.pull_blockers());
+    if blocking.is_clean() {
+        return GitCommand::Pull.run(git, repo, path, branch);
+    }
+    let stashable: &[ChangeKind] = match autostash {
+        Autostash::Off => &[],
+        Autostash::Tracked => &[ChangeKind::Staged, ChangeKind::Unstaged],
+        Autostash::All => &[
+            ChangeKind::Staged,
+            ChangeKind::Unstaged,
+            ChangeKind::Untracked,
+        ],
+    };
+    if blocking.only(stashable) != blocking {
+        bail!(
+            "Skipped pull on branch {}, because it has local changes: {}",
+            branch,
+            blocking
+        );
+    }
+
+    let stash_before = get_stash_head(git, repo, path)?;
+    let stash_output = GitCommand::StashPush {
+        include_untracked: autostash == Autostash::All,
+    }
+    .run(git, repo, path, "")?;
+    if !stash_output.status.success() {
+        bail!(
+            "Unable to stash changes on branch {}, pull aborted! {}",
+            branch,
+            get_output_message(&stash_output)
+        );
+    }
+    // stashing might not have created a new entry, and popping in that case would restore some
+    // unrelated older stash. In a dry run, nothing was stashed, but the pop is still reported.
+    if !git.is_dry_run() && get_stash_head(git, repo, path)? == stash_before {
+        return GitCommand::Pull.run(git, repo, path, branch);
+    }
+
+    // the changes are restored even if the pull failed, timed out or was cancelled, and are only
+    // left in the stash if that is not possible
+    let pulled = GitCommand::Pull.run(git, repo, path, branch);
+    let pop_failure = match GitCommand::StashPop.run(git, repo, path, "") {
+        Ok(pop_output) if pop_output.status.success() => return pulled,
+        Ok(pop_output) => get_output_message(&pop_output),
+        Err(e) => e.to_string(),
+    };
+    let kept = format!(
+        "Unable to restore autostashed changes on branch {} after pulling, the changes are kept in stash@{{0}}! {}",
+        branch, pop_failure
+    );
+    match pulled {
+        Ok(_) => Err(eyre!(kept)),
+        Err(e) => Err(e.wrap_err(kept)),
+    }
+}
+
+/// Returns the commit the stash of the checkout at `path` currently points to, or `None` if there
+/// are no stash entries
+///
+/// # Arguments
+///
+/// * `git` - The `GitRunner` running the git commands
+/// * `repo` - The `Repo` being operated on
+/// * `path` - The path of the checkout being checked
+fn get_stash_head(git: &GitRunner, repo: &Repo, path: &str) -> Result<Option<String>> {
+    let output = GitCommand::StashHead.run(git, repo, path, "")?;
+    Ok(get_output_lines(output)?.into_iter().next())
 }
 
 /// Runs a `git push` operation, defined in GitCommand::run(...) and returns a `eyre::Result<Output>`
 ///
+/// Mirrors are never pushed to their `url`, but only with `--mirror` to their `push_mirror`, if
+/// they have one.
+///
 /// # Arguments
 ///
 /// * `repo` - The `Repo` being operated on
 fn run_push(task: &mut RepoTask) -> Result<Output> {
-    let push = |repo: &Repo, path: &str, branch: &str| GitCommand::Push.run(repo, path, branch);
-    run_operation_with_worktrees(task, push, "Push")
+    with_hooks(task, Hook::PrePush, Hook::PostPush, |task| {
+        if task.repo.is_mirror() {
+            if task.repo.push_mirror.is_none() {
+                task.update_state("Mirror has no push_mirror, skipping push".to_string());
+                return Ok(std::process::Command::new("echo")
+                    .arg(task.state.clone())
+                    .output()?);
+            }
+            return run_mirror_operation(task, GitCommand::PushMirror, "Push mirror");
+        }
+        if task.repo.revision.is_some() {
+            return revision::run_revision_push(task);
+        }
+        let push = |git: &GitRunner, repo: &Repo, path: &str, branch: &str| {
+            GitCommand::Push.run(git, repo, path, branch)
+        };
+        run_operation_with_worktrees(task, push, "Push", false)
+    })
 }
 
-/// Runs a `run_clone`, in case the repository has not been cloned yet, otherwise it runs `run_pull` and `run_push`, and returns a `eyre::Result<Output>` in either way
+/// Runs `command` in the root of a mirror repository, and returns a `eyre::Result<Output>`
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the mirror
+/// * `command` - The `GitCommand` being run
+/// * `op` - Name of the operation, needed for terminal output
+fn run_mirror_operation(task: &mut RepoTask, command: GitCommand, op: &str) -> Result<Output> {
+    task.start_phase(op, None);
+    let output = command.run(&task.git, &task.repo, &task.repo.path, "")?;
+    if !output.status.success() {
+        return Ok(output);
+    }
+    task.update_state(format!("{} complete!", op));
+    Ok(std::process::Command::new("echo")
+        .arg(task.state.clone())
+        .output()?)
+}
+
+/// Fetches the remote of the repo without changing any checkout, reports how far the branch of
+/// every checkout diverged from its remote branch, and returns a `eyre::Result<Output>`
 ///
 /// # Arguments
 ///
 /// * `repo` - The `Repo` being operated on
-fn run_sync(task: &mut RepoTask) -> Result<Output> {
-    run_clone(task)?;
-    run_pull(task)?;
-    run_push(task)?;
-    task.update_state(format!("{}", "Sync complete!".bright_green()));
+fn run_fetch(task: &mut RepoTask) -> Result<Output> {
+    if !Path::new(&task.repo.path).exists() {
+        bail!("Repo has not been cloned yet!");
+    }
+    if task.repo.is_mirror() {
+        return run_mirror_operation(task, GitCommand::RemoteUpdate, "Remote update");
+    }
+    task.start_phase("Fetch", None);
+    if task.repo.is_bare() {
+        track_remote_branches(task)?;
+    }
+    task.backend.fetch(&task.repo, &task.repo.path)?;
+    for entry in task
+        .backend
+        .worktrees(&task.repo, &task.repo.path)?
+        .into_iter()
+        .filter(|entry| !entry.is_bare)
+    {
+        let branch = match &entry.branch {
+            Some(branch) => branch,
+            None => continue,
+        };
+        match task.backend.ahead_behind(&task.repo, &entry.path, branch)? {
+            Some((0, 0)) => task.update_state(format!("{} is up to date with origin", branch)),
+            Some((ahead, behind)) => task.update_state(format!(
+                "{} at {}: {} ahead, {} behind origin",
+                branch, entry.path, ahead, behind
+            )),
+            None => task.update_state(format!("{} has no remote branch", branch)),
+        }
+    }
+    task.update_state("Fetch complete!".to_string());
     Ok(std::process::Command::new("echo")
-        .arg(&mut task.state.clone())
+        .arg(task.state.clone())
         .output()?)
 }
 
-/// Wrapper function for running processing a `eyre::Result<Output>` and printing to stdout
+/// Reports the local changes of every checkout of the repo, how far its branch diverged from the
+/// last fetched state of its remote branch, as well as submodules that are not initialised or out
+/// of date, if the repo manages submodules, and returns a `eyre::Result<Output>`
 ///
 /// # Arguments
 ///
-/// * `result` - The `Result<Output>` being processed
-fn process(result: Result<Output>) {
-    match result {
-        Ok(output) => {
-            if output.status.success() {
-                match std::str::from_utf8(&output.stdout) {
-                    Ok(stdout) => {
-                        if !stdout.is_empty() {
-                            println!("{} Output:\n{}", "Success!".bright_green(), stdout);
-                        } else {
-                            println!("{}", "Success!".bright_green());
-                        }
-                    }
-                    Err(e) => {
-                        println!(
-                            "{} Err: {:?}",
-                            "Unable to convert output to String!".bright_red(),
-                            e
-                        );
-                    }
+/// * `repo` - The `Repo` being operated on
+fn run_status(task: &mut RepoTask) -> Result<Output> {
+    if !Path::new(&task.repo.path).exists() {
+        bail!("Repo has not been cloned yet!");
+    }
+    for entry in task
+        .backend
+        .worktrees(&task.repo, &task.repo.path)?
+        .into_iter()
+        .filter(|entry| !entry.is_bare)
+    {
+        let status = task.backend.status(&task.repo, &entry.path)?;
+        let mut message = format!(
+            "{} at {}: {}",
+            entry.branch.as_deref().unwrap_or("(detached)"),
+            entry.path,
+            status
+        );
+        if let Some(branch) = &entry.branch {
+            match task.backend.ahead_behind(&task.repo, &entry.path, branch)? {
+                Some((0, 0)) | None => {}
+                Some((ahead, behind)) => {
+                    message.push_str(&format!(", {} ahead, {} behind origin", ahead, behind))
                 }
-            } else {
-                println!(
-                    "{} Output: {}",
-                    "Failure!".bright_red(),
-                    std::str::from_utf8(&output.stderr).unwrap_or("unknown error")
-                );
             }
         }
-        Err(report) => {
-            println!("{} Report: {:?}", "Error!".bright_red(), report);
+        if task.repo.submodules() != Submodules::None {
+            let output = GitCommand::SubmoduleStatus.run(&task.git, &task.repo, &entry.path, "")?;
+            for (submodule, problem) in
+                SubmoduleProblem::from_submodule_status(&get_output_lines(output)?)
+            {
+                message.push_str(&format!("\n  submodule {}: {}", submodule, problem));
+            }
+        }
+        task.update_state(message);
+        if let Some(drift) = revision::drift(&task.git, &task.repo, &entry.path)? {
+            task.report_problem(format!(
+                "Drifted from revision {}: {}",
+                task.repo.revision.as_deref().unwrap_or_default(),
+                drift
+            ));
         }
-    };
+    }
+    task.update_state("Status complete!".to_string());
+    Ok(std::process::Command::new("echo")
+        .arg(task.state.clone())
+        .output()?)
 }
 
-/// Checks whether the branch at `path` has unstaged changes and returns a `eyre::Result<bool>`
+/// Records the commit every checkout of the repo is at in `task.lock`, and returns a
+/// `eyre::Result<Output>`
 ///
-/// This is useful for doing `git pull` commands, where the operation needs to error out in that
-/// case.
+/// Mirrors have no checkout, so nothing is recorded for them.
 ///
 /// # Arguments
 ///
-/// * `repo` - Basically a dead argument, this is unfortunately needed for the GitCommand::run()
-/// method
-/// * `path` - The path to the branch being checked
-fn has_unstaged_changes(repo: &Repo, path: &str) -> Result<bool> {
-    Ok(!GitCommand::StatusPorcelain
-        .run(repo, path, "")?
-        .stdout
-        .is_empty())
+/// * `repo` - The `Repo` being operated on
+fn run_lock(task: &mut RepoTask) -> Result<Output> {
+    if !Path::new(&task.repo.path).exists() {
+        bail!("Repo has not been cloned yet!");
+    }
+    let mut checkouts = vec![];
+    for entry in task
+        .backend
+        .worktrees(&task.repo, &task.repo.path)?
+        .into_iter()
+        .filter(|entry| !entry.is_bare)
+    {
+        let output = GitCommand::VerifyRef.run(&task.git, &task.repo, &entry.path, "HEAD")?;
+        match get_output_lines(output)?.into_iter().next() {
+            Some(commit) => {
+                task.update_state(format!(
+                    "{} at {}: {}",
+                    entry.branch.as_deref().unwrap_or("(detached)"),
+                    entry.path,
+                    commit
+                ));
+                checkouts.push(LockedCheckout {
+                    path: entry.path,
+                    branch: entry.branch,
+                    commit,
+                });
+            }
+            None => {
+                task.report_problem(format!("Checkout at {} has no commit to lock", entry.path))
+            }
+        }
+    }
+    task.lock = Some(LockedRepo {
+        name: task.name.clone(),
+        url: task.repo.url.clone(),
+        checkouts,
+    });
+    task.update_state("Lock complete!".to_string());
+    Ok(std::process::Command::new("echo")
+        .arg(task.state.clone())
+        .output()?)
 }
 
-/// Parse an `Output.stdout` into a `Result<Vec<String>>` containing the lines out that stdout
+/// Brings every checkout of the repo to the commit recorded for it in `options.lockfile`, and
+/// returns a `eyre::Result<Output>`
+///
+/// Checkouts with local changes of a kind listed in the repo's `pull_blockers` are left alone, and
+/// commits that are missing locally are fetched first. Both are reported as problems if they stop
+/// a checkout from reaching its commit.
 ///
 /// # Arguments
 ///
-/// * `output` - The `Output` being processed
-fn get_output_lines(output: Output) -> Result<Vec<String>> {
-    Ok(String::from_utf8(output.stdout)?
-        .lines()
-        .map(|x| x.to_string())
-        .collect())
+/// * `repo` - The `Repo` being operated on
+/// * `locked` - Whether the commits of the lockfile are checked out, which is the only mode so far
+/// * `options` - The `Options` holding the lockfile
+fn run_checkout(task: &mut RepoTask, locked: bool, options: &Options) -> Result<Output> {
+    let lockfile = match &options.lockfile {
+        Some(lockfile) if locked => lockfile,
+        _ => bail!("Nothing to check out, checkout needs --locked!"),
+    };
+    let locked_repo = match lockfile.get(&task.name) {
+        Some(locked_repo) => locked_repo.clone(),
+        None => bail!("Repo is not in the lockfile, lock it with `repoteer lock` first!"),
+    };
+    if !Path::new(&task.repo.path).exists() {
+        bail!("Repo has not been cloned yet!");
+    }
+    let entries = task.backend.worktrees(&task.repo, &task.repo.path)?;
+    let mut fetched = false;
+    for checkout in locked_repo.checkouts.iter() {
+        if !entries.iter().any(|entry| entry.path == checkout.path) {
+            task.report_problem(format!(
+                "Checkout at {} does not exist anymore",
+                checkout.path
+            ));
+            continue;
+        }
+        task.start_phase("Checkout", checkout.branch.as_deref());
+        if let Err(e) = checkout_locked(task, checkout, &mut fetched) {
+            task.report_problem(e.to_string());
+        }
+    }
+    task.update_state("Checkout complete!".to_string());
+    Ok(std::process::Command::new("echo")
+        .arg(task.state.clone())
+        .output()?)
 }
 
-/// Checks whether the repository at `path` is a worktree repository
+/// Brings a single checkout to the commit it was locked at
+///
+/// If the locked branch still points to that commit, the branch is checked out, otherwise the
+/// commit is checked out on a detached HEAD, so that no branch loses commits.
 ///
 /// # Arguments
 ///
-/// * `path` - The path to the branch being checked
-fn has_worktrees(path: &str) -> Result<bool> {
-    let output = std::process::Command::new("git")
-        .args(["worktree", "list"])
-        .current_dir(path)
-        .output()?;
-    let output_lines: Vec<String> = get_output_lines(output)?;
-    Ok(!output_lines.is_empty() && output_lines[0].contains("(bare)"))
+/// * `task` - The `RepoTask` of the repo being operated on
+/// * `checkout` - The `LockedCheckout` being restored
+/// * `fetched` - Whether the repo has been fetched already, which is only done once per repo
+fn checkout_locked(
+    task: &mut RepoTask,
+    checkout: &LockedCheckout,
+    fetched: &mut bool,
+) -> Result<()> {
+    let (git, repo, path) = (&task.git, &task.repo, checkout.path.as_str());
+    let blocking = task.backend.status(repo, path)?.only(&repo.pull_blockers());
+    if !blocking.is_clean() {
+        bail!(
+            "Skipped checkout at {}, because it has local changes: {}",
+            path,
+            blocking
+        );
+    }
+    let exists = |commit: &str| -> Result<bool> {
+        Ok(GitCommand::CommitExists
+            .run(git, repo, path, commit)?
+            .status
+            .success())
+    };
+    if !exists(&checkout.commit)? && !*fetched {
+        *fetched = true;
+        task.backend.fetch(repo, &repo.path)?;
+    }
+    if !exists(&checkout.commit)? {
+        bail!(
+            "Commit {} is missing at {}, even after fetching",
+            checkout.commit,
+            path
+        );
+    }
+    let resolve = |reference: &str| -> Result<Option<String>> {
+        let output = GitCommand::VerifyRef.run(git, repo, path, reference)?;
+        Ok(get_output_lines(output)?.into_iter().next())
+    };
+    let branch_at_commit = match &checkout.branch {
+        Some(branch) => {
+            resolve(&format!("refs/heads/{}", branch))?.as_ref() == Some(&checkout.commit)
+        }
+        None => false,
+    };
+    let current_branch = GitCommand::CurrentBranch.run(git, repo, path, "")?;
+    let current_branch = String::from_utf8_lossy(&current_branch.stdout)
+        .trim()
+        .to_string();
+    let head = resolve("HEAD")?;
+    let at_commit = head.as_ref() == Some(&checkout.commit)
+        && (!branch_at_commit || checkout.branch.as_deref() == Some(current_branch.as_str()));
+    if at_commit {
+        task.update_state(format!("{} is already at {}", path, checkout.commit));
+        return Ok(());
+    }
+    let output = match (&checkout.branch, branch_at_commit) {
+        (Some(branch), true) => GitCommand::Checkout.run(git, repo, path, branch)?,
+        _ => GitCommand::CheckoutDetach.run(git, repo, path, &checkout.commit)?,
+    };
+    if !output.status.success() {
+        bail!(
+            "Unable to check out {} at {}: {}",
+            checkout.commit,
+            path,
+            get_output_message(&output)
+        );
+    }
+    task.update_state(format!("checked out {} at {}", checkout.commit, path));
+    Ok(())
 }
 
-/// Checks the repository at `path`, and returns a `Result<Vec<String>>` containing the different
-/// branch names that have been checked out.
+/// Runs a `run_clone`, in case the repository has not been cloned yet, otherwise it runs `run_pull` and `run_push`, and returns a `eyre::Result<Output>` in either way
 ///
-/// Only works for non-bare repositories.
+/// Missing worktrees are created in either case, and if `options.prune` is set, worktrees of
+/// branches that were deleted upstream are removed before pulling.
 ///
 /// # Arguments
 ///
-/// * `path` - The path to the branch being checked
-fn get_branches(path: &str) -> Result<Vec<String>> {
-    let output = std::process::Command::new("git")
-        .arg("branch")
-        .current_dir(path)
-        .output()?;
-    Ok(get_output_lines(output)?
-        .into_iter()
-        .map(|mut line| line.split_off(2))
-        .collect())
+/// * `repo` - The `Repo` being operated on
+/// * `options` - The `Options` modifying how the sync is run
+fn run_sync(task: &mut RepoTask, options: Options) -> Result<Output> {
+    with_hooks(task, Hook::PreSync, Hook::PostSync, |task| {
+        if Path::new(&task.repo.path).exists() {
+            add_missing_worktrees(task)?;
+        } else {
+            let output = run_clone(task)?;
+            // in a dry run, there is no clone to pull or push
+            if !output.status.success() || task.git.is_dry_run() {
+                return Ok(output);
+            }
+        }
+        if options.prune {
+            prune_worktrees(task)?;
+        }
+        let output = run_pull(task, options)?;
+        if task.repo.is_mirror() && !output.status.success() {
+            return Ok(output);
+        }
+        let output = run_push(task)?;
+        if task.repo.is_mirror() && !output.status.success() {
+            return Ok(output);
+        }
+        task.update_state("Sync complete!".to_string());
+        Ok(std::process::Command::new("echo")
+            .arg(task.state.clone())
+            .output()?)
+    })
 }
 
-/// Checks the repository at `path`, and returns a `Result<Vec<String>>` containing the different
-/// worktree names that have been checked out.
+/// Runs the `pre` hook, `operation` and then the `post` hook on the repo of `task`
+///
+/// The `post` hook only runs if `operation` succeeded.
 ///
 /// # Arguments
 ///
-/// * `path` - The path to the branch being checked
-fn get_worktrees(path: &str) -> Result<Vec<String>> {
-    let output = std::process::Command::new("git")
-        .args(["worktree", "list"])
-        .current_dir(path)
-        .output()?;
-    Ok(get_output_lines(output)?
-        .into_iter()
-        .filter(|line| line.ends_with(']'))
-        .map(|line| {
-            line.split_whitespace()
-                .filter(|word| word.starts_with('[') && word.ends_with(']'))
-                .map(|word| word.strip_prefix('[').unwrap_or(word))
-                .map(|word| word.strip_suffix(']').unwrap_or(word))
-                .collect()
-        })
+/// * `task` - The `RepoTask` of the repo being operated on
+/// * `pre` - The `Hook` that runs before `operation`
+/// * `post` - The `Hook` that runs after `operation`
+/// * `operation` - The operation being run on the repo
+fn with_hooks<F>(task: &mut RepoTask, pre: Hook, post: Hook, operation: F) -> Result<Output>
+where
+    F: FnOnce(&mut RepoTask) -> Result<Output>,
+{
+    task.run_hook(pre)?;
+    let output = operation(task)?;
+    if output.status.success() {
+        task.run_hook(post)?;
+    }
+    Ok(output)
+}
+
+/// Wrapper function for processing the `eyre::Result<Output>` of a command into its `Outcome`
+///
+/// # Arguments
+///
+/// * `result` - The `Result<Output>` being processed
+fn process(result: Result<Output>) -> Outcome {
+    match result {
+        Ok(output) if output.status.success() => Outcome::Success,
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            match ErrorKind::classify(&stderr) {
+                ErrorKind::Authentication => Outcome::AuthFailed(stderr),
+                _ => Outcome::Failure(stderr),
+            }
+        }
+        Err(report) => match report.downcast_ref::<Interruption>() {
+            Some(interruption) => (*interruption).into(),
+            // backends report failed git commands as errors, carrying git's stderr
+            None => match ErrorKind::classify(&report.to_string()) {
+                ErrorKind::Authentication => Outcome::AuthFailed(report.to_string()),
+                _ => Outcome::Error(report.to_string()),
+            },
+        },
+    }
+}
+
+impl From<Interruption> for Outcome {
+    fn from(interruption: Interruption) -> Self {
+        match interruption {
+            Interruption::Cancelled => Outcome::Cancelled,
+            _ => Outcome::TimedOut(interruption.to_string()),
+        }
+    }
+}
+
+/// Parse an `Output.stdout` into a `Result<Vec<String>>` containing the lines out that stdout
+///
+/// # Arguments
+///
+/// * `output` - The `Output` being processed
+fn get_output_lines(output: Output) -> Result<Vec<String>> {
+    Ok(String::from_utf8(output.stdout)?
+        .lines()
+        .map(|x| x.to_string())
         .collect())
 }
 
-/// Checks the repository at `path` and returns a `Result<String>` containing the name of the
-/// current branch
+/// Returns the trimmed stderr and stdout of `output` as a single message
+///
+/// Git is not consistent about where it reports errors, e.g. conflicts are printed to stdout.
 ///
 /// # Arguments
 ///
-/// * `path` - The path to the branch being checked
-fn get_current_branch(path: &str) -> Result<String> {
-    Ok(String::from_utf8(
-        std::process::Command::new("git")
-            .args(["branch", "--show-current"])
-            .current_dir(path)
-            .output()?
-            .stdout,
-    )?)
+/// * `output` - The `Output` being processed
+fn get_output_message(output: &Output) -> String {
+    [&output.stderr, &output.stdout]
+        .iter()
+        .map(|bytes| String::from_utf8_lossy(bytes).trim().to_string())
+        .filter(|message| !message.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 /// Wrapper function for git operations where the semantics of the git commands change depending on
 /// whether the local repository is bare / has worktrees or not
 ///
+/// `f` is run for the branch checked out in each worktree, in the actual location of the worktree
+/// as reported by git, so other local branches are left alone. Worktrees on a detached HEAD are
+/// skipped. The root of a bare repository is only ever fetched, if `fetch_bare_root` is set.
+///
 /// # Arguments
 ///
 /// * `repo` - The `Repo` being processed
 /// * `f` - The function being run
 /// * `op` - Name of the operation, needed for terminal output
-fn run_operation_with_worktrees<F>(task: &mut RepoTask, f: F, op: &str) -> Result<Output>
+/// * `fetch_bare_root` - Whether to run `git fetch` in the root of a bare repository
+fn run_operation_with_worktrees<F>(
+    task: &mut RepoTask,
+    f: F,
+    op: &str,
+    fetch_bare_root: bool,
+) -> Result<Output>
 where
-    F: Fn(&Repo, &str, &str) -> Result<Output>,
+    F: Fn(&GitRunner, &Repo, &str, &str) -> Result<Output>,
 {
-    let has_worktrees = has_worktrees(&task.repo.path)?;
-    let branches = if has_worktrees {
-        get_worktrees(&task.repo.path)?
-    } else {
-        get_branches(&task.repo.path)?
-    };
-    for branch in branches.into_iter() {
-        task.update_state(format!(
-            "running operation {} on branch {}",
-            op.cyan(),
-            branch.cyan()
-        ));
-        task.print_state();
-        let path = if has_worktrees {
-            format!("{}/{}", &task.repo.path, &branch)
-        } else {
-            (&task.repo.path).to_string()
-        };
-        match f(&task.repo, &path, &branch) {
-            Ok(_) => {}
-            Err(e) => {
-                task.update_state(format!("   Error! Report: {}", e));
-                task.print_state();
+    let entries = task.backend.worktrees(&task.repo, &task.repo.path)?;
+    let is_bare = entries.first().is_some_and(|e| e.is_bare);
+    if is_bare && fetch_bare_root {
+        task.start_phase("Fetch", None);
+        track_remote_branches(task)?;
+        if let Err(e) = task.backend.fetch(&task.repo, &task.repo.path) {
+            task.report_problem(e.to_string());
+        }
+    }
+    // the bare root has no branch checked out, and every other entry is a checkout whose branch is
+    // only ever operated on where it is checked out
+    let targets: Vec<(String, String)> = entries
+        .into_iter()
+        .skip(if is_bare { 1 } else { 0 })
+        .filter_map(|entry| match entry.branch {
+            Some(branch) => Some((entry.path, branch)),
+            None => {
+                task.update_state(format!(
+                    "skipping worktree at {}, because it has no branch checked out",
+                    entry.path
+                ));
+                None
             }
-        };
+        })
+        .collect();
+    for (path, branch) in targets.into_iter() {
+        task.start_phase(op, Some(&branch));
+        report_failure(task, f(&task.git, &task.repo, &path, &branch));
     }
     task.update_state(format!("{} complete!", op));
     Ok(std::process::Command::new("echo")
-        .arg(&mut task.state.clone())
+        .arg(task.state.clone())
         .output()?)
 }
+
+/// Configures the bare repository of `task` to fetch the branches of `origin` into
+/// `refs/remotes/origin`, unless it has a fetch refspec already
+///
+/// `git clone --bare` does not write a fetch refspec, so `git fetch origin` would only update
+/// `FETCH_HEAD`. Without the remote-tracking branches, worktrees of branches created upstream after
+/// the clone could not be added, and the worktrees would never be reported as behind.
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the bare repository
+fn track_remote_branches(task: &mut RepoTask) -> Result<()> {
+    // in a dry run, the repo may not have been cloned
+    if Path::new(&task.repo.path).exists() {
+        let output = GitCommand::FetchRefspecs.run(&task.git, &task.repo, &task.repo.path, "")?;
+        if !get_output_lines(output)?.is_empty() {
+            return Ok(());
+        }
+    }
+    let result = GitCommand::ConfigSet {
+        key: "remote.origin.fetch".to_string(),
+        value: "+refs/heads/*:refs/remotes/origin/*".to_string(),
+    }
+    .run(&task.git, &task.repo, &task.repo.path, "");
+    report_failure(task, result);
+    Ok(())
+}
+
+/// Reports the error or the output of a failed git command as a problem of `task`
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` the command was run for
+/// * `result` - The `Result<Output>` of the git command
+fn report_failure(task: &mut RepoTask, result: Result<Output>) {
+    match result {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => task.report_problem(get_output_message(&output)),
+        Err(e) => task.report_problem(e.to_string()),
+    }
+}
+
+/// Creates the worktrees declared for the repo that have not been checked out yet
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the bare repository
+fn add_missing_worktrees(task: &mut RepoTask) -> Result<()> {
+    let declared = match &task.repo.worktrees {
+        Some(worktrees) if task.repo.is_bare() => worktrees.clone(),
+        _ => return Ok(()),
+    };
+    // in a dry run, the repo may not have been cloned
+    let existing = if Path::new(&task.repo.path).exists() {
+        task.backend.worktrees(&task.repo, &task.repo.path)?
+    } else {
+        vec![]
+    };
+    let missing: Vec<_> = declared
+        .iter()
+        .filter(|w| {
+            !existing
+                .iter()
+                .any(|entry| entry.branch.as_deref() == Some(w.branch()))
+        })
+        .collect();
+    // branches created upstream since the last fetch can only be checked out after fetching them
+    if !existing.is_empty() && !missing.iter().all(|w| knows_branch(task, w.branch())) {
+        track_remote_branches(task)?;
+        if let Err(e) = task.backend.fetch(&task.repo, &task.repo.path) {
+            task.report_problem(e.to_string());
+        }
+    }
+    for worktree in missing {
+        let worktree_path = worktree.path(&task.repo.path).to_string_lossy().to_string();
+        let output = GitCommand::WorktreeAdd.run(
+            &task.git,
+            &task.repo,
+            &worktree_path,
+            worktree.branch(),
+        )?;
+        if output.status.success() && task.repo.submodules() != Submodules::None {
+            let result = GitCommand::SubmoduleUpdate.run(&task.git, &task.repo, &worktree_path, "");
+            report_failure(task, result);
+        }
+        if output.status.success() {
+            task.update_state(format!(
+                "added worktree for branch {} at {}",
+                worktree.branch(),
+                worktree_path
+            ));
+        } else {
+            task.report_problem(format!(
+                "Unable to add worktree for branch {}: {}",
+                worktree.branch(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether the bare repository of `task` has `branch` as a local or as a remote-tracking branch
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the bare repository
+/// * `branch` - The name of the branch
+fn knows_branch(task: &RepoTask, branch: &str) -> bool {
+    [
+        format!("refs/heads/{}", branch),
+        format!("refs/remotes/origin/{}", branch),
+    ]
+    .iter()
+    .any(|reference| {
+        GitCommand::VerifyRef
+            .run(&task.git, &task.repo, &task.repo.path, reference)
+            .is_ok_and(|output| output.status.success())
+    })
+}
+
+/// Removes the worktrees of a bare repository whose branches were deleted upstream
+///
+/// Only branches that tracked an upstream branch, which is gone now, are pruned. Worktrees of
+/// branches that were never pushed, that hold commits no remote branch contains, or that have local
+/// changes are kept, and reported instead.
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the bare repository
+fn prune_worktrees(task: &mut RepoTask) -> Result<()> {
+    if task.repo.is_mirror() {
+        return Ok(());
+    }
+    let entries = task.backend.worktrees(&task.repo, &task.repo.path)?;
+    if !entries.first().is_some_and(|e| e.is_bare) {
+        return Ok(());
+    }
+    let remote_branches = task.backend.remote_branches(&task.repo, &task.repo.path)?;
+    for (worktree_path, branch) in entries
+        .into_iter()
+        .filter_map(|entry| entry.branch.map(|branch| (entry.path, branch)))
+        .filter(|(_, branch)| !remote_branches.contains(branch))
+    {
+        // prune runs before fetching, so the remote-tracking branch of a deleted branch is still
+        // around, if the branch was ever fetched
+        if !had_upstream(task, &branch)? {
+            continue;
+        }
+        let unpushed = get_output_lines(GitCommand::UnpushedCount.run(
+            &task.git,
+            &task.repo,
+            &task.repo.path,
+            &branch,
+        )?)?;
+        let unpushed = unpushed.first().map_or("0", |count| count.trim());
+        if unpushed != "0" {
+            task.update_state(format!(
+                "kept worktree for deleted branch {}, because it has {} commit(s) on no remote branch",
+                branch, unpushed
+            ));
+            continue;
+        }
+        let status = task.backend.status(&task.repo, &worktree_path)?;
+        if !status.is_clean() {
+            task.update_state(format!(
+                "kept worktree for deleted branch {}, because it has local changes: {}",
+                branch, status
+            ));
+            continue;
+        }
+        let output = GitCommand::WorktreeRemove.run(&task.git, &task.repo, &worktree_path, "")?;
+        if output.status.success() {
+            task.update_state(format!("pruned worktree for deleted branch {}", branch));
+        } else {
+            task.report_problem(format!(
+                "Unable to prune worktree for branch {}: {}",
+                branch,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `branch` tracked an upstream branch, either configured as `branch.<branch>.merge` or
+/// seen as `refs/remotes/origin/<branch>` by an earlier fetch
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the bare repository
+/// * `branch` - The name of the local branch
+fn had_upstream(task: &RepoTask, branch: &str) -> Result<bool> {
+    let merge = GitCommand::UpstreamMerge.run(&task.git, &task.repo, &task.repo.path, branch)?;
+    if merge.status.success() {
+        return Ok(true);
+    }
+    let tracking = GitCommand::VerifyRef.run(
+        &task.git,
+        &task.repo,
+        &task.repo.path,
+        &format!("refs/remotes/origin/{}", branch),
+    )?;
+    Ok(tracking.status.success())
+}
@@ -0,0 +1,299 @@
+use color_eyre::Result;
+use std::sync::Arc;
+
+use super::{status::DirtyStatus, GitRunner};
+use crate::manifest::repo::Repo;
+
+pub use self::cli::CliBackend;
+#[cfg(feature = "libgit2")]
+pub use self::libgit2::Libgit2Backend;
+
+mod cli;
+#[cfg(feature = "libgit2")]
+mod libgit2;
+
+/// Abstracts the git interactions that repoteer needs to query and update repositories
+///
+/// Operations that change the checkouts, like pulls, pushes and stashes, are always run through
+/// the git CLI, so that they behave exactly like they would when run by hand.
+pub trait GitBackend: Send + Sync {
+    /// Clones `repo` to `repo.path`, honouring its clone options
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being cloned
+    fn clone_repo(&self, repo: &Repo) -> Result<()>;
+
+    /// Fetches the remote `origin` of the repository at `path`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `path` - The path to the repository being fetched
+    fn fetch(&self, repo: &Repo, path: &str) -> Result<()>;
+
+    /// Returns the local changes of the checkout at `path`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `path` - The path to the checkout being checked
+    fn status(&self, repo: &Repo, path: &str) -> Result<DirtyStatus>;
+
+    /// Returns the names of the local branches of the repository at `path`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `path` - The path to the repository being checked
+    fn branches(&self, repo: &Repo, path: &str) -> Result<Vec<String>>;
+
+    /// Returns all worktrees of the repository at `path`, with the main worktree or bare root as
+    /// the first entry
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `path` - The path to the repository being checked
+    fn worktrees(&self, repo: &Repo, path: &str) -> Result<Vec<WorktreeEntry>>;
+
+    /// Returns the names of the branches that exist on the remote `origin`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `path` - The path to the repository being checked
+    fn remote_branches(&self, repo: &Repo, path: &str) -> Result<Vec<String>>;
+
+    /// Returns how many commits `branch` is ahead of and behind `origin/<branch>`, or `None` if
+    /// there is no such remote tracking branch
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `path` - The path to the repository being checked
+    /// * `branch` - The local branch being compared
+    fn ahead_behind(&self, repo: &Repo, path: &str, branch: &str)
+        -> Result<Option<(usize, usize)>>;
+}
+
+/// A single worktree of a repository
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WorktreeEntry {
+    /// Location of the worktree on the filesystem
+    pub path: String,
+
+    /// The branch checked out in the worktree, `None` for bare and detached worktrees
+    pub branch: Option<String>,
+
+    /// Whether this entry is the root of a bare repository
+    pub is_bare: bool,
+}
+
+/// Enumerates the available `GitBackend` implementations
+#[derive(clap::ValueEnum, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Runs the git CLI and parses its output
+    #[default]
+    Cli,
+
+    /// Uses libgit2 in-process, and falls back to the git CLI where libgit2 cannot be used
+    #[cfg(feature = "libgit2")]
+    Libgit2,
+}
+
+impl BackendKind {
+    /// Builds the `GitBackend` declared by Self
+    ///
+    /// # Arguments
+    ///
+    /// * `git` - The `GitRunner` used for running the git CLI
+    pub fn build(self, git: GitRunner) -> Arc<dyn GitBackend> {
+        match self {
+            BackendKind::Cli => Arc::new(CliBackend::new(git)),
+            #[cfg(feature = "libgit2")]
+            BackendKind::Libgit2 => Arc::new(Libgit2Backend::new(CliBackend::new(git))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path, process::Command};
+    use tempfile::TempDir;
+
+    /// Runs git with a fixed identity in `dir`, and panics if it fails
+    fn git(dir: &Path, args: &[&str]) {
+        let output = Command::new("git")
+            .args([
+                "-c",
+                "user.name=repoteer",
+                "-c",
+                "user.email=repoteer@localhost",
+            ])
+            .args(["-c", "init.defaultBranch=main"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    /// Creates a bare remote with the branches `main` and `dev`, and returns the temporary
+    /// directory containing it, along with the `file://` URL of the remote
+    fn setup_remote() -> (TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let remote = dir.path().join("remote.git");
+        let work = dir.path().join("work");
+        git(dir.path(), &["init", "--bare", remote.to_str().unwrap()]);
+        git(dir.path(), &["init", work.to_str().unwrap()]);
+        fs::write(work.join("a.txt"), "a\n").unwrap();
+        git(&work, &["add", "a.txt"]);
+        git(&work, &["commit", "-m", "initial"]);
+        git(
+            &work,
+            &["remote", "add", "origin", remote.to_str().unwrap()],
+        );
+        git(&work, &["push", "origin", "main", "main:dev"]);
+        let url = format!("file://{}", remote.display());
+        (dir, url)
+    }
+
+    fn repo(url: &str, path: &Path) -> Repo {
+        Repo {
+            url: url.to_string(),
+            path: path.to_string_lossy().to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn check_clone_and_queries(backend: &dyn GitBackend) {
+        let (dir, url) = setup_remote();
+        let repo = repo(&url, &dir.path().join("clone"));
+        backend.clone_repo(&repo).unwrap();
+
+        assert_eq!(
+            backend.branches(&repo, &repo.path).unwrap(),
+            vec!["main".to_string()]
+        );
+        let mut remote_branches = backend.remote_branches(&repo, &repo.path).unwrap();
+        remote_branches.sort();
+        assert_eq!(remote_branches, vec!["dev".to_string(), "main".to_string()]);
+        let worktrees = backend.worktrees(&repo, &repo.path).unwrap();
+        assert_eq!(worktrees.len(), 1);
+        assert_eq!(worktrees[0].branch.as_deref(), Some("main"));
+        assert!(!worktrees[0].is_bare);
+        assert!(backend.status(&repo, &repo.path).unwrap().is_clean());
+        assert_eq!(
+            backend.ahead_behind(&repo, &repo.path, "main").unwrap(),
+            Some((0, 0))
+        );
+        assert_eq!(
+            backend.ahead_behind(&repo, &repo.path, "nope").unwrap(),
+            None
+        );
+    }
+
+    fn check_status(backend: &dyn GitBackend) {
+        let (dir, url) = setup_remote();
+        let repo = repo(&url, &dir.path().join("clone"));
+        backend.clone_repo(&repo).unwrap();
+        let clone = Path::new(&repo.path);
+        fs::write(clone.join("a.txt"), "changed\n").unwrap();
+        fs::write(clone.join("b.txt"), "staged\n").unwrap();
+        git(clone, &["add", "b.txt"]);
+        fs::write(clone.join("c.txt"), "untracked\n").unwrap();
+        assert_eq!(
+            backend.status(&repo, &repo.path).unwrap(),
+            DirtyStatus {
+                staged: 1,
+                unstaged: 1,
+                untracked: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    fn check_fetch_and_ahead_behind(backend: &dyn GitBackend) {
+        let (dir, url) = setup_remote();
+        let repo = repo(&url, &dir.path().join("clone"));
+        backend.clone_repo(&repo).unwrap();
+        let work = dir.path().join("work");
+        fs::write(work.join("a.txt"), "upstream\n").unwrap();
+        git(&work, &["commit", "-am", "upstream"]);
+        git(&work, &["push", "origin", "main"]);
+        let clone = Path::new(&repo.path);
+        fs::write(clone.join("d.txt"), "local\n").unwrap();
+        git(clone, &["add", "d.txt"]);
+        git(clone, &["commit", "-m", "local"]);
+
+        backend.fetch(&repo, &repo.path).unwrap();
+        assert_eq!(
+            backend.ahead_behind(&repo, &repo.path, "main").unwrap(),
+            Some((1, 1))
+        );
+    }
+
+    fn check_bare_worktrees(backend: &dyn GitBackend) {
+        let (dir, url) = setup_remote();
+        let mut repo = repo(&url, &dir.path().join("bare"));
+        repo.is_bare = Some(true);
+        backend.clone_repo(&repo).unwrap();
+        let bare = Path::new(&repo.path);
+        let elsewhere = dir.path().join("elsewhere");
+        git(
+            bare,
+            &["worktree", "add", elsewhere.to_str().unwrap(), "dev"],
+        );
+
+        let worktrees = backend.worktrees(&repo, &repo.path).unwrap();
+        assert_eq!(worktrees.len(), 2);
+        assert!(worktrees[0].is_bare);
+        assert_eq!(worktrees[1].branch.as_deref(), Some("dev"));
+        assert_eq!(
+            fs::canonicalize(&worktrees[1].path).unwrap(),
+            fs::canonicalize(&elsewhere).unwrap()
+        );
+        let mut branches = backend.branches(&repo, &repo.path).unwrap();
+        branches.sort();
+        assert_eq!(branches, vec!["dev".to_string(), "main".to_string()]);
+    }
+
+    macro_rules! backend_tests {
+        ($name:ident, $backend:expr) => {
+            mod $name {
+                use super::*;
+
+                #[test]
+                fn clone_and_queries() {
+                    check_clone_and_queries(&$backend);
+                }
+
+                #[test]
+                fn status() {
+                    check_status(&$backend);
+                }
+
+                #[test]
+                fn fetch_and_ahead_behind() {
+                    check_fetch_and_ahead_behind(&$backend);
+                }
+
+                #[test]
+                fn bare_worktrees() {
+                    check_bare_worktrees(&$backend);
+                }
+            }
+        };
+    }
+
+    backend_tests!(cli, CliBackend::default());
+    #[cfg(feature = "libgit2")]
+    backend_tests!(libgit2, Libgit2Backend::default());
+}
@@ -0,0 +1,244 @@
+use color_eyre::{eyre::bail, Result};
+use std::time::Instant;
+
+use super::{GitBackend, WorktreeEntry};
+use crate::{
+    manifest::repo::Repo,
+    operations::{
+        get_output_lines, get_output_message, interrupt::Interruption, status::DirtyStatus,
+        GitCommand, GitRunner,
+    },
+};
+
+/// `GitBackend` that runs the git CLI and parses its output
+#[derive(Clone, Default)]
+pub struct CliBackend {
+    git: GitRunner,
+}
+
+impl CliBackend {
+    pub fn new(git: GitRunner) -> Self {
+        CliBackend { git }
+    }
+
+    /// Whether the `GitRunner` of this backend only reports commands that change repositories
+    pub fn is_dry_run(&self) -> bool {
+        self.git.is_dry_run()
+    }
+
+    /// Returns the `Interruption` that stops the commands of `repo` at this point, if there is one
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `started` - When the current operation was started
+    pub fn interruption(&self, repo: &Repo, started: Instant) -> Option<Interruption> {
+        self.git.interruption(repo, started)
+    }
+
+    /// Runs `transfer` in place of the git command `argv`, see `GitRunner::transfer`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    /// * `argv` - The git command `transfer` stands in for, starting with `git`
+    /// * `cwd` - The directory `transfer` operates on
+    /// * `transfer` - Runs the transfer once
+    pub fn transfer(
+        &self,
+        repo: &Repo,
+        argv: &[&str],
+        cwd: &str,
+        transfer: impl FnMut() -> Result<()>,
+    ) -> Result<()> {
+        let argv = argv.iter().map(|arg| arg.to_string()).collect();
+        self.git.transfer(repo, argv, cwd, transfer)
+    }
+}
+
+impl GitBackend for CliBackend {
+    fn clone_repo(&self, repo: &Repo) -> Result<()> {
+        let output = GitCommand::Clone.run(&self.git, repo, &repo.path, "")?;
+        if !output.status.success() {
+            bail!("Unable to clone: {}", get_output_message(&output));
+        }
+        Ok(())
+    }
+
+    fn fetch(&self, repo: &Repo, path: &str) -> Result<()> {
+        let output = GitCommand::Fetch.run(&self.git, repo, path, "")?;
+        if !output.status.success() {
+            bail!("Unable to fetch: {}", get_output_message(&output));
+        }
+        Ok(())
+    }
+
+    fn status(&self, repo: &Repo, path: &str) -> Result<DirtyStatus> {
+        let output = GitCommand::StatusPorcelain.run(&self.git, repo, path, "")?;
+        if !output.status.success() {
+            bail!(
+                "Unable to check the status of {}: {}",
+                path,
+                get_output_message(&output)
+            );
+        }
+        Ok(DirtyStatus::from_porcelain_v2(&get_output_lines(output)?))
+    }
+
+    fn branches(&self, repo: &Repo, path: &str) -> Result<Vec<String>> {
+        let output = GitCommand::BranchList.run(&self.git, repo, path, "")?;
+        if !output.status.success() {
+            bail!("Unable to list branches: {}", get_output_message(&output));
+        }
+        get_output_lines(output)
+    }
+
+    fn worktrees(&self, repo: &Repo, path: &str) -> Result<Vec<WorktreeEntry>> {
+        let output = GitCommand::WorktreeList.run(&self.git, repo, path, "")?;
+        if !output.status.success() {
+            bail!("Unable to list worktrees: {}", get_output_message(&output));
+        }
+        Ok(parse_worktree_list(&get_output_lines(output)?))
+    }
+
+    fn remote_branches(&self, repo: &Repo, path: &str) -> Result<Vec<String>> {
+        let output = GitCommand::LsRemoteHeads.run(&self.git, repo, path, "")?;
+        if !output.status.success() {
+            bail!(
+                "Unable to list remote branches: {}",
+                get_output_message(&output)
+            );
+        }
+        Ok(get_output_lines(output)?
+            .into_iter()
+            .filter_map(|line| {
+                line.split_whitespace()
+                    .nth(1)
+                    .and_then(|r| r.strip_prefix("refs/heads/"))
+                    .map(|b| b.to_string())
+            })
+            .collect())
+    }
+
+    fn ahead_behind(
+        &self,
+        repo: &Repo,
+        path: &str,
+        branch: &str,
+    ) -> Result<Option<(usize, usize)>> {
+        let upstream = format!("refs/remotes/origin/{}", branch);
+        let local = format!("refs/heads/{}", branch);
+        for reference in [&upstream, &local] {
+            if !GitCommand::VerifyRef
+                .run(&self.git, repo, path, reference)?
+                .status
+                .success()
+            {
+                return Ok(None);
+            }
+        }
+        let output = GitCommand::AheadBehind.run(&self.git, repo, path, branch)?;
+        if !output.status.success() {
+            bail!(
+                "Unable to compare {} with its upstream: {}",
+                branch,
+                get_output_message(&output)
+            );
+        }
+        let counts: Vec<usize> = get_output_lines(output)?
+            .first()
+            .map(|line| {
+                line.split_whitespace()
+                    .filter_map(|count| count.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        match counts[..] {
+            [ahead, behind] => Ok(Some((ahead, behind))),
+            _ => bail!("Unable to parse the ahead/behind counts of {}", branch),
+        }
+    }
+}
+
+/// Parses the lines of `git worktree list --porcelain` into a `Vec<WorktreeEntry>`
+///
+/// # Arguments
+///
+/// * `lines` - The lines of the porcelain output
+fn parse_worktree_list(lines: &[String]) -> Vec<WorktreeEntry> {
+    let mut entries: Vec<WorktreeEntry> = vec![];
+    for line in lines.iter() {
+        if let Some(path) = line.strip_prefix("worktree ") {
+            entries.push(WorktreeEntry {
+                path: path.to_string(),
+                branch: None,
+                is_bare: false,
+            });
+        } else if let Some(entry) = entries.last_mut() {
+            if let Some(branch) = line.strip_prefix("branch ") {
+                entry.branch = Some(
+                    branch
+                        .strip_prefix("refs/heads/")
+                        .unwrap_or(branch)
+                        .to_string(),
+                );
+            } else if line == "bare" {
+                entry.is_bare = true;
+            }
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_worktree_list_bare() {
+        let lines: Vec<String> = [
+            "worktree /home/foo/repo",
+            "bare",
+            "",
+            "worktree /home/foo/repo/main",
+            "HEAD 3f2c1e0a9b8d7c6e5f4a3b2c1d0e9f8a7b6c5d4e",
+            "branch refs/heads/main",
+            "",
+            "worktree /somewhere/else/feature",
+            "HEAD 3f2c1e0a9b8d7c6e5f4a3b2c1d0e9f8a7b6c5d4e",
+            "branch refs/heads/feature/foo",
+            "",
+            "worktree /home/foo/repo/detached",
+            "HEAD 3f2c1e0a9b8d7c6e5f4a3b2c1d0e9f8a7b6c5d4e",
+            "detached",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        assert_eq!(
+            parse_worktree_list(&lines),
+            vec![
+                WorktreeEntry {
+                    path: "/home/foo/repo".to_string(),
+                    branch: None,
+                    is_bare: true,
+                },
+                WorktreeEntry {
+                    path: "/home/foo/repo/main".to_string(),
+                    branch: Some("main".to_string()),
+                    is_bare: false,
+                },
+                WorktreeEntry {
+                    path: "/somewhere/else/feature".to_string(),
+                    branch: Some("feature/foo".to_string()),
+                    is_bare: false,
+                },
+                WorktreeEntry {
+                    path: "/home/foo/repo/detached".to_string(),
+                    branch: None,
+                    is_bare: false,
+                },
+            ]
+        );
+    }
+}
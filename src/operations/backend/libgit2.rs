@@ -0,0 +1,339 @@
+use color_eyre::{eyre::eyre, Report, Result};
+use git2::{
+    build::RepoBuilder, BranchType, Config, Cred, CredentialType, Direction, ErrorClass, ErrorCode,
+    FetchOptions, RemoteCallbacks, Repository, Status, StatusOptions,
+};
+use std::{path::Path, time::Instant};
+
+use super::{CliBackend, GitBackend, WorktreeEntry};
+use crate::{
+    manifest::repo::{Repo, Submodules},
+    operations::status::DirtyStatus,
+};
+
+/// `GitBackend` that uses libgit2 in-process
+///
+/// libgit2 is built without ssh support, and does not know about all the clone options git
+/// supports, so those cases are handed to the `CliBackend`.
+#[derive(Clone, Default)]
+pub struct Libgit2Backend {
+    cli: CliBackend,
+}
+
+impl Libgit2Backend {
+    pub fn new(cli: CliBackend) -> Self {
+        Libgit2Backend { cli }
+    }
+
+    /// Whether network operations for `repo` have to go through the git CLI
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being operated on
+    fn needs_cli_transport(repo: &Repo) -> bool {
+        repo.url.starts_with("ssh://")
+            || (!repo.url.contains("://") && repo.url.contains(':'))
+            // libgit2 does not know about the environment variables and config entries git uses
+            || repo.env.is_some()
+            || repo.git_config.is_some()
+    }
+
+    /// Whether cloning `repo` needs options that libgit2 does not support
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being cloned
+    fn needs_cli_clone(repo: &Repo) -> bool {
+        Self::needs_cli_transport(repo)
+            || repo.is_bare()
+            || repo.is_mirror()
+            || repo.filter.is_some()
+            || repo.single_branch.unwrap_or(false)
+            || repo.sparse_checkout.is_some()
+            || repo.submodules() == Submodules::Recursive
+    }
+
+    /// Returns `RemoteCallbacks` that abort the transfer once the operations on `repo` are
+    /// interrupted, because libgit2 runs in-process and cannot be killed like the git CLI
+    ///
+    /// Credentials are asked from the credential helpers configured for `repo`, like git does. If
+    /// the remote rejects them, the transfer fails instead of asking again.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being transferred
+    /// * `started` - When the transfer was started
+    fn callbacks<'a>(&'a self, repo: &'a Repo, started: Instant) -> RemoteCallbacks<'a> {
+        let mut callbacks = RemoteCallbacks::new();
+        let mut asked = false;
+        callbacks
+            .credentials(move |url, username, allowed| {
+                if asked || !allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                    return Err(git2::Error::new(
+                        ErrorCode::Auth,
+                        ErrorClass::Http,
+                        format!("credentials for {} were rejected or are not supported", url),
+                    ));
+                }
+                asked = true;
+                // the repo is not there yet while it is cloned
+                let config = Repository::open(&repo.path)
+                    .and_then(|repository| repository.config())
+                    .or_else(|_| Config::open_default())?;
+                Cred::credential_helper(&config, url, username).map_err(|error| {
+                    git2::Error::new(ErrorCode::Auth, ErrorClass::Http, error.message())
+                })
+            })
+            .transfer_progress(move |_| self.cli.interruption(repo, started).is_none())
+            .sideband_progress(move |_| self.cli.interruption(repo, started).is_none());
+        callbacks
+    }
+
+    /// Returns `FetchOptions` with the `callbacks` of the transfer
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being transferred
+    /// * `started` - When the transfer was started
+    fn fetch_options<'a>(&'a self, repo: &'a Repo, started: Instant) -> FetchOptions<'a> {
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(self.callbacks(repo, started));
+        fetch_options
+    }
+
+    /// Returns the `Interruption` that aborted a transfer as the error, if there is one, instead of
+    /// the generic error libgit2 reports for it
+    ///
+    /// Rejected or missing credentials are described the way git describes them, so that they are
+    /// classified as `ErrorKind::Authentication` like failures of the git CLI.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being transferred
+    /// * `started` - When the transfer was started
+    /// * `error` - The error libgit2 reported
+    fn transfer_error(&self, repo: &Repo, started: Instant, error: git2::Error) -> Report {
+        match self.cli.interruption(repo, started) {
+            Some(interruption) => interruption.into(),
+            None if error.code() == ErrorCode::Auth => {
+                eyre!("Authentication failed: {}", error.message())
+            }
+            None => error.into(),
+        }
+    }
+}
+
+/// Strips the trailing slash libgit2 puts at the end of directory paths, so that paths match the
+/// ones reported by the git CLI
+///
+/// # Arguments
+///
+/// * `path` - The path being normalised
+fn normalise_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    path.strip_suffix('/').unwrap_or(&path).to_string()
+}
+
+/// Returns the name of the branch checked out in `repo`, or `None` if its HEAD is detached
+///
+/// # Arguments
+///
+/// * `repo` - The `Repository` being checked
+fn head_branch(repo: &Repository) -> Option<String> {
+    let head = repo.find_reference("HEAD").ok()?;
+    let target = head.symbolic_target()?;
+    target.strip_prefix("refs/heads/").map(|b| b.to_string())
+}
+
+impl GitBackend for Libgit2Backend {
+    fn clone_repo(&self, repo: &Repo) -> Result<()> {
+        // the CLI backend reports the clone in a dry run, instead of running it
+        if Self::needs_cli_clone(repo) || self.cli.is_dry_run() {
+            return self.cli.clone_repo(repo);
+        }
+        let depth = repo.depth.map(|depth| format!("--depth={}", depth));
+        let branch = repo
+            .branch
+            .as_ref()
+            .map(|branch| format!("--branch={}", branch));
+        let argv: Vec<&str> = ["git", "clone"]
+            .into_iter()
+            .chain(depth.as_deref())
+            .chain(branch.as_deref())
+            .chain(["--", &repo.url, &repo.path])
+            .collect();
+        self.cli.transfer(repo, &argv, ".", || {
+            let started = Instant::now();
+            let mut fetch_options = self.fetch_options(repo, started);
+            if let Some(depth) = repo.depth {
+                fetch_options.depth(depth as i32);
+            }
+            let mut builder = RepoBuilder::new();
+            builder.fetch_options(fetch_options);
+            if let Some(branch) = &repo.branch {
+                builder.branch(branch);
+            }
+            builder
+                .clone(&repo.url, Path::new(&repo.path))
+                .map_err(|error| self.transfer_error(repo, started, error))?;
+            Ok(())
+        })
+    }
+
+    fn fetch(&self, repo: &Repo, path: &str) -> Result<()> {
+        if Self::needs_cli_transport(repo) || self.cli.is_dry_run() {
+            return self.cli.fetch(repo, path);
+        }
+        let repository = Repository::open(path)?;
+        let mut remote = repository.find_remote("origin")?;
+        self.cli
+            .transfer(repo, &["git", "fetch", "origin"], path, || {
+                let started = Instant::now();
+                remote
+                    .fetch(
+                        &[] as &[&str],
+                        Some(&mut self.fetch_options(repo, started)),
+                        None,
+                    )
+                    .map_err(|error| self.transfer_error(repo, started, error))
+            })
+    }
+
+    fn status(&self, _repo: &Repo, path: &str) -> Result<DirtyStatus> {
+        let repository = Repository::open(path)?;
+        let submodule_paths: Vec<String> = repository
+            .submodules()?
+            .iter()
+            .map(|submodule| submodule.path().to_string_lossy().to_string())
+            .collect();
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(false)
+            .include_ignored(false);
+        let mut status = DirtyStatus::default();
+        for entry in repository.statuses(Some(&mut options))?.iter() {
+            let flags = entry.status();
+            let entry_path = entry.path().unwrap_or_default().trim_end_matches('/');
+            if submodule_paths.iter().any(|p| p == entry_path) {
+                status.submodule_dirty += 1;
+            } else if flags.is_conflicted() {
+                status.conflicted += 1;
+            } else if flags.is_wt_new() {
+                status.untracked += 1;
+            } else {
+                if flags.intersects(
+                    Status::INDEX_NEW
+                        | Status::INDEX_MODIFIED
+                        | Status::INDEX_DELETED
+                        | Status::INDEX_RENAMED
+                        | Status::INDEX_TYPECHANGE,
+                ) {
+                    status.staged += 1;
+                }
+                if flags.intersects(
+                    Status::WT_MODIFIED
+                        | Status::WT_DELETED
+                        | Status::WT_RENAMED
+                        | Status::WT_TYPECHANGE,
+                ) {
+                    status.unstaged += 1;
+                }
+            }
+        }
+        Ok(status)
+    }
+
+    fn branches(&self, _repo: &Repo, path: &str) -> Result<Vec<String>> {
+        let repository = Repository::open(path)?;
+        let mut branches = vec![];
+        for branch in repository.branches(Some(BranchType::Local))? {
+            if let Some(name) = branch?.0.name()? {
+                branches.push(name.to_string());
+            }
+        }
+        Ok(branches)
+    }
+
+    fn worktrees(&self, _repo: &Repo, path: &str) -> Result<Vec<WorktreeEntry>> {
+        let repository = Repository::open(path)?;
+        // when `path` is a linked worktree, the entries are listed from the main repository, whose
+        // git directory contains the worktree's at `worktrees/<name>`
+        let repository = match repository.path().parent().and_then(|p| p.parent()) {
+            Some(common_dir) if repository.is_worktree() => Repository::open(common_dir)?,
+            _ => repository,
+        };
+        let mut entries = vec![if repository.is_bare() {
+            WorktreeEntry {
+                path: normalise_path(repository.path()),
+                branch: None,
+                is_bare: true,
+            }
+        } else {
+            WorktreeEntry {
+                path: normalise_path(repository.workdir().unwrap_or(repository.path())),
+                branch: head_branch(&repository),
+                is_bare: false,
+            }
+        }];
+        for name in repository.worktrees()?.iter().flatten() {
+            let worktree = repository.find_worktree(name)?;
+            entries.push(WorktreeEntry {
+                path: normalise_path(worktree.path()),
+                branch: Repository::open_from_worktree(&worktree)
+                    .ok()
+                    .and_then(|r| head_branch(&r)),
+                is_bare: false,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn remote_branches(&self, repo: &Repo, path: &str) -> Result<Vec<String>> {
+        if Self::needs_cli_transport(repo) {
+            return self.cli.remote_branches(repo, path);
+        }
+        let repository = Repository::open(path)?;
+        let mut remote = repository.find_remote("origin")?;
+        let mut branches = vec![];
+        self.cli.transfer(
+            repo,
+            &["git", "ls-remote", "--heads", "origin"],
+            path,
+            || {
+                let started = Instant::now();
+                let connection = remote
+                    .connect_auth(Direction::Fetch, Some(self.callbacks(repo, started)), None)
+                    .map_err(|error| self.transfer_error(repo, started, error))?;
+                branches = connection
+                    .list()
+                    .map_err(|error| self.transfer_error(repo, started, error))?
+                    .iter()
+                    .filter_map(|head| head.name().strip_prefix("refs/heads/"))
+                    .map(|b| b.to_string())
+                    .collect();
+                Ok(())
+            },
+        )?;
+        Ok(branches)
+    }
+
+    fn ahead_behind(
+        &self,
+        _repo: &Repo,
+        path: &str,
+        branch: &str,
+    ) -> Result<Option<(usize, usize)>> {
+        let repository = Repository::open(path)?;
+        let resolve = |name: String| repository.refname_to_id(&name).ok();
+        match (
+            resolve(format!("refs/heads/{}", branch)),
+            resolve(format!("refs/remotes/origin/{}", branch)),
+        ) {
+            (Some(local), Some(upstream)) => {
+                Ok(Some(repository.graph_ahead_behind(local, upstream)?))
+            }
+            _ => Ok(None),
+        }
+    }
+}
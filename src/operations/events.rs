@@ -0,0 +1,169 @@
+use serde::{Serialize, Serializer};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{retry::Retry, Outcome};
+
+/// Sending half of the channel that `Event`s are emitted on
+pub type EventSender = UnboundedSender<Event>;
+
+/// Progress of the operations, emitted while they are running
+///
+/// Every event names the repo it belongs to, see `Repo::name`. Events of a single repo are
+/// emitted in order, but the events of different repos are interleaved, because repos are
+/// processed concurrently.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The command started running on the repo
+    RepoStarted {
+        /// Name of the repo
+        repo: String,
+
+        /// URL of the repo's remote
+        url: String,
+    },
+
+    /// A step of the command started, like pulling a single branch
+    PhaseStarted {
+        /// Name of the repo
+        repo: String,
+
+        /// Name of the operation, like `Pull`
+        op: String,
+
+        /// The branch being operated on, if the step operates on a single branch
+        branch: Option<String>,
+    },
+
+    /// Informational message about the state of the repo
+    Message {
+        /// Name of the repo
+        repo: String,
+
+        /// The message
+        message: String,
+    },
+
+    /// A step of the command failed, without stopping the command as a whole
+    Problem {
+        /// Name of the repo
+        repo: String,
+
+        /// Description of what went wrong
+        problem: String,
+    },
+
+    /// A git command exited
+    GitCommandFinished {
+        /// Name of the repo
+        repo: String,
+
+        /// The arguments git was called with, starting with `git`
+        argv: Vec<String>,
+
+        /// The directory git was run in
+        cwd: String,
+
+        /// The exit code of git, `None` if it was killed by a signal
+        status: Option<i32>,
+
+        /// What git printed to stdout
+        stdout: String,
+
+        /// What git printed to stderr
+        stderr: String,
+
+        /// How long git ran for
+        #[serde(serialize_with = "serialize_seconds")]
+        duration: Duration,
+    },
+
+    /// A git command failed because of the network, and is run again after a delay
+    Retrying {
+        /// Name of the repo
+        repo: String,
+
+        /// The failed attempt
+        #[serde(flatten)]
+        retry: Retry,
+    },
+
+    /// A hook exited
+    HookFinished {
+        /// Name of the repo
+        repo: String,
+
+        /// Name of the hook, like `post_pull`
+        hook: String,
+
+        /// The shell command of the hook
+        command: String,
+
+        /// The exit code of the hook, `None` if it was killed by a signal
+        status: Option<i32>,
+
+        /// What the hook printed to stdout
+        stdout: String,
+
+        /// What the hook printed to stderr
+        stderr: String,
+
+        /// How long the hook ran for
+        #[serde(serialize_with = "serialize_seconds")]
+        duration: Duration,
+    },
+
+    /// A git command that would have changed the repository, or a hook, was not run, because this
+    /// is a dry run
+    CommandSkipped {
+        /// Name of the repo
+        repo: String,
+
+        /// The arguments the command would have been called with, starting with `git` or `sh`
+        argv: Vec<String>,
+
+        /// The directory the command would have been run in
+        cwd: String,
+    },
+
+    /// The command finished running on the repo
+    RepoFinished {
+        /// Name of the repo
+        repo: String,
+
+        /// How the command as a whole ended
+        outcome: Outcome,
+
+        /// The problems reported while running the command
+        problems: Vec<String>,
+
+        /// The git commands that were run again after transient failures
+        retries: Vec<Retry>,
+    },
+}
+
+impl Event {
+    /// Returns the name of the repo this event belongs to
+    pub fn repo(&self) -> &str {
+        match self {
+            Event::RepoStarted { repo, .. }
+            | Event::PhaseStarted { repo, .. }
+            | Event::Message { repo, .. }
+            | Event::Problem { repo, .. }
+            | Event::GitCommandFinished { repo, .. }
+            | Event::HookFinished { repo, .. }
+            | Event::Retrying { repo, .. }
+            | Event::CommandSkipped { repo, .. }
+            | Event::RepoFinished { repo, .. } => repo,
+        }
+    }
+}
+
+/// Serializes a `Duration` as fractional seconds
+pub(crate) fn serialize_seconds<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
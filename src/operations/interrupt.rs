@@ -0,0 +1,334 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Read},
+    process::{Child, Command, Output},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Handle for cancelling running operations from elsewhere, like a Ctrl-C handler
+///
+/// Clones share their state, so cancelling one of them cancels all of them.
+#[derive(Debug, Default, Clone)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Cancels the operations: running git commands are killed, and no new ones are started
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether `cancel` has been called on this token or one of its clones
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Enumerates why a git command was stopped before it exited, or was not started at all
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interruption {
+    /// The git command ran longer than the command timeout
+    CommandTimeout(Duration),
+
+    /// The commands of the repo ran longer than the repo timeout
+    RepoTimeout(Duration),
+
+    /// The operations were cancelled through their `CancelToken`
+    Cancelled,
+}
+
+impl fmt::Display for Interruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interruption::CommandTimeout(timeout) => write!(
+                f,
+                "git command timed out after {}",
+                humantime::format_duration(*timeout)
+            ),
+            Interruption::RepoTimeout(timeout) => write!(
+                f,
+                "repo timed out after {}",
+                humantime::format_duration(*timeout)
+            ),
+            Interruption::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Interruption {}
+
+/// Keeps track of the cancellation and the timeouts the git commands of every repo are subject to
+#[derive(Debug, Default, Clone)]
+pub struct Deadlines {
+    cancel: CancelToken,
+    command_timeout: Option<Duration>,
+    repo_timeout: Option<Duration>,
+    repos_started: Arc<Mutex<HashMap<String, Instant>>>,
+    repos_interrupted: Arc<Mutex<HashMap<String, Interruption>>>,
+}
+
+impl Deadlines {
+    pub fn new(
+        cancel: CancelToken,
+        command_timeout: Option<Duration>,
+        repo_timeout: Option<Duration>,
+    ) -> Self {
+        Deadlines {
+            cancel,
+            command_timeout,
+            repo_timeout,
+            repos_started: Arc::default(),
+            repos_interrupted: Arc::default(),
+        }
+    }
+
+    /// Starts the repo timeout of `repo`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - Name of the repo
+    pub fn start_repo(&self, repo: &str) {
+        if let Ok(mut started) = self.repos_started.lock() {
+            started.insert(repo.to_string(), Instant::now());
+        }
+    }
+
+    /// Returns the `Interruption` that stops the commands of `repo` at this point, if there is one
+    ///
+    /// The first `Interruption` of every repo is recorded, see `interrupted`.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - Name of the repo
+    /// * `command_started` - When the current git command was started
+    pub fn check(&self, repo: &str, command_started: Instant) -> Option<Interruption> {
+        let interruption = self.find(repo, command_started)?;
+        if let Ok(mut interrupted) = self.repos_interrupted.lock() {
+            interrupted.entry(repo.to_string()).or_insert(interruption);
+        }
+        Some(interruption)
+    }
+
+    /// Returns the first `Interruption` that stopped a command of `repo`, if there was one
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - Name of the repo
+    pub fn interrupted(&self, repo: &str) -> Option<Interruption> {
+        self.repos_interrupted
+            .lock()
+            .ok()
+            .and_then(|interrupted| interrupted.get(repo).copied())
+    }
+
+    /// Returns the `Interruption` that applies to `repo` at this point, without recording it
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - Name of the repo
+    /// * `command_started` - When the current git command was started
+    fn find(&self, repo: &str, command_started: Instant) -> Option<Interruption> {
+        if self.cancel.is_cancelled() {
+            return Some(Interruption::Cancelled);
+        }
+        if let Some(timeout) = self.repo_timeout {
+            let repo_started = self
+                .repos_started
+                .lock()
+                .ok()
+                .and_then(|started| started.get(repo).copied());
+            if repo_started.is_some_and(|started| started.elapsed() >= timeout) {
+                return Some(Interruption::RepoTimeout(timeout));
+            }
+        }
+        match self.command_timeout {
+            Some(timeout) if command_started.elapsed() >= timeout => {
+                Some(Interruption::CommandTimeout(timeout))
+            }
+            _ => None,
+        }
+    }
+
+    /// Sleeps for `duration`, unless `repo` is interrupted first, in which case the
+    /// `Interruption` is returned
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - Name of the repo
+    /// * `duration` - How long to sleep for
+    pub fn sleep(&self, repo: &str, duration: Duration) -> Option<Interruption> {
+        let started = Instant::now();
+        while started.elapsed() < duration {
+            if let Some(interruption) = self.check(repo, Instant::now()) {
+                return Some(interruption);
+            }
+            thread::sleep((duration - started.elapsed()).min(Duration::from_millis(50)));
+        }
+        self.check(repo, Instant::now())
+    }
+
+    /// Waits for `child` to exit and collects its `Output`, unless it is interrupted first
+    ///
+    /// An interrupted child is killed, together with its own process group if it leads one, see
+    /// `isolate`, and returned with an empty output, because children outside of that group may keep
+    /// the pipes open for a while longer.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - Name of the repo `child` was started for
+    /// * `child` - The git process, with piped stdout and stderr
+    pub fn wait(&self, repo: &str, mut child: Child) -> io::Result<(Output, Option<Interruption>)> {
+        let started = Instant::now();
+        let stdout = read_to_end(child.stdout.take());
+        let stderr = read_to_end(child.stderr.take());
+        // most git commands exit within a few milliseconds, so the first polls come quickly
+        let mut pause = Duration::from_millis(1);
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok((
+                    Output {
+                        status,
+                        stdout: stdout.join().unwrap_or_default(),
+                        stderr: stderr.join().unwrap_or_default(),
+                    },
+                    None,
+                ));
+            }
+            if let Some(interruption) = self.check(repo, started) {
+                kill(&mut child);
+                let status = child.wait()?;
+                return Ok((
+                    Output {
+                        status,
+                        stdout: vec![],
+                        stderr: vec![],
+                    },
+                    Some(interruption),
+                ));
+            }
+            thread::sleep(pause);
+            pause = (pause * 2).min(Duration::from_millis(50));
+        }
+    }
+}
+
+/// Makes the process spawned by `command` lead a process group of its own, so that `Deadlines::wait`
+/// can kill it together with its children, like ssh, git-remote-https or a credential helper,
+/// which would otherwise keep the connection and the index lock
+///
+/// Only commands that do not ask on the terminal may be isolated, since a process outside of the
+/// foreground process group is stopped when it reads from the terminal.
+///
+/// # Arguments
+///
+/// * `command` - The `Command` about to be spawned
+pub fn isolate(command: &mut Command) {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+}
+
+/// Kills `child`, and every process in its process group if it leads one
+///
+/// # Arguments
+///
+/// * `child` - The interrupted process
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        let pid = child.id() as libc::pid_t;
+        // SAFETY: both functions only take plain integers, and signalling a group that no longer
+        // exists merely fails
+        unsafe {
+            if libc::getpgid(pid) == pid {
+                libc::killpg(pid, libc::SIGKILL);
+            }
+        }
+    }
+    let _ = child.kill();
+}
+
+/// Reads `pipe` to its end on a separate thread, so that the child does not block on a full pipe
+///
+/// # Arguments
+///
+/// * `pipe` - The stdout or stderr of a child process
+fn read_to_end(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    fn spawn(script: &str) -> Child {
+        Command::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn finished_child_returns_output() {
+        let deadlines = Deadlines::new(CancelToken::default(), Some(Duration::from_secs(10)), None);
+        let (output, interruption) = deadlines
+            .wait("repo", spawn("echo out; echo err >&2"))
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+        assert_eq!(interruption, None);
+    }
+
+    #[test]
+    fn hung_child_is_killed_after_command_timeout() {
+        let timeout = Duration::from_millis(100);
+        let deadlines = Deadlines::new(CancelToken::default(), Some(timeout), None);
+        let started = Instant::now();
+        let (output, interruption) = deadlines.wait("repo", spawn("exec sleep 10")).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!output.status.success());
+        assert_eq!(interruption, Some(Interruption::CommandTimeout(timeout)));
+    }
+
+    #[test]
+    fn cancelled_and_timed_out_repos_are_interrupted() {
+        let cancel = CancelToken::default();
+        let deadlines = Deadlines::new(cancel.clone(), None, Some(Duration::ZERO));
+        assert_eq!(deadlines.check("repo", Instant::now()), None);
+        deadlines.start_repo("repo");
+        assert_eq!(
+            deadlines.check("repo", Instant::now()),
+            Some(Interruption::RepoTimeout(Duration::ZERO))
+        );
+        assert_eq!(deadlines.check("other", Instant::now()), None);
+        cancel.cancel();
+        assert_eq!(
+            deadlines.check("other", Instant::now()),
+            Some(Interruption::Cancelled)
+        );
+        assert_eq!(
+            deadlines.interrupted("repo"),
+            Some(Interruption::RepoTimeout(Duration::ZERO))
+        );
+        assert_eq!(
+            deadlines.interrupted("other"),
+            Some(Interruption::Cancelled)
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use serde::Serialize;
+use std::time::Duration;
+
+use super::events::serialize_seconds;
+
+/// Parts of git's stderr, or of libgit2's errors, that point to a network failure, which may go
+/// away on its own
+const TRANSIENT_ERRORS: [&str; 19] = [
+    "could not resolve host",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "connection reset",
+    "connection refused",
+    "couldn't connect to server",
+    "connection timed out",
+    "operation timed out",
+    "network is unreachable",
+    "the remote end hung up unexpectedly",
+    "unexpected disconnect while reading sideband packet",
+    "early eof",
+    "returned error: 5",
+    "unexpected http status code: 5",
+    "internal server error",
+    "bad gateway",
+    "service unavailable",
+    "gateway timeout",
+    "tls connection was non-properly terminated",
+];
+
+/// Parts of git's stderr, or of libgit2's errors, that point to missing or rejected credentials,
+/// or an unverified host key
+const AUTHENTICATION_ERRORS: [&str; 11] = [
+    "authentication failed",
+    "authentication required",
+    "permission denied (publickey",
+    "could not read username",
+    "could not read password",
+    "terminal prompts disabled",
+    "host key verification failed",
+    "invalid username or password",
+    "access denied",
+    "returned error: 401",
+    "authentication replays",
+];
+
+/// Parts of git's stderr that point to a failure that retrying cannot fix, even when the stderr
+/// also matches one of the `TRANSIENT_ERRORS`
+const PERMANENT_ERRORS: [&str; 3] = [
+    "permission denied",
+    "repository not found",
+    "does not appear to be a git repository",
+];
+
+/// curl error codes in git's `RPC failed; curl <code>` message that point to a failed, dropped or
+/// stalled connection, like 56 for a connection reset
+const TRANSIENT_CURL_CODES: [u32; 7] = [7, 18, 28, 52, 55, 56, 92];
+
+/// Enumerates how a failed git command is treated
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The failure is likely to go away when the command is run again, like a dropped connection
+    Transient,
+
+    /// Running the command again is going to fail the same way, like a rejected push
+    Permanent,
+
+    /// The remote rejected the credentials or the host key, or git would have had to prompt for
+    /// them, which is permanent as well
+    Authentication,
+}
+
+impl ErrorKind {
+    /// Classifies the failure of a git command from its `stderr`
+    ///
+    /// # Arguments
+    ///
+    /// * `stderr` - What the failed git command printed to stderr
+    pub fn classify(stderr: &str) -> Self {
+        let stderr = stderr.to_lowercase();
+        if AUTHENTICATION_ERRORS
+            .iter()
+            .any(|error| stderr.contains(error))
+        {
+            ErrorKind::Authentication
+        } else if PERMANENT_ERRORS.iter().any(|error| stderr.contains(error)) {
+            ErrorKind::Permanent
+        } else if let Some(kind) = Self::classify_rpc_failure(&stderr) {
+            kind
+        } else if TRANSIENT_ERRORS.iter().any(|error| stderr.contains(error)) {
+            ErrorKind::Transient
+        } else {
+            ErrorKind::Permanent
+        }
+    }
+
+    /// Classifies the failed HTTP request git reports with `RPC failed; HTTP <status> curl <code>`,
+    /// or `None` if there is none in `stderr`
+    ///
+    /// Git follows that message up with generic ones, like that the remote end hung up, so only
+    /// the HTTP status is considered, or the curl error code if there is no status. Only 5xx
+    /// responses and connections that failed or dropped are transient, while responses like 403
+    /// or 413 are not going to change.
+    ///
+    /// # Arguments
+    ///
+    /// * `stderr` - What the failed git command printed to stderr, in lowercase
+    fn classify_rpc_failure(stderr: &str) -> Option<Self> {
+        let (_, failure) = stderr.split_once("rpc failed;")?;
+        let failure = failure.lines().next().unwrap_or_default();
+        // older versions of git report `RPC failed; result=22, HTTP code = 500`
+        let number_after = |label: &str| {
+            let (_, rest) = failure.split_once(label)?;
+            rest.trim_start_matches([' ', '='])
+                .split(|c: char| !c.is_ascii_digit())
+                .next()?
+                .parse::<u32>()
+                .ok()
+        };
+        let transient = match (number_after("http code"), number_after("http ")) {
+            (Some(status), _) | (None, Some(status)) => (500..600).contains(&status),
+            (None, None) => {
+                number_after("curl ").is_some_and(|code| TRANSIENT_CURL_CODES.contains(&code))
+            }
+        };
+        Some(match transient {
+            true => ErrorKind::Transient,
+            false => ErrorKind::Permanent,
+        })
+    }
+}
+
+/// How often and how fast git commands that talk to a remote are retried after transient failures
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How often a command is retried, on top of its first attempt
+    pub retries: u32,
+
+    /// How long to wait before the first retry, which doubles with every further retry
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Longest time waited between two attempts
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+    /// Returns how long to wait before the `retry`th retry, counting from 1
+    ///
+    /// # Arguments
+    ///
+    /// * `retry` - The number of the retry
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(Self::MAX_BACKOFF)
+    }
+}
+
+/// A git command that was run again after a transient failure
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Retry {
+    /// The arguments git was called with, starting with `git`
+    pub argv: Vec<String>,
+
+    /// The number of the attempt that failed, counting from 1
+    pub attempt: u32,
+
+    /// How long repoteer waited before the next attempt
+    #[serde(serialize_with = "serialize_seconds")]
+    pub delay: Duration,
+
+    /// The last line git printed to stderr on the failed attempt
+    pub reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_failures_are_transient() {
+        for stderr in [
+            "fatal: unable to access 'https://github.com/foo/bar.git/': Could not resolve host: github.com",
+            "error: RPC failed; curl 56 Recv failure: Connection reset by peer\nfatal: early EOF",
+            "fatal: unable to access 'https://example.com/foo.git/': The requested URL returned error: 503",
+            "fatal: unable to access 'http://127.0.0.1:1/foo.git/': Failed to connect to 127.0.0.1 port 1 after 0 ms: Couldn't connect to server",
+            "ssh: connect to host example.com port 22: Connection timed out\nfatal: Could not read from remote repository.",
+            "unexpected http status code: 502; class=Http (34)",
+            "error: RPC failed; HTTP 502 curl 22 The requested URL returned error: 502\n\
+             fatal: expected flush after ref listing",
+            "error: RPC failed; result=22, HTTP code = 503\nfatal: the remote end hung up unexpectedly",
+            "error: RPC failed; curl 18 transfer closed with outstanding read data remaining",
+            "error: RPC failed; curl 92 HTTP/2 stream 5 was not closed cleanly: CANCEL (err 8)",
+        ] {
+            assert_eq!(ErrorKind::classify(stderr), ErrorKind::Transient, "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn other_failures_are_permanent() {
+        for stderr in [
+            "fatal: unable to access 'https://example.com/foo.git/': The requested URL returned error: 404",
+            "fatal: '/home/foo/missing.git' does not appear to be a git repository",
+            " ! [rejected]        main -> main (non-fast-forward)",
+            "fatal: Not possible to fast-forward, aborting.",
+            "error: RPC failed; HTTP 403 curl 22 The requested URL returned error: 403\n\
+             fatal: the remote end hung up unexpectedly",
+            "error: RPC failed; HTTP 413 curl 22 The requested URL returned error: 413\n\
+             send-pack: unexpected disconnect while reading sideband packet\n\
+             fatal: the remote end hung up unexpectedly",
+            "error: RPC failed; curl 6 Could not resolve proxy: proxy.invalid",
+        ] {
+            assert_eq!(ErrorKind::classify(stderr), ErrorKind::Permanent, "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn credential_failures_are_authentication_errors() {
+        for stderr in [
+            "fatal: Authentication failed for 'https://github.com/foo/bar.git/'",
+            "git@github.com: Permission denied (publickey).\nfatal: Could not read from remote repository.",
+            "fatal: could not read Username for 'https://github.com': terminal prompts disabled",
+            "Host key verification failed.\nfatal: Could not read from remote repository.",
+            "too many redirects or authentication replays; class=Http (34)",
+        ] {
+            assert_eq!(
+                ErrorKind::classify(stderr),
+                ErrorKind::Authentication,
+                "{}",
+                stderr
+            );
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_limit() {
+        let policy = RetryPolicy {
+            retries: 10,
+            backoff: Duration::from_secs(1),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(10), RetryPolicy::MAX_BACKOFF);
+    }
+}
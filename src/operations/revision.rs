@@ -0,0 +1,338 @@
+use color_eyre::{eyre::bail, Result};
+use std::{fmt, process::Output};
+
+use super::{
+    backend::GitBackend, get_output_lines, get_output_message, GitCommand, GitRunner, RepoTask,
+};
+use crate::manifest::repo::{Repo, Submodules};
+
+/// Enumerates what the `revision` of a repo can refer to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RevisionKind {
+    /// A branch, which the checkout follows by fast-forwarding it to its remote branch
+    Branch,
+
+    /// A tag, which is checked out on a detached HEAD
+    Tag,
+
+    /// Any other commit, like a SHA, which is checked out on a detached HEAD
+    Commit,
+}
+
+impl fmt::Display for RevisionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevisionKind::Branch => write!(f, "branch"),
+            RevisionKind::Tag => write!(f, "tag"),
+            RevisionKind::Commit => write!(f, "commit"),
+        }
+    }
+}
+
+/// Returns the commit `reference` resolves to in the checkout at `path`, if it exists
+///
+/// # Arguments
+///
+/// * `git` - The `GitRunner` running the git commands
+/// * `repo` - The `Repo` being operated on
+/// * `path` - The path of the checkout
+/// * `reference` - Anything `git rev-parse` understands, like a ref or a SHA
+pub(super) fn resolve_ref(
+    git: &GitRunner,
+    repo: &Repo,
+    path: &str,
+    reference: &str,
+) -> Result<Option<String>> {
+    let output = GitCommand::VerifyRef.run(git, repo, path, reference)?;
+    Ok(get_output_lines(output)?.into_iter().next())
+}
+
+/// Returns the branch checked out at `path`, `None` if HEAD is detached
+///
+/// # Arguments
+///
+/// * `git` - The `GitRunner` running the git commands
+/// * `repo` - The `Repo` being operated on
+/// * `path` - The path of the checkout
+pub(super) fn current_branch(git: &GitRunner, repo: &Repo, path: &str) -> Result<Option<String>> {
+    let output = GitCommand::CurrentBranch.run(git, repo, path, "")?;
+    Ok(get_output_lines(output)?.into_iter().next())
+}
+
+/// Returns what `revision` refers to in the checkout at `path`, and the commit it points to, or
+/// `None` if it refers to nothing
+///
+/// Branches take precedence over tags, and tags over commits, like they do for `git checkout`.
+/// For branches, the last fetched state of the remote branch is preferred over the local branch.
+///
+/// # Arguments
+///
+/// * `git` - The `GitRunner` running the git commands
+/// * `repo` - The `Repo` being operated on
+/// * `path` - The path of the checkout
+/// * `revision` - The revision declared for the repo
+pub fn resolve(
+    git: &GitRunner,
+    repo: &Repo,
+    path: &str,
+    revision: &str,
+) -> Result<Option<(RevisionKind, String)>> {
+    for (kind, reference) in [
+        (
+            RevisionKind::Branch,
+            format!("refs/remotes/origin/{}", revision),
+        ),
+        (RevisionKind::Branch, format!("refs/heads/{}", revision)),
+        (
+            RevisionKind::Tag,
+            format!("refs/tags/{}^{{commit}}", revision),
+        ),
+        (RevisionKind::Commit, format!("{}^{{commit}}", revision)),
+    ] {
+        if let Some(commit) = resolve_ref(git, repo, path, &reference)? {
+            return Ok(Some((kind, commit)));
+        }
+    }
+    Ok(None)
+}
+
+/// Moves the checkout at `path` to the repo's `revision`, and returns a message describing what
+/// was done
+///
+/// Branches are checked out and fast-forwarded to their remote branch, while tags and commits are
+/// checked out on a detached HEAD. Local work is never discarded: checkouts with local changes of a
+/// kind listed in the repo's `pull_blockers` are left alone, as are detached HEADs with commits
+/// that no branch or tag contains.
+///
+/// # Arguments
+///
+/// * `git` - The `GitRunner` running the git commands
+/// * `backend` - The `GitBackend` used to check the status of the checkout
+/// * `repo` - The `Repo` being operated on
+/// * `path` - The path of the checkout
+pub fn move_to_revision(
+    git: &GitRunner,
+    backend: &dyn GitBackend,
+    repo: &Repo,
+    path: &str,
+) -> Result<String> {
+    let revision = match &repo.revision {
+        Some(revision) => revision.as_str(),
+        None => bail!("Repo does not declare a revision!"),
+    };
+    let blocking = backend.status(repo, path)?.only(&repo.pull_blockers());
+    if !blocking.is_clean() {
+        bail!(
+            "Skipped moving to revision {}, because the checkout has local changes: {}",
+            revision,
+            blocking
+        );
+    }
+    let (kind, commit) = match resolve(git, repo, path, revision)? {
+        Some(resolved) => resolved,
+        None => bail!("Revision {} does not exist!", revision),
+    };
+    let head = resolve_ref(git, repo, path, "HEAD")?;
+    let branch = current_branch(git, repo, path)?;
+    let message = match kind {
+        RevisionKind::Branch => {
+            if branch.as_deref() != Some(revision) {
+                ensure_head_is_kept(git, repo, path, &branch)?;
+                let output = if resolve_ref(git, repo, path, &format!("refs/heads/{}", revision))?
+                    .is_some()
+                {
+                    GitCommand::Checkout.run(git, repo, path, revision)?
+                } else {
+                    GitCommand::CheckoutTrack.run(git, repo, path, revision)?
+                };
+                check(&output, revision)?;
+            }
+            if resolve_ref(
+                git,
+                repo,
+                path,
+                &format!("refs/remotes/origin/{}", revision),
+            )?
+            .is_some()
+            {
+                let output = GitCommand::MergeFastForward.run(git, repo, path, revision)?;
+                if !output.status.success() {
+                    bail!(
+                        "Unable to fast-forward branch {} to origin/{}: {}",
+                        revision,
+                        revision,
+                        get_output_message(&output)
+                    );
+                }
+            }
+            format!("branch {} is up to date", revision)
+        }
+        RevisionKind::Tag | RevisionKind::Commit => {
+            if head.as_deref() == Some(commit.as_str()) && branch.is_none() {
+                return Ok(format!("already at {} {}", kind, revision));
+            }
+            ensure_head_is_kept(git, repo, path, &branch)?;
+            let output = GitCommand::CheckoutDetach.run(git, repo, path, &commit)?;
+            check(&output, revision)?;
+            format!("checked out {} {}", kind, revision)
+        }
+    };
+    if repo.submodules() != Submodules::None {
+        let output = GitCommand::SubmoduleUpdate.run(git, repo, path, "")?;
+        if !output.status.success() {
+            bail!(
+                "Moved to revision {}, but updating its submodules failed! {}",
+                revision,
+                get_output_message(&output)
+            );
+        }
+    }
+    Ok(message)
+}
+
+/// Fails if moving HEAD away would lose commits, which is the case for a detached HEAD with
+/// commits that no branch or tag contains
+///
+/// # Arguments
+///
+/// * `git` - The `GitRunner` running the git commands
+/// * `repo` - The `Repo` being operated on
+/// * `path` - The path of the checkout
+/// * `branch` - The branch checked out at `path`, `None` if HEAD is detached
+pub(super) fn ensure_head_is_kept(
+    git: &GitRunner,
+    repo: &Repo,
+    path: &str,
+    branch: &Option<String>,
+) -> Result<()> {
+    if branch.is_some() {
+        return Ok(());
+    }
+    let output = GitCommand::RefsContaining.run(git, repo, path, "HEAD")?;
+    if get_output_lines(output)?.is_empty() {
+        bail!("Skipped moving the detached HEAD, because no branch or tag contains its commits");
+    }
+    Ok(())
+}
+
+/// Fails with the message of `output`, if the checkout of `revision` failed
+///
+/// # Arguments
+///
+/// * `output` - The `Output` of the checkout
+/// * `revision` - The revision being checked out
+fn check(output: &Output, revision: &str) -> Result<()> {
+    if !output.status.success() {
+        bail!(
+            "Unable to check out revision {}: {}",
+            revision,
+            get_output_message(output)
+        );
+    }
+    Ok(())
+}
+
+/// Returns how the checkout at `path` drifted from the repo's `revision`, or `None` if it did not
+///
+/// A checkout drifted from a branch if it is not on that branch, and from a tag or commit if its
+/// HEAD is somewhere else. How far a branch is ahead or behind is left to the status of the branch.
+///
+/// # Arguments
+///
+/// * `git` - The `GitRunner` running the git commands
+/// * `repo` - The `Repo` being operated on
+/// * `path` - The path of the checkout
+pub fn drift(git: &GitRunner, repo: &Repo, path: &str) -> Result<Option<String>> {
+    let revision = match &repo.revision {
+        Some(revision) => revision.as_str(),
+        None => return Ok(None),
+    };
+    let (kind, commit) = match resolve(git, repo, path, revision)? {
+        Some(resolved) => resolved,
+        None => return Ok(Some(format!("revision {} does not exist", revision))),
+    };
+    let branch = current_branch(git, repo, path)?;
+    let head = resolve_ref(git, repo, path, "HEAD")?;
+    Ok(match kind {
+        RevisionKind::Branch if branch.as_deref() != Some(revision) => Some(format!(
+            "on {} instead of branch {}",
+            branch.map_or("a detached HEAD".to_string(), |b| format!("branch {}", b)),
+            revision
+        )),
+        RevisionKind::Tag | RevisionKind::Commit if head.as_deref() != Some(commit.as_str()) => {
+            Some(format!(
+                "HEAD is at {} instead of {} {}",
+                head.as_deref().unwrap_or("no commit"),
+                kind,
+                revision
+            ))
+        }
+        _ => None,
+    })
+}
+
+/// Fetches the repo and moves its checkout to its `revision`, instead of pulling every branch,
+/// and returns a `eyre::Result<Output>`
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+pub(super) fn run_revision_pull(task: &mut RepoTask) -> Result<Output> {
+    task.start_phase("Fetch", None);
+    if let Err(e) = task.backend.fetch(&task.repo, &task.repo.path) {
+        task.report_problem(e.to_string());
+    }
+    checkout_revision(task);
+    task.update_state("Pull complete!".to_string());
+    Ok(std::process::Command::new("echo")
+        .arg(task.state.clone())
+        .output()?)
+}
+
+/// Moves the checkout of the repo to its `revision`, and reports it as a problem if that fails
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+pub(super) fn checkout_revision(task: &mut RepoTask) {
+    task.start_phase("Checkout", task.repo.revision.as_deref());
+    match move_to_revision(
+        &task.git,
+        task.backend.as_ref(),
+        &task.repo,
+        &task.repo.path,
+    ) {
+        Ok(message) => task.update_state(message),
+        Err(e) => task.report_problem(e.to_string()),
+    }
+}
+
+/// Pushes the branch the repo's `revision` refers to, instead of every branch, and returns a
+/// `eyre::Result<Output>`
+///
+/// Repos kept at a tag or commit are not pushed, because their branches are not pulled either.
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+pub(super) fn run_revision_push(task: &mut RepoTask) -> Result<Output> {
+    let revision = task.repo.revision.clone().unwrap_or_default();
+    match resolve(&task.git, &task.repo, &task.repo.path, &revision)? {
+        Some((RevisionKind::Branch, _)) => {
+            task.start_phase("Push", Some(&revision));
+            let output = GitCommand::Push.run(&task.git, &task.repo, &task.repo.path, &revision)?;
+            if !output.status.success() {
+                task.report_problem(get_output_message(&output));
+            }
+            task.update_state("Push complete!".to_string());
+        }
+        Some((kind, _)) => task.update_state(format!(
+            "Repo is kept at {} {}, skipping push",
+            kind, revision
+        )),
+        None => task.report_problem(format!("Revision {} does not exist!", revision)),
+    }
+    Ok(std::process::Command::new("echo")
+        .arg(task.state.clone())
+        .output()?)
+}
@@ -0,0 +1,318 @@
+use color_eyre::{eyre::bail, Result};
+use std::{path::Path, process::Output};
+
+use super::{
+    get_output_lines, get_output_message, ref_tips,
+    revision::{current_branch, ensure_head_is_kept, resolve_ref},
+    status::DirtyStatus,
+    GitCommand, RepoTask,
+};
+use crate::state::snapshot::{CheckoutSnapshot, RepoSnapshot, Snapshot, StashSnapshot};
+
+/// Records the HEAD, the branch tips, the stashes and the local changes of the repo in
+/// `task.snapshot`, and returns a `eyre::Result<Output>`
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+pub(super) fn run_snapshot(task: &mut RepoTask) -> Result<Output> {
+    if !Path::new(&task.repo.path).exists() {
+        bail!("Repo has not been cloned yet!");
+    }
+    let branches = ref_tips(task, GitCommand::BranchTips)?;
+    let mut checkouts = vec![];
+    for entry in task
+        .backend
+        .worktrees(&task.repo, &task.repo.path)?
+        .into_iter()
+        .filter(|entry| !entry.is_bare)
+    {
+        match resolve_ref(&task.git, &task.repo, &entry.path, "HEAD")? {
+            Some(head) => checkouts.push(CheckoutSnapshot {
+                dirty: task.backend.status(&task.repo, &entry.path)?,
+                path: entry.path,
+                branch: entry.branch,
+                head,
+            }),
+            None => task.report_problem(format!(
+                "Checkout at {} has no commit to snapshot",
+                entry.path
+            )),
+        }
+    }
+    let stashes = match checkouts.first() {
+        Some(checkout) => stash_list(task, &checkout.path.clone())?,
+        None => vec![],
+    };
+    task.update_state(format!(
+        "recorded {} branches, {} checkouts and {} stashes",
+        branches.len(),
+        checkouts.len(),
+        stashes.len()
+    ));
+    task.snapshot = Some(RepoSnapshot {
+        name: task.name.clone(),
+        url: task.repo.url.clone(),
+        branches,
+        checkouts,
+        stashes,
+    });
+    task.update_state("Snapshot complete!".to_string());
+    Ok(std::process::Command::new("echo")
+        .arg(task.state.clone())
+        .output()?)
+}
+
+/// Brings the branches and checkouts of the repo back to the state recorded in `snapshot`, where
+/// that is safe, and returns a `eyre::Result<Output>`
+///
+/// Branches are reset to their recorded commits, and checkouts get their recorded branch or
+/// detached HEAD back, unless they have local changes of a kind listed in the repo's
+/// `pull_blockers`. Branches are only moved back if they gained commits on top of their recorded
+/// commits, unless `force` is set, and the commits they drop are reported. Everything that cannot
+/// be restored, like commits or stashes that no longer exist, branches created after the snapshot
+/// and local changes at the time of the snapshot, is reported as a problem.
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+/// * `snapshot` - The `Snapshot` being restored
+/// * `force` - Whether branches that moved away from the snapshot are reset too
+pub(super) fn run_restore(task: &mut RepoTask, snapshot: &Snapshot, force: bool) -> Result<Output> {
+    let saved = match snapshot.get(&task.name) {
+        Some(saved) => saved.clone(),
+        None => bail!("Repo is not in snapshot {}!", snapshot.name),
+    };
+    if !Path::new(&task.repo.path).exists() {
+        bail!("Repo has not been cloned yet!");
+    }
+    let entries: Vec<_> = task
+        .backend
+        .worktrees(&task.repo, &task.repo.path)?
+        .into_iter()
+        .filter(|entry| !entry.is_bare)
+        .collect();
+    let mut dirty = vec![];
+    for entry in entries.iter() {
+        let changes = local_changes(task, &entry.path)?;
+        if !changes.is_clean() {
+            task.report_problem(format!(
+                "Skipped restoring checkout at {}, because it has local changes: {}",
+                entry.path, changes
+            ));
+            dirty.push(entry.path.clone());
+        }
+    }
+
+    task.start_phase("Restore branches", None);
+    let current = ref_tips(task, GitCommand::BranchTips)?;
+    for (branch, commit) in saved.branches.iter() {
+        let tip = current.get(branch);
+        if tip == Some(commit) {
+            continue;
+        }
+        let exists = GitCommand::CommitExists
+            .run(&task.git, &task.repo, &task.repo.path, commit)?
+            .status
+            .success();
+        if !exists {
+            task.report_problem(format!(
+                "Unable to restore branch {} to {}, because the commit does not exist anymore",
+                branch, commit
+            ));
+            continue;
+        }
+        let checked_out = entries
+            .iter()
+            .find(|entry| entry.branch.as_ref() == Some(branch));
+        if checked_out.is_some_and(|entry| dirty.contains(&entry.path)) {
+            continue;
+        }
+        let dropped = match tip {
+            Some(_) => get_output_lines(
+                GitCommand::CommitsBetween {
+                    from: commit.clone(),
+                }
+                .run(&task.git, &task.repo, &task.repo.path, branch)?,
+            )?,
+            None => vec![],
+        };
+        // a branch that only gained commits since the snapshot is safe to move back, but one that
+        // was rebased or reset elsewhere would lose commits the snapshot knows nothing about
+        if !dropped.is_empty() && !force {
+            let on_top = GitCommand::IsAncestor {
+                descendant: tip.cloned().unwrap_or_default(),
+            }
+            .run(&task.git, &task.repo, &task.repo.path, commit)?
+            .status
+            .success();
+            if !on_top {
+                task.report_problem(format!(
+                    "Skipped restoring branch {} to {}, because it moved away from the snapshot. \
+                     Restore with --force to drop its commits: {}",
+                    branch,
+                    commit,
+                    dropped.join(", ")
+                ));
+                continue;
+            }
+        }
+        let output = match checked_out {
+            Some(entry) => GitCommand::ResetKeep.run(&task.git, &task.repo, &entry.path, commit)?,
+            None => GitCommand::UpdateRef {
+                new: commit.clone(),
+                old: tip.cloned().unwrap_or_default(),
+            }
+            .run(&task.git, &task.repo, &task.repo.path, branch)?,
+        };
+        if !output.status.success() {
+            task.report_problem(format!(
+                "Unable to restore branch {} to {}: {}",
+                branch,
+                commit,
+                get_output_message(&output)
+            ));
+            continue;
+        }
+        task.update_state(match tip {
+            Some(tip) => format!("reset branch {} from {} to {}", branch, tip, commit),
+            None => format!("recreated branch {} at {}", branch, commit),
+        });
+        if !dropped.is_empty() {
+            task.report_problem(format!(
+                "Dropped {} commit(s) made after the snapshot from branch {}, which are kept in its \
+                 reflog: {}",
+                dropped.len(),
+                branch,
+                dropped.join(", ")
+            ));
+        }
+    }
+    for branch in current.keys().filter(|b| !saved.branches.contains_key(*b)) {
+        task.report_problem(format!(
+            "Kept branch {}, which did not exist when the snapshot was taken",
+            branch
+        ));
+    }
+
+    for checkout in saved.checkouts.iter() {
+        restore_checkout(task, checkout, &dirty)?;
+    }
+
+    if let Some(path) = entries.first().map(|entry| entry.path.clone()) {
+        let stashes = stash_list(task, &path)?;
+        for stash in saved
+            .stashes
+            .iter()
+            .filter(|saved| !stashes.iter().any(|s| s.commit == saved.commit))
+        {
+            task.report_problem(format!(
+                "Stash \"{}\" is gone, apply it with `git stash apply {}` while its commit still exists",
+                stash.message, stash.commit
+            ));
+        }
+    }
+    task.update_state("Restore complete!".to_string());
+    Ok(std::process::Command::new("echo")
+        .arg(task.state.clone())
+        .output()?)
+}
+
+/// Checks out the recorded branch or detached HEAD of a single checkout again
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+/// * `checkout` - The `CheckoutSnapshot` being restored
+/// * `dirty` - Paths of the checkouts that have local changes, which are left alone
+fn restore_checkout(
+    task: &mut RepoTask,
+    checkout: &CheckoutSnapshot,
+    dirty: &[String],
+) -> Result<()> {
+    let recorded = checkout.dirty.only(&task.repo.pull_blockers());
+    if !recorded.is_clean() {
+        task.report_problem(format!(
+            "Checkout at {} had local changes when the snapshot was taken, which are not restored: {}",
+            checkout.path, recorded
+        ));
+    }
+    if !Path::new(&checkout.path).exists() {
+        task.report_problem(format!(
+            "Checkout at {} does not exist anymore",
+            checkout.path
+        ));
+        return Ok(());
+    }
+    if dirty.contains(&checkout.path) {
+        return Ok(());
+    }
+    let (git, repo, path) = (&task.git, &task.repo, checkout.path.as_str());
+    let branch = current_branch(git, repo, path)?;
+    let head = resolve_ref(git, repo, path, "HEAD")?;
+    let restored = match &checkout.branch {
+        Some(_) => branch == checkout.branch,
+        None => branch.is_none() && head.as_ref() == Some(&checkout.head),
+    };
+    if restored {
+        return Ok(());
+    }
+    if let Err(e) = ensure_head_is_kept(git, repo, path, &branch) {
+        task.report_problem(format!("Checkout at {}: {}", path, e));
+        return Ok(());
+    }
+    let output = match &checkout.branch {
+        Some(branch) => GitCommand::Checkout.run(git, repo, path, branch)?,
+        None => GitCommand::CheckoutDetach.run(git, repo, path, &checkout.head)?,
+    };
+    if output.status.success() {
+        task.update_state(format!(
+            "checked out {} at {}",
+            checkout.branch.as_deref().unwrap_or(&checkout.head),
+            path
+        ));
+    } else {
+        task.report_problem(format!(
+            "Unable to restore checkout at {}: {}",
+            path,
+            get_output_message(&output)
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the local changes of the checkout at `path` that count against restoring it
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+/// * `path` - The path of the checkout
+fn local_changes(task: &RepoTask, path: &str) -> Result<DirtyStatus> {
+    Ok(task
+        .backend
+        .status(&task.repo, path)?
+        .only(&task.repo.pull_blockers()))
+}
+
+/// Returns the stashes of the repo, newest first
+///
+/// # Arguments
+///
+/// * `task` - The `RepoTask` of the repo
+/// * `path` - The path of one of the checkouts of the repo
+fn stash_list(task: &RepoTask, path: &str) -> Result<Vec<StashSnapshot>> {
+    let output = GitCommand::StashList.run(&task.git, &task.repo, path, "")?;
+    if !output.status.success() {
+        bail!("Unable to list stashes: {}", get_output_message(&output));
+    }
+    Ok(get_output_lines(output)?
+        .into_iter()
+        .filter_map(|line| {
+            let (commit, message) = line.split_once(' ')?;
+            Some(StashSnapshot {
+                commit: commit.to_string(),
+                message: message.to_string(),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::manifest::repo::ChangeKind;
+
+/// Counts of the local changes of a checkout, grouped by their `ChangeKind`
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct DirtyStatus {
+    /// Number of files with changes in the index
+    pub staged: usize,
+
+    /// Number of tracked files with changes in the working tree
+    pub unstaged: usize,
+
+    /// Number of untracked files
+    pub untracked: usize,
+
+    /// Number of files with unresolved merge conflicts
+    pub conflicted: usize,
+
+    /// Number of submodules with new commits, modifications or untracked files
+    pub submodule_dirty: usize,
+}
+
+impl DirtyStatus {
+    /// Parses the lines of `git status --porcelain=v2` into a `DirtyStatus`
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The lines of the porcelain output
+    pub fn from_porcelain_v2(lines: &[String]) -> Self {
+        let mut status = DirtyStatus::default();
+        for line in lines.iter() {
+            let mut fields = line.split(' ');
+            match fields.next() {
+                Some("1") | Some("2") => {
+                    let xy = fields.next().unwrap_or("..");
+                    let submodule = fields.next().unwrap_or("N...");
+                    if submodule.starts_with('S') {
+                        status.submodule_dirty += 1;
+                        continue;
+                    }
+                    if !xy.starts_with('.') {
+                        status.staged += 1;
+                    }
+                    if !xy.ends_with('.') {
+                        status.unstaged += 1;
+                    }
+                }
+                Some("u") => status.conflicted += 1,
+                Some("?") => status.untracked += 1,
+                _ => {}
+            }
+        }
+        status
+    }
+
+    /// Returns the number of changes of the given `kind`
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The `ChangeKind` being counted
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        match kind {
+            ChangeKind::Staged => self.staged,
+            ChangeKind::Unstaged => self.unstaged,
+            ChangeKind::Untracked => self.untracked,
+            ChangeKind::Conflicted => self.conflicted,
+            ChangeKind::SubmoduleDirty => self.submodule_dirty,
+        }
+    }
+
+    /// Whether there are no local changes at all
+    pub fn is_clean(&self) -> bool {
+        ChangeKind::ALL.iter().all(|kind| self.count(*kind) == 0)
+    }
+
+    /// Returns a `DirtyStatus` that only keeps the counts of the given `kinds`
+    ///
+    /// # Arguments
+    ///
+    /// * `kinds` - The `ChangeKind`s being kept
+    pub fn only(&self, kinds: &[ChangeKind]) -> Self {
+        let keep = |kind: ChangeKind, count: usize| if kinds.contains(&kind) { count } else { 0 };
+        DirtyStatus {
+            staged: keep(ChangeKind::Staged, self.staged),
+            unstaged: keep(ChangeKind::Unstaged, self.unstaged),
+            untracked: keep(ChangeKind::Untracked, self.untracked),
+            conflicted: keep(ChangeKind::Conflicted, self.conflicted),
+            submodule_dirty: keep(ChangeKind::SubmoduleDirty, self.submodule_dirty),
+        }
+    }
+}
+
+impl fmt::Display for DirtyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_clean() {
+            return write!(f, "clean");
+        }
+        let parts: Vec<String> = ChangeKind::ALL
+            .iter()
+            .filter(|kind| self.count(**kind) > 0)
+            .map(|kind| format!("{} {}", self.count(*kind), kind))
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Enumerates the problems `git submodule status` can report for a submodule
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SubmoduleProblem {
+    /// The submodule has not been initialised
+    Uninitialized,
+
+    /// The checked out commit does not match the one recorded in the superproject
+    OutOfDate,
+
+    /// The submodule has merge conflicts
+    Conflicted,
+}
+
+impl SubmoduleProblem {
+    /// Parses the lines of `git submodule status` into the paths of the submodules that have a
+    /// problem, paired with that `SubmoduleProblem`
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The lines of the `git submodule status` output
+    pub fn from_submodule_status(lines: &[String]) -> Vec<(String, SubmoduleProblem)> {
+        lines
+            .iter()
+            .filter_map(|line| {
+                let problem = match line.chars().next()? {
+                    '-' => SubmoduleProblem::Uninitialized,
+                    '+' => SubmoduleProblem::OutOfDate,
+                    'U' => SubmoduleProblem::Conflicted,
+                    _ => return None,
+                };
+                let path = line[1..].split_whitespace().nth(1)?;
+                Some((path.to_string(), problem))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for SubmoduleProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                SubmoduleProblem::Uninitialized => "not initialised",
+                SubmoduleProblem::OutOfDate => "out of date",
+                SubmoduleProblem::Conflicted => "conflicted",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn from_porcelain_v2_clean() {
+        let status = DirtyStatus::from_porcelain_v2(&[]);
+        assert!(status.is_clean());
+        assert_eq!(status.to_string(), "clean");
+    }
+
+    #[test]
+    fn from_porcelain_v2_categories() {
+        let status = DirtyStatus::from_porcelain_v2(&lines(&[
+            "1 M. N... 100644 100644 100644 3f2c1e0 3f2c1e1 staged.txt",
+            "1 .M N... 100644 100644 100644 3f2c1e0 3f2c1e0 unstaged.txt",
+            "1 MM N... 100644 100644 100644 3f2c1e0 3f2c1e1 both.txt",
+            "2 R. N... 100644 100644 100644 3f2c1e0 3f2c1e0 R100 new.txt\told.txt",
+            "u UU N... 100644 100644 100644 100644 3f2c1e0 3f2c1e1 3f2c1e2 conflict.txt",
+            "1 .M SC.. 160000 160000 160000 3f2c1e0 3f2c1e0 vendor/lib",
+            "? build/artifact.o",
+            "! ignored.log",
+        ]));
+        assert_eq!(
+            status,
+            DirtyStatus {
+                staged: 3,
+                unstaged: 2,
+                untracked: 1,
+                conflicted: 1,
+                submodule_dirty: 1,
+            }
+        );
+        assert_eq!(
+            status.to_string(),
+            "3 staged, 2 unstaged, 1 untracked, 1 conflicted, 1 submodule-dirty"
+        );
+    }
+
+    #[test]
+    fn only_untracked() {
+        let status = DirtyStatus::from_porcelain_v2(&lines(&["? build/artifact.o"]));
+        assert!(!status.is_clean());
+        assert!(status
+            .only(&[ChangeKind::Staged, ChangeKind::Unstaged])
+            .is_clean());
+    }
+
+    #[test]
+    fn from_submodule_status() {
+        let problems = SubmoduleProblem::from_submodule_status(&lines(&[
+            " 3f2c1e0a9b8d7c6e5f4a3b2c1d0e9f8a7b6c5d4e vendor/ok (v1.0.0)",
+            "-3f2c1e0a9b8d7c6e5f4a3b2c1d0e9f8a7b6c5d4e vendor/missing",
+            "+3f2c1e0a9b8d7c6e5f4a3b2c1d0e9f8a7b6c5d4e vendor/stale (v1.0.0-2-g3f2c1e0)",
+            "U3f2c1e0a9b8d7c6e5f4a3b2c1d0e9f8a7b6c5d4e vendor/conflict",
+        ]));
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    "vendor/missing".to_string(),
+                    SubmoduleProblem::Uninitialized
+                ),
+                ("vendor/stale".to_string(), SubmoduleProblem::OutOfDate),
+                ("vendor/conflict".to_string(), SubmoduleProblem::Conflicted),
+            ]
+        );
+    }
+}
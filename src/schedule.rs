@@ -0,0 +1,362 @@
+use color_eyre::eyre::{eyre, Report};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+/// Prefix of the names of the systemd units written by `repoteer schedule install`
+const UNIT_PREFIX: &str = "repoteer-";
+
+/// A scheduled run of repoteer, as a systemd user `.service` running repoteer once and a `.timer`
+/// starting the service periodically
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// Name of the schedule, the units are called `repoteer-<name>.service` and
+    /// `repoteer-<name>.timer`
+    pub name: String,
+
+    /// How long the timer waits between runs
+    pub every: Duration,
+
+    /// The repoteer executable and the arguments it is run with, including the command
+    pub argv: Vec<String>,
+}
+
+impl Schedule {
+    /// Returns the directory systemd looks for user units in, which is
+    /// `$XDG_CONFIG_HOME/systemd/user`, or `$HOME/.config/systemd/user` if `XDG_CONFIG_HOME` is not
+    /// set
+    pub fn unit_dir() -> Result<PathBuf, Report> {
+        match env::var("XDG_CONFIG_HOME") {
+            Ok(config_home) if !config_home.is_empty() => {
+                Ok(PathBuf::from(config_home).join("systemd/user"))
+            }
+            _ => match env::var("HOME") {
+                Ok(home) => Ok(PathBuf::from(home).join(".config/systemd/user")),
+                Err(e) => Err(eyre!(
+                    "Unable to read env var HOME! Error: {:?}",
+                    e.to_string()
+                )),
+            },
+        }
+    }
+
+    /// Returns the name of the unit of the schedule called `name`, with the extension `extension`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the schedule
+    /// * `extension` - Either `service` or `timer`
+    pub fn unit_name(name: &str, extension: &str) -> String {
+        format!("{}{}.{}", UNIT_PREFIX, name, extension)
+    }
+
+    /// Returns the contents of the `.service` unit, which runs repoteer once
+    pub fn render_service(&self) -> String {
+        format!(
+            "# Written by `repoteer schedule install`, remove with `repoteer schedule remove {}`
+[Unit]
+Description=repoteer {}
+
+[Service]
+Type=oneshot
+Environment=NO_COLOR=1
+ExecStart={}
+",
+            self.name,
+            self.name,
+            self.argv
+                .iter()
+                .map(|arg| quote_arg(arg))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    }
+
+    /// Returns the contents of the `.timer` unit, which starts the service a minute after the
+    /// timer is started, like after logging in, and then every `self.every`
+    pub fn render_timer(&self) -> String {
+        format!(
+            "# Written by `repoteer schedule install`, remove with `repoteer schedule remove {}`
+[Unit]
+Description=Run repoteer {} every {}
+
+[Timer]
+OnActiveSec=1min
+OnUnitActiveSec={}
+Unit={}
+
+[Install]
+WantedBy=timers.target
+",
+            self.name,
+            self.name,
+            humantime::format_duration(self.every),
+            humantime::format_duration(self.every),
+            Self::unit_name(&self.name, "service")
+        )
+    }
+
+    /// Writes the `.service` and `.timer` units to `dir`, creating it if needed, and returns the
+    /// path of the timer
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory the units are written to, see `Schedule::unit_dir`
+    pub fn write(&self, dir: impl AsRef<Path>) -> Result<PathBuf, Report> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(|e| {
+            eyre!(
+                "Unable to create directory {:?}! Error: {:?}",
+                dir,
+                e.to_string()
+            )
+        })?;
+        let units = [
+            ("service", self.render_service()),
+            ("timer", self.render_timer()),
+        ];
+        for (extension, contents) in units.iter() {
+            let path = dir.join(Self::unit_name(&self.name, extension));
+            fs::write(&path, contents).map_err(|e| {
+                eyre!(
+                    "Unable to write to file {:?}! Error: {:?}",
+                    path,
+                    e.to_string()
+                )
+            })?;
+        }
+        Ok(dir.join(Self::unit_name(&self.name, "timer")))
+    }
+
+    /// Returns every schedule whose units are in `dir`, sorted by name
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the units, see `Schedule::unit_dir`
+    pub fn installed(dir: impl AsRef<Path>) -> Result<Vec<Schedule>, Report> {
+        let dir = dir.as_ref();
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let entries = fs::read_dir(dir).map_err(|e| {
+            eyre!(
+                "Unable to read directory {:?}! Error: {:?}",
+                dir,
+                e.to_string()
+            )
+        })?;
+        let mut schedules = vec![];
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let name = match file_name
+                .strip_prefix(UNIT_PREFIX)
+                .and_then(|name| name.strip_suffix(".timer"))
+            {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            schedules.push(Self::from_units(dir, &name)?);
+        }
+        schedules.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(schedules)
+    }
+
+    /// Reads the schedule called `name` back from its units in `dir`
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the units
+    /// * `name` - Name of the schedule
+    fn from_units(dir: &Path, name: &str) -> Result<Schedule, Report> {
+        let read = |extension: &str| {
+            let path = dir.join(Self::unit_name(name, extension));
+            fs::read_to_string(&path).map_err(|e| {
+                eyre!(
+                    "Unable to read from file {:?}! Error: {:?}",
+                    path,
+                    e.to_string()
+                )
+            })
+        };
+        let value = |contents: &str, key: &str| {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                .map(|value| value.to_string())
+        };
+        let timer = read("timer")?;
+        let every = value(&timer, "OnUnitActiveSec")
+            .and_then(|every| humantime::parse_duration(&every).ok())
+            .ok_or_else(|| eyre!("Timer of schedule {} has no valid OnUnitActiveSec!", name))?;
+        let argv = value(&read("service")?, "ExecStart")
+            .map(|exec_start| split_args(&exec_start))
+            .ok_or_else(|| eyre!("Service of schedule {} has no ExecStart!", name))?;
+        Ok(Schedule {
+            name: name.to_string(),
+            every,
+            argv,
+        })
+    }
+
+    /// Deletes the units of the schedule called `name` from `dir`, and fails if there are none
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the units
+    /// * `name` - Name of the schedule
+    pub fn remove(dir: impl AsRef<Path>, name: &str) -> Result<(), Report> {
+        let dir = dir.as_ref();
+        let paths: Vec<PathBuf> = ["timer", "service"]
+            .iter()
+            .map(|extension| dir.join(Self::unit_name(name, extension)))
+            .filter(|path| path.exists())
+            .collect();
+        if paths.is_empty() {
+            return Err(eyre!("There is no schedule called {} in {:?}!", name, dir));
+        }
+        for path in paths {
+            fs::remove_file(&path).map_err(|e| {
+                eyre!(
+                    "Unable to remove file {:?}! Error: {:?}",
+                    path,
+                    e.to_string()
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `systemctl --user` with `args`, and returns its stdout
+///
+/// # Arguments
+///
+/// * `args` - The arguments passed to `systemctl --user`
+pub fn systemctl(args: &[&str]) -> Result<String, Report> {
+    let output = process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .map_err(|e| eyre!("Unable to run systemctl! Error: {:?}", e.to_string()))?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "systemctl --user {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Returns `arg` as a single argument of an `ExecStart` line, quoted if needed
+///
+/// systemd expands `%` specifiers and `$` variables in `ExecStart`, so both are escaped as well.
+///
+/// # Arguments
+///
+/// * `arg` - The argument being quoted
+fn quote_arg(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty()
+        && !escaped
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';'))
+    {
+        return escaped;
+    }
+    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Splits an `ExecStart` line written with `quote_arg` back into its arguments
+///
+/// # Arguments
+///
+/// * `exec_start` - The value of the `ExecStart` line
+fn split_args(exec_start: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut chars = exec_start.chars().peekable();
+    while chars.peek().is_some() {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut arg = String::new();
+        let (mut quoted, mut was_quoted) = (false, false);
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => (quoted, was_quoted) = (!quoted, true),
+                '\\' if quoted => arg.extend(chars.next()),
+                c if c.is_whitespace() && !quoted => break,
+                c => arg.push(c),
+            }
+        }
+        // an empty argument is written as `""`
+        if !arg.is_empty() || was_quoted {
+            args.push(arg.replace("%%", "%").replace("$$", "$"));
+        }
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule() -> Schedule {
+        Schedule {
+            name: "fetch".to_string(),
+            every: Duration::from_secs(30 * 60),
+            argv: vec![
+                "/usr/bin/repoteer".to_string(),
+                "--manifest".to_string(),
+                "/home/foo/my repos/manifest.toml".to_string(),
+                "--tag".to_string(),
+                "100%".to_string(),
+                "fetch".to_string(),
+            ],
+        }
+    }
+
+    #[test]
+    fn units_run_repoteer_periodically() {
+        let schedule = schedule();
+        assert!(schedule.render_service().contains(
+            "ExecStart=/usr/bin/repoteer --manifest \"/home/foo/my repos/manifest.toml\" --tag 100%% fetch\n"
+        ));
+        let timer = schedule.render_timer();
+        assert!(timer.contains("OnUnitActiveSec=30m\n"));
+        assert!(timer.contains("Unit=repoteer-fetch.service\n"));
+        assert!(timer.contains("WantedBy=timers.target\n"));
+    }
+
+    #[test]
+    fn installed_schedules_are_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let schedule = schedule();
+        let timer = schedule.write(dir.path()).unwrap();
+        assert_eq!(timer, dir.path().join("repoteer-fetch.timer"));
+        assert_eq!(Schedule::installed(dir.path()).unwrap(), vec![schedule]);
+
+        Schedule::remove(dir.path(), "fetch").unwrap();
+        assert!(Schedule::installed(dir.path()).unwrap().is_empty());
+        assert!(Schedule::remove(dir.path(), "fetch").is_err());
+    }
+
+    #[test]
+    fn quoted_args_are_split_back() {
+        let args = [
+            "plain",
+            "with space",
+            "quote\"d",
+            "back\\slash",
+            "$HOME",
+            "",
+        ];
+        let exec_start = args
+            .iter()
+            .map(|arg| quote_arg(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(split_args(&exec_start), args);
+    }
+}
@@ -0,0 +1,23 @@
+use std::{env, path::PathBuf};
+
+use color_eyre::eyre::{eyre, Report};
+
+pub mod history;
+pub mod snapshot;
+
+/// Returns the directory repoteer keeps its state in, which is `$XDG_STATE_HOME/repoteer`, or
+/// `$HOME/.local/state/repoteer` if `XDG_STATE_HOME` is not set
+///
+/// The directory is not created by this function.
+pub fn state_dir() -> Result<PathBuf, Report> {
+    match env::var("XDG_STATE_HOME") {
+        Ok(state_home) if !state_home.is_empty() => Ok(PathBuf::from(state_home).join("repoteer")),
+        _ => match env::var("HOME") {
+            Ok(home) => Ok(PathBuf::from(home).join(".local/state/repoteer")),
+            Err(e) => Err(eyre!(
+                "Unable to read env var HOME! Error: {:?}",
+                e.to_string()
+            )),
+        },
+    }
+}
@@ -0,0 +1,327 @@
+use color_eyre::eyre::{eyre, Report};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use crate::operations::{Outcome, RepoReport};
+
+/// Names of the commands that talk to the remotes of the repos, see `Command::contacts_remote`
+///
+/// Only their runs tell whether a repo is in sync, a successful `status` does not.
+const REMOTE_COMMANDS: [&str; 5] = ["sync", "clone", "pull", "push", "fetch"];
+
+/// The results of the past runs of repoteer, per repo, which `run_operations` records when
+/// `Options::state_dir` is set
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct History {
+    /// The recorded runs of every repo, oldest first, keyed by the name of the repo
+    #[serde(default)]
+    pub repos: BTreeMap<String, Vec<HistoryEntry>>,
+}
+
+/// The result of running a single command on a single repo
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Name of the command, like `sync`
+    pub command: String,
+
+    /// When the command started on the repo, in RFC 3339 format
+    pub started: String,
+
+    /// When the command finished on the repo, in RFC 3339 format
+    pub finished: String,
+
+    /// How the command ended
+    pub outcome: Outcome,
+
+    /// Failures of single steps that did not stop the command as a whole
+    #[serde(default)]
+    pub problems: Vec<String>,
+
+    /// The checkouts whose HEAD the command moved
+    #[serde(default)]
+    pub commits: Vec<CommitRange>,
+}
+
+/// How the HEAD of a single checkout moved during a command
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommitRange {
+    /// Location of the checkout, as reported by `git worktree list`
+    pub path: String,
+
+    /// The commit HEAD pointed to before the command, `None` if the checkout did not exist
+    pub before: Option<String>,
+
+    /// The commit HEAD pointed to after the command, `None` if the checkout was removed
+    pub after: Option<String>,
+}
+
+impl History {
+    /// How many runs are kept per repo, older ones are dropped
+    pub const MAX_ENTRIES: usize = 100;
+
+    /// Returns the path of the history file below `state_dir`
+    ///
+    /// # Arguments
+    ///
+    /// * `state_dir` - The directory repoteer keeps its state in, see `state::state_dir`
+    pub fn path(state_dir: impl AsRef<Path>) -> PathBuf {
+        state_dir.as_ref().join("history.json")
+    }
+
+    /// Returns the `History` stored at `json_path`, or an empty one if the file does not exist
+    ///
+    /// # Arguments
+    ///
+    /// * `json_path` - Path pointing to the history file
+    pub fn load(json_path: impl AsRef<Path>) -> Result<Self, Report> {
+        let json_path = json_path.as_ref();
+        if !json_path.exists() {
+            return Ok(History::default());
+        }
+        let json_str = fs::read_to_string(json_path).map_err(|e| {
+            eyre!(
+                "Unable to read from file {:?}! Error: {:?}",
+                json_path,
+                e.to_string()
+            )
+        })?;
+        serde_json::from_str(&json_str).map_err(|e| {
+            eyre!(
+                "Unable to parse {:?} to History instance! Error: {:?}",
+                json_path,
+                e.to_string()
+            )
+        })
+    }
+
+    /// Writes the history to `json_path`, creating its directory if needed
+    ///
+    /// The file is replaced in one step, so that an interrupted write does not lose the history.
+    ///
+    /// # Arguments
+    ///
+    /// * `json_path` - Path the history is written to
+    pub fn write(&self, json_path: impl AsRef<Path>) -> Result<(), Report> {
+        let json_path = json_path.as_ref();
+        let json_str = serde_json::to_string(self).map_err(|e| {
+            eyre!(
+                "Unable to serialize History instance to JSON! Error: {:?}",
+                e.to_string()
+            )
+        })?;
+        if let Some(dir) = json_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                eyre!(
+                    "Unable to create directory {:?}! Error: {:?}",
+                    dir,
+                    e.to_string()
+                )
+            })?;
+        }
+        let tmp_path = json_path.with_extension("json.tmp");
+        fs::write(&tmp_path, json_str)
+            .and_then(|_| fs::rename(&tmp_path, json_path))
+            .map_err(|e| {
+                eyre!(
+                    "Unable to write to file {:?}! Error: {:?}",
+                    json_path,
+                    e.to_string()
+                )
+            })
+    }
+
+    /// Records the results of `command` in the history file at `json_path`
+    ///
+    /// The file is locked from loading it to writing it back, so that runs finishing at the same
+    /// time, like `watch` and a scheduled sync, do not drop each other's entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `json_path` - Path pointing to the history file
+    /// * `command` - Name of the command, like `sync`
+    /// * `reports` - The `RepoReport`s of the repos the command ran on
+    pub fn record_all(
+        json_path: impl AsRef<Path>,
+        command: &str,
+        reports: &[RepoReport],
+    ) -> Result<(), Report> {
+        let json_path = json_path.as_ref();
+        if let Some(dir) = json_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                eyre!(
+                    "Unable to create directory {:?}! Error: {:?}",
+                    dir,
+                    e.to_string()
+                )
+            })?;
+        }
+        let lock_path = json_path.with_extension("json.lock");
+        // the lock is released when the file is closed
+        let _lock = File::create(&lock_path)
+            .and_then(|file| file.lock().map(|_| file))
+            .map_err(|e| {
+                eyre!(
+                    "Unable to lock file {:?}! Error: {:?}",
+                    lock_path,
+                    e.to_string()
+                )
+            })?;
+        let mut history = History::load(json_path)?;
+        for report in reports.iter() {
+            history.record(command, report);
+        }
+        history.write(json_path)
+    }
+
+    /// Records the result of `command` for the repo of `report`, and drops its oldest runs beyond
+    /// `MAX_ENTRIES`
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Name of the command, like `sync`
+    /// * `report` - The `RepoReport` of the repo
+    pub fn record(&mut self, command: &str, report: &RepoReport) {
+        let entries = self.repos.entry(report.name.clone()).or_default();
+        entries.push(HistoryEntry {
+            command: command.to_string(),
+            started: humantime::format_rfc3339_seconds(report.started).to_string(),
+            finished: humantime::format_rfc3339_seconds(report.finished).to_string(),
+            outcome: report.outcome.clone(),
+            problems: report.problems.clone(),
+            commits: report.commits.clone(),
+        });
+        if entries.len() > Self::MAX_ENTRIES {
+            entries.drain(..entries.len() - Self::MAX_ENTRIES);
+        }
+    }
+
+    /// Returns the last run of the repo called `name` that talked to its remote and succeeded
+    /// without problems
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the repo, see `Repo::name`
+    pub fn last_success(&self, name: &str) -> Option<&HistoryEntry> {
+        self.repos
+            .get(name)?
+            .iter()
+            .rev()
+            .find(|entry| entry.contacts_remote() && entry.is_success())
+    }
+
+    /// Returns the runs of the repo called `name` that talked to its remote and failed since its
+    /// last success, oldest first
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the repo, see `Repo::name`
+    pub fn failing(&self, name: &str) -> Vec<&HistoryEntry> {
+        let entries: Vec<&HistoryEntry> = self
+            .repos
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(|entry| entry.contacts_remote())
+            .collect();
+        let first_failure = entries
+            .iter()
+            .rposition(|entry| entry.is_success())
+            .map_or(0, |last_success| last_success + 1);
+        entries[first_failure..].to_vec()
+    }
+}
+
+impl HistoryEntry {
+    /// Whether the run succeeded without any problems
+    pub fn is_success(&self) -> bool {
+        self.outcome == Outcome::Success && self.problems.is_empty()
+    }
+
+    /// Whether the command of the run talked to the remote of the repo
+    pub fn contacts_remote(&self) -> bool {
+        REMOTE_COMMANDS.contains(&self.command.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn report(outcome: Outcome, at: u64) -> RepoReport {
+        let started = SystemTime::UNIX_EPOCH + Duration::from_secs(at);
+        RepoReport {
+            name: "api".to_string(),
+            url: "git@github.com:company/api.git".to_string(),
+            path: "/home/foo/work/api".to_string(),
+            outcome,
+            problems: vec![],
+            retries: vec![],
+            lock: None,
+            snapshot: None,
+            started,
+            finished: started + Duration::from_secs(1),
+            commits: vec![],
+            incoming: vec![],
+            diverged: vec![],
+        }
+    }
+
+    #[test]
+    fn failing_runs_are_counted_since_last_success() {
+        let mut history = History::default();
+        history.record("sync", &report(Outcome::Failure("old".to_string()), 0));
+        history.record("sync", &report(Outcome::Success, 60));
+        history.record("pull", &report(Outcome::Failure("a".to_string()), 120));
+        history.record("sync", &report(Outcome::Cancelled, 180));
+        // read-only commands neither end nor extend the failures
+        history.record("status", &report(Outcome::Success, 240));
+        history.record("status", &report(Outcome::Error("b".to_string()), 300));
+
+        let last_success = history.last_success("api").unwrap();
+        assert_eq!(last_success.started, "1970-01-01T00:01:00Z");
+        assert_eq!(last_success.finished, "1970-01-01T00:01:01Z");
+        let failing = history.failing("api");
+        assert_eq!(failing.len(), 2);
+        assert_eq!(failing[0].command, "pull");
+        assert!(history.failing("other").is_empty());
+    }
+
+    #[test]
+    fn concurrent_runs_keep_each_others_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = History::path(dir.path());
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let mut report = report(Outcome::Success, i);
+                    report.name = format!("repo{}", i);
+                    History::record_all(&path, "sync", &[report]).unwrap();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(History::load(&path).unwrap().repos.len(), 8);
+    }
+
+    #[test]
+    fn old_runs_are_dropped() {
+        let mut history = History::default();
+        for at in 0..History::MAX_ENTRIES as u64 + 5 {
+            history.record("sync", &report(Outcome::Success, at));
+        }
+        let entries = &history.repos["api"];
+        assert_eq!(entries.len(), History::MAX_ENTRIES);
+        assert_eq!(entries[0].started, "1970-01-01T00:00:05Z");
+
+        let json_str = serde_json::to_string(&history).unwrap();
+        assert_eq!(serde_json::from_str::<History>(&json_str).unwrap(), history);
+    }
+}
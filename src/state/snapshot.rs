@@ -0,0 +1,176 @@
+use color_eyre::eyre::{eyre, Report};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::operations::status::DirtyStatus;
+
+/// The state of the repos at one point in time, written by `repoteer snapshot` and restored by
+/// `repoteer restore`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Name of the snapshot, which is also the name of its file
+    pub name: String,
+
+    /// When the snapshot was taken, in RFC 3339 format
+    pub created: String,
+
+    /// The state of every repo in the snapshot
+    pub repos: Vec<RepoSnapshot>,
+}
+
+/// The state of a single repo in a `Snapshot`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RepoSnapshot {
+    /// Name of the repo, see `Repo::name`
+    pub name: String,
+
+    /// URL of the repo's remote
+    pub url: String,
+
+    /// The commit every local branch pointed to
+    pub branches: BTreeMap<String, String>,
+
+    /// Every checkout of the repo, which is the repo itself, or the worktrees of a bare repo
+    pub checkouts: Vec<CheckoutSnapshot>,
+
+    /// The stashes of the repo, newest first
+    pub stashes: Vec<StashSnapshot>,
+}
+
+/// The state of a single checkout in a `RepoSnapshot`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CheckoutSnapshot {
+    /// Location of the checkout, as reported by `git worktree list`
+    pub path: String,
+
+    /// The branch that was checked out, `None` if HEAD was detached
+    pub branch: Option<String>,
+
+    /// The commit HEAD pointed to
+    pub head: String,
+
+    /// The local changes of the checkout, which are recorded, but not restored
+    pub dirty: DirtyStatus,
+}
+
+/// A single stash in a `RepoSnapshot`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StashSnapshot {
+    /// The commit of the stash, which can be applied with `git stash apply` as long as it exists
+    pub commit: String,
+
+    /// The message of the stash
+    pub message: String,
+}
+
+impl Snapshot {
+    /// Returns the directory snapshots are kept in, below `state_dir`
+    ///
+    /// # Arguments
+    ///
+    /// * `state_dir` - The directory repoteer keeps its state in, see `state::state_dir`
+    pub fn dir(state_dir: impl AsRef<Path>) -> PathBuf {
+        state_dir.as_ref().join("snapshots")
+    }
+
+    /// Returns the path of the snapshot called `name`, and fails if `name` cannot be used as a
+    /// file name
+    ///
+    /// # Arguments
+    ///
+    /// * `state_dir` - The directory repoteer keeps its state in, see `state::state_dir`
+    /// * `name` - Name of the snapshot
+    pub fn path(state_dir: impl AsRef<Path>, name: &str) -> Result<PathBuf, Report> {
+        if name.is_empty() || name.starts_with('.') || name.contains('/') {
+            return Err(eyre!(
+                "{:?} is not a valid snapshot name, it must not be empty, start with a dot or contain a slash!",
+                name
+            ));
+        }
+        Ok(Self::dir(state_dir).join(format!("{}.json", name)))
+    }
+
+    /// Returns a `Result<snapshot::Snapshot, Report>` from a JSON file
+    ///
+    /// # Arguments
+    ///
+    /// * `json_path` - Path pointing to the snapshot
+    pub fn from_json_file(json_path: impl AsRef<Path>) -> Result<Self, Report> {
+        let json_path = json_path.as_ref();
+        let json_str = fs::read_to_string(json_path).map_err(|e| {
+            eyre!(
+                "Unable to read from file {:?}! Error: {:?}",
+                json_path,
+                e.to_string()
+            )
+        })?;
+        serde_json::from_str(&json_str).map_err(|e| {
+            eyre!(
+                "Unable to parse {:?} to Snapshot instance! Error: {:?}",
+                json_path,
+                e.to_string()
+            )
+        })
+    }
+
+    /// Writes the snapshot to `json_path`, creating its directory if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `json_path` - Path the snapshot is written to
+    pub fn write(&self, json_path: impl AsRef<Path>) -> Result<(), Report> {
+        let json_path = json_path.as_ref();
+        let json_str = serde_json::to_string_pretty(self).map_err(|e| {
+            eyre!(
+                "Unable to serialize Snapshot instance to JSON! Error: {:?}",
+                e.to_string()
+            )
+        })?;
+        if let Some(dir) = json_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                eyre!(
+                    "Unable to create directory {:?}! Error: {:?}",
+                    dir,
+                    e.to_string()
+                )
+            })?;
+        }
+        fs::write(json_path, json_str).map_err(|e| {
+            eyre!(
+                "Unable to write to file {:?}! Error: {:?}",
+                json_path,
+                e.to_string()
+            )
+        })
+    }
+
+    /// Returns the state of the repo called `name`, if it is part of the snapshot
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the repo, see `Repo::name`
+    pub fn get(&self, name: &str) -> Option<&RepoSnapshot> {
+        self.repos.iter().find(|repo| repo.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_names_must_be_file_names() {
+        let state_dir = Path::new("/home/foo/.local/state/repoteer");
+        assert_eq!(
+            Snapshot::path(state_dir, "before-rebase").unwrap(),
+            PathBuf::from("/home/foo/.local/state/repoteer/snapshots/before-rebase.json")
+        );
+        for name in ["", "../escape", ".hidden", "a/b"] {
+            assert!(Snapshot::path(state_dir, name).is_err(), "{}", name);
+        }
+    }
+}
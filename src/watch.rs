@@ -0,0 +1,274 @@
+use color_eyre::Result;
+use colored::*;
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    sync::{mpsc::unbounded_channel, Notify},
+    time::{self, MissedTickBehavior},
+};
+
+use self::{backoff::Backoff, refs::RefWatcher};
+use crate::{
+    cli::render::{render_events, OutputFormat},
+    notify::notify,
+    operations::run_operations_with_events,
+    Command, Manifest, Options, RepoFilter, RepoReport,
+};
+
+pub mod backoff;
+
+#[cfg(target_os = "linux")]
+mod refs;
+
+#[cfg(not(target_os = "linux"))]
+mod refs {
+    use color_eyre::{eyre::bail, Result};
+
+    use crate::Repo;
+
+    /// Stands in for the inotify based `RefWatcher`, which is only available on Linux
+    pub struct RefWatcher;
+
+    impl RefWatcher {
+        pub fn new() -> Result<Self> {
+            bail!("--push-on-commit needs inotify, which is only available on Linux!")
+        }
+
+        pub fn add(&mut self, _repo: &Repo) -> Result<()> {
+            Ok(())
+        }
+
+        pub async fn changed(&mut self) -> Result<String> {
+            std::future::pending().await
+        }
+
+        pub fn discard_pending(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+}
+
+/// Settings of `Command::Watch`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchOptions {
+    /// How long to wait between syncs
+    pub interval: Duration,
+
+    /// Whether repos are pushed soon after a commit lands on one of their local branches
+    pub push_on_commit: bool,
+
+    /// How long the branches of a repo need to stay unchanged before they are pushed
+    pub push_delay: Duration,
+
+    /// The longest a failing repo waits for its next sync
+    pub max_backoff: Duration,
+}
+
+/// Resolves once SIGTERM or Ctrl-C is received
+#[cfg(unix)]
+fn shutdown_signal() -> Result<impl Future<Output = ()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+    Ok(async move {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = sigterm.recv() => {}
+        }
+    })
+}
+
+/// Resolves once Ctrl-C is received, since there is no SIGTERM outside of unix
+#[cfg(not(unix))]
+fn shutdown_signal() -> Result<impl Future<Output = ()>> {
+    Ok(async {
+        let _ = tokio::signal::ctrl_c().await;
+    })
+}
+
+/// Syncs the repos of `manifest` every `watch.interval`, until SIGTERM or Ctrl-C is received
+///
+/// Repos whose syncs keep failing are held back from the following syncs, see `Backoff`. With
+/// `watch.push_on_commit`, the local branches of every cloned repo are watched, and a repo is
+/// pushed once its branches stayed unchanged for `watch.push_delay`. Branches moved while a command
+/// runs, like by pulling, do not cause a push. A signal cancels the running command, and returns
+/// once its repos are reported.
+///
+/// # Arguments
+///
+/// * `manifest` - The `Manifest` of the repos being synced
+/// * `options` - The `Options` every command runs with
+/// * `watch` - The `WatchOptions` deciding when the repos are synced and pushed
+/// * `output` - The `OutputFormat` the commands are reported in
+pub async fn watch(
+    manifest: Manifest,
+    options: Options,
+    watch: WatchOptions,
+    output: OutputFormat,
+) -> Result<()> {
+    let shutdown = Arc::new(Notify::new());
+    let signalled = shutdown_signal()?;
+    let (cancel, notify) = (options.cancel.clone(), shutdown.clone());
+    tokio::spawn(async move {
+        signalled.await;
+        cancel.cancel();
+        notify.notify_one();
+    });
+
+    let mut refs = match watch.push_on_commit {
+        true => Some(RefWatcher::new()?),
+        false => None,
+    };
+    let mut backoff = Backoff::new(watch.interval, watch.max_backoff);
+    let mut pushes: BTreeMap<String, Instant> = BTreeMap::new();
+    let mut ticker = time::interval(watch.interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    while !options.cancel.is_cancelled() {
+        // repos cloned by the last sync are watched from now on
+        if let Some(refs) = refs.as_mut() {
+            for repo in manifest.repos.iter() {
+                refs.add(repo)?;
+            }
+        }
+        let next_push = pushes.values().min().copied();
+        tokio::select! {
+            _ = shutdown.notified() => break,
+            tick = ticker.tick() => {
+                // failing repos are held back for whole intervals, counted from the tick
+                let tick = tick.into_std();
+                let names = due_repos(&manifest, &backoff, tick, output);
+                let reports = run(Command::Sync, &names, &manifest, &options, output).await?;
+                for report in reports.iter() {
+                    backoff.record(report, tick);
+                }
+                // syncing pushed the repos already
+                pushes.retain(|name, _| !names.contains(name));
+                discard_pending(&mut refs)?;
+            }
+            name = changed(&mut refs) => {
+                pushes.insert(name?, Instant::now() + watch.push_delay);
+            }
+            _ = time::sleep_until(next_push.unwrap_or_else(Instant::now).into()), if next_push.is_some() => {
+                let now = Instant::now();
+                let names: Vec<String> = pushes
+                    .iter()
+                    .filter(|(_, push_at)| **push_at <= now)
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                pushes.retain(|_, push_at| *push_at > now);
+                run(Command::Push, &names, &manifest, &options, output).await?;
+                discard_pending(&mut refs)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the names of the repos that are synced next, leaving out those held back by `backoff`
+///
+/// # Arguments
+///
+/// * `manifest` - The `Manifest` of the repos being synced
+/// * `backoff` - The `Backoff` of the failing repos
+/// * `now` - When the sync runs
+/// * `output` - The `OutputFormat` deciding whether held back repos are printed
+fn due_repos(
+    manifest: &Manifest,
+    backoff: &Backoff,
+    now: Instant,
+    output: OutputFormat,
+) -> Vec<String> {
+    let mut names = vec![];
+    for name in manifest.repos.iter().map(|repo| repo.name()) {
+        match backoff.held_back(&name, now) {
+            Some(failing) if output.is_human_readable() => println!(
+                "{}: {} after {} failed syncs in a row, syncing again at {}",
+                name.yellow(),
+                "Held back".bright_red(),
+                failing.failures,
+                humantime::format_rfc3339_seconds(
+                    SystemTime::now() + failing.retry_at.saturating_duration_since(Instant::now())
+                )
+            ),
+            Some(_) => {}
+            None => names.push(name),
+        }
+    }
+    names
+}
+
+/// Runs `command` on the repos of `manifest` called `names`, and returns their `RepoReport`s
+///
+/// # Arguments
+///
+/// * `command` - The `Command` being run
+/// * `names` - Names of the repos the command runs on
+/// * `manifest` - The `Manifest` holding the repos
+/// * `options` - The `Options` the command runs with
+/// * `output` - The `OutputFormat` the command is reported in
+async fn run(
+    command: Command,
+    names: &[String],
+    manifest: &Manifest,
+    options: &Options,
+    output: OutputFormat,
+) -> Result<Vec<RepoReport>> {
+    if names.is_empty() {
+        return Ok(vec![]);
+    }
+    let manifest = manifest.clone().filter(&RepoFilter {
+        names: names.to_vec(),
+        tags: vec![],
+    })?;
+    if output.is_human_readable() {
+        println!(
+            "{} Running {} on {} repos\n",
+            humantime::format_rfc3339_seconds(SystemTime::now()),
+            command.name().yellow(),
+            manifest.repos.len()
+        );
+    }
+    let (events, receiver) = unbounded_channel();
+    let renderer = tokio::spawn(render_events(
+        receiver,
+        output.renderer(manifest.repos.len()),
+    ));
+    let reports =
+        run_operations_with_events(command.clone(), manifest, options.clone(), events).await?;
+    renderer.await?;
+    if !options.dry_run {
+        if let Err(e) = notify(&options.notifications, &command, &reports) {
+            eprintln!("{} {}", "Failed to notify!".bright_red(), e);
+        }
+    }
+    Ok(reports)
+}
+
+/// Waits until a branch of one of the repos watched by `refs` moves, and returns the name of that
+/// repo, or waits forever if no repos are watched
+///
+/// # Arguments
+///
+/// * `refs` - The `RefWatcher`, if `WatchOptions::push_on_commit` is set
+async fn changed(refs: &mut Option<RefWatcher>) -> Result<String> {
+    match refs {
+        Some(refs) => refs.changed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Discards the branch moves `refs` already received, which were caused by the last command
+///
+/// # Arguments
+///
+/// * `refs` - The `RefWatcher`, if `WatchOptions::push_on_commit` is set
+fn discard_pending(refs: &mut Option<RefWatcher>) -> Result<()> {
+    match refs {
+        Some(refs) => refs.discard_pending(),
+        None => Ok(()),
+    }
+}
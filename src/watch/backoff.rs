@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::operations::{Outcome, RepoReport};
+
+/// Keeps track of the repos whose syncs keep failing, and holds them back from the next syncs
+///
+/// After `n` failures in a row, a repo is synced again once `interval * 2^n` have passed, but
+/// never later than `max` after its last failure. A successful sync resets the backoff.
+#[derive(Debug)]
+pub struct Backoff {
+    interval: Duration,
+    max: Duration,
+    failing: HashMap<String, Failing>,
+}
+
+/// A repo whose last syncs failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failing {
+    /// How many syncs of the repo failed in a row
+    pub failures: u32,
+
+    /// When the repo is synced again
+    pub retry_at: Instant,
+}
+
+impl Backoff {
+    /// Returns a `Backoff` without any failing repos
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - How long `watch` waits between syncs
+    /// * `max` - The longest a failing repo waits for its next sync
+    pub fn new(interval: Duration, max: Duration) -> Self {
+        Backoff {
+            interval,
+            max,
+            failing: HashMap::new(),
+        }
+    }
+
+    /// Returns how long a repo waits for its next sync after `failures` failures in a row
+    ///
+    /// # Arguments
+    ///
+    /// * `failures` - How many syncs of the repo failed in a row
+    pub fn delay(&self, failures: u32) -> Duration {
+        self.interval
+            .checked_mul(2u32.saturating_pow(failures))
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+
+    /// Records the result of syncing the repo of `report`
+    ///
+    /// Cancelled syncs are not counted, since they say nothing about the repo.
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - The `RepoReport` of the sync
+    /// * `now` - When the sync started
+    pub fn record(&mut self, report: &RepoReport, now: Instant) {
+        match report.outcome {
+            Outcome::Success => {
+                self.failing.remove(&report.name);
+            }
+            Outcome::Cancelled => {}
+            _ => {
+                let failures = self
+                    .failing
+                    .get(&report.name)
+                    .map_or(1, |failing| failing.failures + 1);
+                self.failing.insert(
+                    report.name.clone(),
+                    Failing {
+                        failures,
+                        retry_at: now + self.delay(failures),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Returns the `Failing` state of the repo called `name`, if it is held back at `now`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the repo, see `Repo::name`
+    /// * `now` - When the next sync runs
+    pub fn held_back(&self, name: &str, now: Instant) -> Option<Failing> {
+        self.failing
+            .get(name)
+            .filter(|failing| failing.retry_at > now)
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn report(outcome: Outcome) -> RepoReport {
+        RepoReport {
+            name: "api".to_string(),
+            url: "git@github.com:company/api.git".to_string(),
+            path: "/home/foo/work/api".to_string(),
+            outcome,
+            problems: vec![],
+            retries: vec![],
+            lock: None,
+            snapshot: None,
+            started: SystemTime::UNIX_EPOCH,
+            finished: SystemTime::UNIX_EPOCH,
+            commits: vec![],
+            incoming: vec![],
+            diverged: vec![],
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_max() {
+        let backoff = Backoff::new(15 * MINUTE, 120 * MINUTE);
+        assert_eq!(backoff.delay(1), 30 * MINUTE);
+        assert_eq!(backoff.delay(2), 60 * MINUTE);
+        assert_eq!(backoff.delay(3), 120 * MINUTE);
+        assert_eq!(backoff.delay(4), 120 * MINUTE);
+        assert_eq!(backoff.delay(200), 120 * MINUTE);
+    }
+
+    #[test]
+    fn failing_repos_are_held_back_until_success() {
+        let mut backoff = Backoff::new(15 * MINUTE, 120 * MINUTE);
+        let start = Instant::now();
+        backoff.record(&report(Outcome::Failure("down".to_string())), start);
+        backoff.record(&report(Outcome::Cancelled), start);
+        assert_eq!(
+            backoff.held_back("api", start + 15 * MINUTE),
+            Some(Failing {
+                failures: 1,
+                retry_at: start + 30 * MINUTE
+            })
+        );
+        assert_eq!(backoff.held_back("api", start + 30 * MINUTE), None);
+        assert_eq!(backoff.held_back("other", start), None);
+
+        let later = start + 30 * MINUTE;
+        backoff.record(&report(Outcome::Error("down".to_string())), later);
+        assert_eq!(
+            backoff
+                .held_back("api", later)
+                .map(|failing| failing.failures),
+            Some(2)
+        );
+        backoff.record(&report(Outcome::Success), later);
+        assert_eq!(backoff.held_back("api", later), None);
+    }
+}
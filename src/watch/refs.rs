@@ -0,0 +1,132 @@
+use color_eyre::{eyre::eyre, Result};
+use futures::{FutureExt, StreamExt};
+use inotify::{EventMask, EventOwned, EventStream, Inotify, WatchDescriptor, WatchMask};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::Repo;
+
+/// Watches the local branches of repos with inotify, to find out when commits land on them
+pub struct RefWatcher {
+    events: EventStream<[u8; 4096]>,
+
+    /// The repo and the directory below `refs/heads` every watch belongs to
+    dirs: HashMap<WatchDescriptor, (String, PathBuf)>,
+
+    /// Names of the repos whose branches are watched
+    repos: HashSet<String>,
+}
+
+impl RefWatcher {
+    /// Returns a `RefWatcher` that does not watch any repo yet
+    pub fn new() -> Result<Self> {
+        let events = Inotify::init()
+            .and_then(|inotify| inotify.into_event_stream([0; 4096]))
+            .map_err(|e| eyre!("Unable to initialize inotify! Error: {:?}", e.to_string()))?;
+        Ok(RefWatcher {
+            events,
+            dirs: HashMap::new(),
+            repos: HashSet::new(),
+        })
+    }
+
+    /// Starts watching the local branches of `repo`, unless they are watched already, or the repo
+    /// has not been cloned yet
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The `Repo` being watched
+    pub fn add(&mut self, repo: &Repo) -> Result<()> {
+        let name = repo.name();
+        let heads = match repo.is_bare() {
+            true => Path::new(&repo.path).join("refs/heads"),
+            false => Path::new(&repo.path).join(".git/refs/heads"),
+        };
+        if self.repos.contains(&name) || !heads.is_dir() {
+            return Ok(());
+        }
+        self.add_dir(&name, heads)?;
+        self.repos.insert(name);
+        Ok(())
+    }
+
+    /// Watches `dir` and every directory below it, which hold the branches with slashes in their
+    /// names
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the repo
+    /// * `dir` - The directory being watched
+    fn add_dir(&mut self, name: &str, dir: PathBuf) -> Result<()> {
+        let wd = self
+            .events
+            .watches()
+            .add(
+                &dir,
+                WatchMask::MOVED_TO | WatchMask::CREATE | WatchMask::ONLYDIR,
+            )
+            .map_err(|e| eyre!("Unable to watch {:?}! Error: {:?}", dir, e.to_string()))?;
+        let subdirs: Vec<PathBuf> = fs::read_dir(&dir)
+            .map_err(|e| eyre!("Unable to read {:?}! Error: {:?}", dir, e.to_string()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+        self.dirs.insert(wd, (name.to_string(), dir));
+        for subdir in subdirs {
+            self.add_dir(name, subdir)?;
+        }
+        Ok(())
+    }
+
+    /// Waits until a branch of one of the watched repos points to a new commit, and returns the
+    /// name of that repo
+    pub async fn changed(&mut self) -> Result<String> {
+        loop {
+            let event = self.events.next().await;
+            if let Some(name) = self.handle(event)? {
+                return Ok(name);
+            }
+        }
+    }
+
+    /// Discards the changed branches that are already queued, like those moved by pulling the
+    /// repos, but keeps watching the directories created in the meantime
+    pub fn discard_pending(&mut self) -> Result<()> {
+        while let Some(event) = self.events.next().now_or_never() {
+            self.handle(event)?;
+        }
+        Ok(())
+    }
+
+    /// Watches the directory created in `event`, or returns the name of the repo if `event` moved
+    /// one of its branches
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The next item of the inotify event stream
+    fn handle(&mut self, event: Option<io::Result<EventOwned>>) -> Result<Option<String>> {
+        let event = match event {
+            Some(event) => event
+                .map_err(|e| eyre!("Unable to read inotify events! Error: {:?}", e.to_string()))?,
+            None => return Err(eyre!("The inotify event stream ended!")),
+        };
+        let (name, path) = match (self.dirs.get(&event.wd), event.name) {
+            (Some((name, dir)), Some(file)) => (name.clone(), dir.join(file)),
+            _ => return Ok(None),
+        };
+        if event.mask.contains(EventMask::CREATE | EventMask::ISDIR) {
+            self.add_dir(&name, path)?;
+            Ok(None)
+        } else if event.mask.contains(EventMask::MOVED_TO)
+            && path.extension().is_none_or(|extension| extension != "lock")
+        {
+            // git writes a branch to `<branch>.lock` first, and then moves it into place
+            Ok(Some(name))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,1374 @@
+//! End to end tests that run repoteer against local bare repositories as remotes
+
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpListener,
+    thread,
+    time::Duration,
+};
+
+use harness::{assert_no_failures, Sandbox};
+use repoteer::{
+    operations::{backend::BackendKind, retry::RetryPolicy},
+    run_operations, Command, Manifest, Options, Outcome,
+};
+
+#[path = "integration/harness.rs"]
+mod harness;
+
+fn plain_manifest(sandbox: &Sandbox, url: &str) {
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\n",
+        url,
+        sandbox.path("checkout").display()
+    ));
+}
+
+fn bare_manifest(sandbox: &Sandbox, url: &str) {
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\nis_bare = true\nworktrees = [\"main\", \"dev\"]\n",
+        url,
+        sandbox.path("bare").display()
+    ));
+}
+
+fn clone_creates_checkout(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+
+    let stdout = sandbox.run(&["clone"]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("Clone complete!"));
+    assert!(sandbox.path("checkout").join("README").exists());
+}
+
+fn sync_clones_and_then_updates_existing_checkout(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+
+    let stdout = sandbox.run(&["sync"]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("Sync complete!"));
+
+    // a second sync must not try to clone into the existing checkout again
+    sandbox.push_upstream("origin", "main", "README", "updated\n");
+    let stdout = sandbox.run(&["sync"]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("Sync complete!"));
+    assert_eq!(
+        fs::read_to_string(sandbox.path("checkout").join("README")).unwrap(),
+        "updated\n"
+    );
+}
+
+#[test]
+fn sync_is_the_default_command() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+
+    let stdout = sandbox.run(&[]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("Sync complete!"));
+}
+
+fn pull_fast_forwards_checkout(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+
+    let stdout = sandbox.run(&["pull"]);
+    assert_no_failures(&stdout);
+    let checkout = sandbox.path("checkout");
+    assert_eq!(
+        sandbox.rev_parse(&checkout, "HEAD"),
+        sandbox.rev_parse(&sandbox.path("origin.git"), "main")
+    );
+}
+
+fn pull_leaves_branches_that_are_not_checked_out_alone(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &["feature"]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    sandbox.git(&checkout, &["branch", "feature", "origin/feature"]);
+    let feature = sandbox.rev_parse(&checkout, "feature");
+    sandbox.push_upstream("origin", "feature", "feature.txt", "feature\n");
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+
+    let stdout = sandbox.run(&["pull"]);
+    assert_no_failures(&stdout);
+    // pulling `feature` into the checkout of `main` would have merged it
+    assert_eq!(
+        sandbox.rev_parse(&checkout, "HEAD"),
+        sandbox.rev_parse(&sandbox.path("origin.git"), "main")
+    );
+    assert!(!checkout.join("feature.txt").exists());
+    assert_eq!(sandbox.rev_parse(&checkout, "feature"), feature);
+}
+
+fn credentials_come_from_the_credential_helper(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    sandbox.remote("origin", &[]);
+    let url = sandbox.serve_with_credentials("origin", "repoteer", "secret");
+    let credentials = sandbox.path("credentials");
+    fs::write(
+        &credentials,
+        url.replace("http://", "http://repoteer:secret@")
+            .replace("/origin.git", "\n"),
+    )
+    .unwrap();
+    // git2 runs helpers containing a slash as a path, so the shell form is used
+    sandbox.credential_helper(&format!(
+        "!git credential-store --file={}",
+        credentials.display()
+    ));
+    plain_manifest(&sandbox, &url);
+
+    let stdout = sandbox.run(&["clone"]);
+    assert_no_failures(&stdout);
+    assert!(sandbox.path("checkout").join("README").exists());
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+    let stdout = sandbox.run(&["fetch"]);
+    assert_no_failures(&stdout);
+    assert_eq!(
+        sandbox.rev_parse(&sandbox.path("checkout"), "origin/main"),
+        sandbox.rev_parse(&sandbox.path("origin.git"), "main")
+    );
+}
+
+fn push_publishes_local_commits(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    sandbox.commit(&checkout, "local.txt", "local\n");
+
+    let stdout = sandbox.run(&["push"]);
+    assert_no_failures(&stdout);
+    assert_eq!(
+        sandbox.rev_parse(&sandbox.path("origin.git"), "main"),
+        sandbox.rev_parse(&checkout, "HEAD")
+    );
+}
+
+fn bare_repo_worktrees_are_created_and_pulled(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &["dev"]);
+    bare_manifest(&sandbox, &url);
+
+    let stdout = sandbox.run(&["clone"]);
+    assert_no_failures(&stdout);
+    let bare = sandbox.path("bare");
+    assert!(bare.join("main").join("README").exists());
+    assert!(bare.join("dev").join("README").exists());
+
+    sandbox.push_upstream("origin", "dev", "dev.txt", "dev\n");
+    let stdout = sandbox.run(&["pull"]);
+    assert_no_failures(&stdout);
+    assert!(bare.join("dev").join("dev.txt").exists());
+    assert!(!bare.join("main").join("dev.txt").exists());
+}
+
+fn sync_adds_worktrees_declared_later(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &["dev"]);
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\nis_bare = true\nworktrees = [\"main\"]\n",
+        url,
+        sandbox.path("bare").display()
+    ));
+    sandbox.run(&["clone"]);
+    assert!(!sandbox.path("bare").join("dev").exists());
+
+    bare_manifest(&sandbox, &url);
+    let stdout = sandbox.run(&["sync"]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("added worktree for branch dev"));
+    assert!(sandbox.path("bare").join("dev").join("README").exists());
+}
+
+fn sync_adds_worktrees_of_branches_created_upstream_after_the_clone(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &["dev"]);
+    bare_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    sandbox.git(
+        &sandbox.path("origin-seed"),
+        &["push", "origin", "main:feature"],
+    );
+    sandbox.push_upstream("origin", "feature", "feature.txt", "feature\n");
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\nis_bare = true\nworktrees = [\"main\", \"dev\", \"feature\"]\n",
+        url,
+        sandbox.path("bare").display()
+    ));
+
+    let stdout = sandbox.run(&["sync"]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("added worktree for branch feature"));
+    let bare = sandbox.path("bare");
+    assert!(bare.join("feature").join("feature.txt").exists());
+    assert_eq!(
+        sandbox.rev_parse(&bare, "refs/remotes/origin/feature"),
+        sandbox.rev_parse(&sandbox.path("origin.git"), "feature")
+    );
+}
+
+fn fetch_reports_bare_worktrees_behind_origin(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &["dev"]);
+    bare_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    sandbox.push_upstream("origin", "dev", "dev.txt", "dev\n");
+
+    let stdout = sandbox.run(&["fetch"]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("0 ahead, 1 behind origin"), "{}", stdout);
+}
+
+fn sync_prune_removes_worktrees_of_deleted_branches(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &["dev"]);
+    bare_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    sandbox.git(&sandbox.path("origin.git"), &["branch", "-D", "dev"]);
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\nis_bare = true\nworktrees = [\"main\"]\n",
+        url,
+        sandbox.path("bare").display()
+    ));
+
+    let stdout = sandbox.run(&["sync", "--prune"]);
+    assert!(stdout.contains("pruned worktree for deleted branch dev"));
+    assert!(!sandbox.path("bare").join("dev").exists());
+    assert!(sandbox.path("bare").join("main").exists());
+}
+
+fn sync_prune_keeps_worktrees_of_unpushed_branches(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &["dev"]);
+    bare_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let bare = sandbox.path("bare");
+    let feature = bare.join("feature");
+    sandbox.git(
+        &bare,
+        &[
+            "worktree",
+            "add",
+            "-b",
+            "feature",
+            feature.to_str().unwrap(),
+        ],
+    );
+    sandbox.commit(&feature, "feature.txt", "feature\n");
+    // dev was pushed before, but its latest commit was not
+    sandbox.commit(&bare.join("dev"), "dev.txt", "dev\n");
+    sandbox.git(&sandbox.path("origin.git"), &["branch", "-D", "dev"]);
+
+    let stdout = sandbox.run(&["--prune", "sync"]);
+    assert!(!stdout.contains("pruned worktree"), "{}", stdout);
+    assert!(stdout.contains(
+        "kept worktree for deleted branch dev, because it has 1 commit(s) on no remote branch"
+    ));
+    assert!(feature.join("feature.txt").exists());
+    assert!(bare.join("dev").join("dev.txt").exists());
+}
+
+fn dirty_tree_skips_pull(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    let head = sandbox.rev_parse(&checkout, "HEAD");
+    fs::write(checkout.join("README"), "local edit\n").unwrap();
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+
+    let stdout = sandbox.run(&["pull"]);
+    assert!(stdout.contains("Skipped pull on branch main, because it has local changes"));
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), head);
+    assert_eq!(
+        fs::read_to_string(checkout.join("README")).unwrap(),
+        "local edit\n"
+    );
+}
+
+fn dirty_tree_is_pulled_with_autostash(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    fs::write(checkout.join("README"), "local edit\n").unwrap();
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+
+    let stdout = sandbox.run(&["pull", "--autostash", "tracked"]);
+    assert_no_failures(&stdout);
+    assert!(checkout.join("new.txt").exists());
+    assert_eq!(
+        fs::read_to_string(checkout.join("README")).unwrap(),
+        "local edit\n"
+    );
+}
+
+#[test]
+fn autostashed_changes_are_restored_when_the_pull_times_out() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    fs::write(checkout.join("README"), "local edit\n").unwrap();
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+    let hook = checkout.join(".git/hooks/post-merge");
+    fs::write(&hook, "#!/bin/sh\nsleep 30\n").unwrap();
+    fs::set_permissions(&hook, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+    let stdout = sandbox.run_failing(&["--timeout", "1s", "--autostash", "tracked", "pull"]);
+    assert!(stdout.contains("Timed out!"), "{}", stdout);
+    assert_eq!(
+        fs::read_to_string(checkout.join("README")).unwrap(),
+        "local edit\n"
+    );
+    assert_eq!(sandbox.git(&checkout, &["stash", "list"]), "");
+}
+
+fn diverged_branch_is_reported_and_left_alone(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    sandbox.commit(&checkout, "local.txt", "local\n");
+    let local_head = sandbox.rev_parse(&checkout, "HEAD");
+    sandbox.push_upstream("origin", "main", "upstream.txt", "upstream\n");
+    let remote = sandbox.path("origin.git");
+    let remote_head = sandbox.rev_parse(&remote, "main");
+
+    let stdout = sandbox.run(&["sync"]);
+    assert!(stdout.contains("Failure!"));
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), local_head);
+    assert_eq!(sandbox.rev_parse(&remote, "main"), remote_head);
+}
+
+fn missing_remote_does_not_affect_other_repos(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &[]);
+    let missing = format!("file://{}", sandbox.path("missing.git").display());
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\n\n[[repos]]\nurl = \"{}\"\npath = \"{}\"\n",
+        missing,
+        sandbox.path("broken").display(),
+        url,
+        sandbox.path("checkout").display()
+    ));
+
+    let stdout = sandbox.run_failing(&["sync"]);
+    assert!(stdout.contains("Error!"));
+    assert!(stdout.contains("Sync complete!"));
+    assert!(!sandbox.path("broken").exists());
+    assert!(sandbox.path("checkout").join("README").exists());
+}
+
+#[test]
+fn group_env_and_git_config_apply_to_repo() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    sandbox.manifest(&format!(
+        "[groups.work]\nenv = {{ REPOTEER_TEST = \"work\" }}\n\
+         git_config = {{ \"user.email\" = \"foo@company.com\" }}\n\n\
+         [[repos]]\nurl = \"{}\"\npath = \"{}\"\ngroup = \"work\"\n\
+         git_config = {{ \"core.hooksPath\" = \"{}\" }}\n",
+        url,
+        sandbox.path("checkout").display(),
+        sandbox.path("hooks").display(),
+    ));
+    fs::create_dir(sandbox.path("hooks")).unwrap();
+    let hook = sandbox.path("hooks").join("post-merge");
+    fs::write(&hook, "#!/bin/sh\necho \"$REPOTEER_TEST\" > ../marker\n").unwrap();
+    fs::set_permissions(&hook, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+    assert_no_failures(&sandbox.run(&["clone"]));
+    let checkout = sandbox.path("checkout");
+    assert_eq!(
+        sandbox.git(&checkout, &["config", "--local", "user.email"]),
+        "foo@company.com"
+    );
+
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+    assert_no_failures(&sandbox.run(&["pull"]));
+    assert_eq!(
+        fs::read_to_string(sandbox.path("marker")).unwrap().trim(),
+        "work"
+    );
+}
+
+#[test]
+fn hooks_run_around_operations() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\n\
+         hooks = {{ post_clone = \"echo $REPOTEER_HOOK $REPOTEER_REPO $REPOTEER_BRANCH > ../cloned\" }}\n",
+        url,
+        sandbox.path("checkout").display(),
+    ));
+    fs::write(
+        sandbox.path("config.toml"),
+        "[hooks]\npost_pull = \"git rev-parse HEAD > ../pulled\"\n",
+    )
+    .unwrap();
+
+    assert_no_failures(&sandbox.run(&["clone"]));
+    assert_eq!(
+        fs::read_to_string(sandbox.path("cloned")).unwrap().trim(),
+        "post_clone checkout main"
+    );
+
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+    let config = sandbox.path("config.toml");
+    assert_no_failures(&sandbox.run(&["--config", config.to_str().unwrap(), "pull"]));
+    let checkout = sandbox.path("checkout");
+    assert_eq!(
+        fs::read_to_string(sandbox.path("pulled")).unwrap().trim(),
+        sandbox.rev_parse(&checkout, "HEAD")
+    );
+}
+
+#[test]
+fn failing_pre_hook_aborts_operation() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\n\
+         hooks = {{ pre_pull = \"echo not today >&2; exit 1\" }}\n",
+        url,
+        sandbox.path("checkout").display(),
+    ));
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    let head = sandbox.rev_parse(&checkout, "HEAD");
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+
+    let stdout = sandbox.run_failing(&["pull"]);
+    assert!(stdout.contains("The pre_pull hook failed: not today"));
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), head);
+}
+
+fn status_reports_local_changes(backend: &'static str) {
+    let sandbox = Sandbox::with_backend(backend);
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    fs::write(checkout.join("README"), "local edit\n").unwrap();
+    fs::write(checkout.join("untracked.txt"), "untracked\n").unwrap();
+
+    let stdout = sandbox.run(&["status"]);
+    assert!(stdout.contains("1 unstaged, 1 untracked"));
+}
+
+#[test]
+fn checkout_locked_restores_locked_commits() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    let locked_head = sandbox.rev_parse(&checkout, "HEAD");
+
+    let stdout = sandbox.run(&["lock"]);
+    assert_no_failures(&stdout);
+    let lockfile = fs::read_to_string(sandbox.path("manifest.lock")).unwrap();
+    assert!(lockfile.contains(&locked_head));
+
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+    sandbox.run(&["pull"]);
+    assert_ne!(sandbox.rev_parse(&checkout, "HEAD"), locked_head);
+
+    let stdout = sandbox.run(&["checkout", "--locked"]);
+    assert_no_failures(&stdout);
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), locked_head);
+    assert!(!checkout.join("new.txt").exists());
+}
+
+#[test]
+fn checkout_locked_reports_dirty_and_missing_commits() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    let head = sandbox.rev_parse(&checkout, "HEAD");
+    // a local commit that never reaches the remote cannot be restored in a fresh clone
+    sandbox.commit(&checkout, "local.txt", "local\n");
+    sandbox.run(&["lock"]);
+    fs::remove_dir_all(&checkout).unwrap();
+    sandbox.run(&["clone"]);
+
+    let stdout = sandbox.run(&["checkout", "--locked"]);
+    assert!(stdout.contains("is missing"), "{}", stdout);
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), head);
+
+    sandbox.run(&["lock"]);
+    sandbox.commit(&checkout, "other.txt", "other\n");
+    fs::write(checkout.join("README"), "local edit\n").unwrap();
+    let stdout = sandbox.run(&["checkout", "--locked"]);
+    assert!(
+        stdout.contains("because it has local changes"),
+        "{}",
+        stdout
+    );
+    assert_ne!(sandbox.rev_parse(&checkout, "HEAD"), head);
+}
+
+fn revision_manifest(sandbox: &Sandbox, url: &str, revision: &str) {
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\nrevision = \"{}\"\n",
+        url,
+        sandbox.path("checkout").display(),
+        revision
+    ));
+}
+
+#[test]
+fn revision_tag_is_checked_out_and_kept() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    let seed = sandbox.path("origin-seed");
+    sandbox.git(&seed, &["tag", "v1"]);
+    sandbox.git(&seed, &["push", "origin", "v1"]);
+    let tagged = sandbox.rev_parse(&seed, "v1");
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+    revision_manifest(&sandbox, &url, "v1");
+
+    let stdout = sandbox.run(&["clone"]);
+    assert_no_failures(&stdout);
+    let checkout = sandbox.path("checkout");
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), tagged);
+
+    sandbox.push_upstream("origin", "main", "newer.txt", "upstream\n");
+    let stdout = sandbox.run(&["sync"]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("already at tag v1"), "{}", stdout);
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), tagged);
+    assert!(!sandbox.run(&["status"]).contains("Drifted"));
+
+    sandbox.git(&checkout, &["checkout", "main"]);
+    let stdout = sandbox.run(&["status"]);
+    assert!(
+        stdout.contains("Drifted from revision v1: HEAD is at"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn revision_branch_is_fast_forwarded() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &["dev"]);
+    revision_manifest(&sandbox, &url, "dev");
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    assert_eq!(sandbox.git(&checkout, &["branch", "--show-current"]), "dev");
+
+    sandbox.push_upstream("origin", "dev", "new.txt", "upstream\n");
+    let stdout = sandbox.run(&["pull"]);
+    assert_no_failures(&stdout);
+    assert_eq!(
+        sandbox.rev_parse(&checkout, "HEAD"),
+        sandbox.rev_parse(&sandbox.path("origin.git"), "dev")
+    );
+}
+
+#[test]
+fn revision_does_not_discard_local_work() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    let initial = sandbox.rev_parse(&sandbox.path("origin.git"), "main");
+    revision_manifest(&sandbox, &url, &initial);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    sandbox.commit(&checkout, "local.txt", "local\n");
+    let local_head = sandbox.rev_parse(&checkout, "HEAD");
+
+    let stdout = sandbox.run(&["pull"]);
+    assert!(
+        stdout.contains("no branch or tag contains its commits"),
+        "{}",
+        stdout
+    );
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), local_head);
+
+    sandbox.git(&checkout, &["checkout", "--quiet", "main"]);
+    fs::write(checkout.join("README"), "local edit\n").unwrap();
+    let stdout = sandbox.run(&["pull"]);
+    assert!(
+        stdout.contains("because the checkout has local changes"),
+        "{}",
+        stdout
+    );
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), initial);
+}
+
+#[test]
+fn restore_resets_branches_to_snapshot() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &["dev"]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    sandbox.git(&checkout, &["branch", "dev", "origin/dev"]);
+    fs::write(checkout.join("README"), "stashed\n").unwrap();
+    sandbox.git(&checkout, &["stash", "push", "--message", "wip"]);
+    let main_head = sandbox.rev_parse(&checkout, "main");
+
+    let stdout = sandbox.run(&["snapshot", "before"]);
+    assert_no_failures(&stdout);
+    assert!(sandbox
+        .path("state/repoteer/snapshots/before.json")
+        .exists());
+    assert!(!sandbox.repoteer(&["snapshot", "before"]).status.success());
+
+    // rewrite both branches, drop the stash and create a new branch
+    sandbox.commit(&checkout, "main.txt", "main\n");
+    sandbox.git(&checkout, &["branch", "--force", "dev", "HEAD"]);
+    sandbox.git(&checkout, &["stash", "drop"]);
+    sandbox.git(&checkout, &["checkout", "--quiet", "-b", "feature"]);
+
+    let stdout = sandbox.run(&["restore", "before"]);
+    assert_eq!(sandbox.rev_parse(&checkout, "main"), main_head);
+    assert_eq!(sandbox.rev_parse(&checkout, "dev"), main_head);
+    assert_eq!(
+        sandbox.git(&checkout, &["branch", "--show-current"]),
+        "main"
+    );
+    assert!(stdout.contains("Kept branch feature"), "{}", stdout);
+    assert!(
+        stdout.contains("Stash \"On main: wip\" is gone"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn restore_only_drops_commits_made_on_top_of_the_snapshot() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &["dev"]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    sandbox.git(&checkout, &["branch", "dev", "origin/dev"]);
+    let initial = sandbox.rev_parse(&checkout, "main");
+    sandbox.run(&["snapshot", "before"]);
+
+    // main gains a commit, while dev is rewritten
+    sandbox.commit(&checkout, "main.txt", "main\n");
+    sandbox.git(&checkout, &["checkout", "--quiet", "dev"]);
+    sandbox.git(
+        &checkout,
+        &["commit", "--quiet", "--amend", "-m", "rewritten"],
+    );
+    let rewritten = sandbox.rev_parse(&checkout, "dev");
+    sandbox.git(&checkout, &["checkout", "--quiet", "main"]);
+
+    let stdout = sandbox.run(&["restore", "before"]);
+    assert_eq!(sandbox.rev_parse(&checkout, "main"), initial);
+    assert!(
+        stdout.contains("Dropped 1 commit(s) made after the snapshot from branch main"),
+        "{}",
+        stdout
+    );
+    assert_eq!(sandbox.rev_parse(&checkout, "dev"), rewritten);
+    assert!(
+        stdout.contains("Skipped restoring branch dev"),
+        "{}",
+        stdout
+    );
+
+    let stdout = sandbox.run(&["restore", "before", "--force"]);
+    assert_eq!(sandbox.rev_parse(&checkout, "dev"), initial);
+    assert!(
+        stdout.contains("Dropped 1 commit(s) made after the snapshot from branch dev"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn restore_leaves_dirty_checkouts_alone() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    sandbox.run(&["snapshot", "before"]);
+    sandbox.commit(&checkout, "main.txt", "main\n");
+    let head = sandbox.rev_parse(&checkout, "HEAD");
+    fs::write(checkout.join("README"), "local edit\n").unwrap();
+
+    let stdout = sandbox.run(&["restore", "before"]);
+    assert!(
+        stdout.contains("because it has local changes"),
+        "{}",
+        stdout
+    );
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), head);
+    assert!(!sandbox.repoteer(&["restore", "missing"]).status.success());
+}
+
+#[test]
+fn history_records_runs_and_moved_commits() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    assert!(sandbox
+        .run(&["history"])
+        .contains("No runs have been recorded yet"));
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    let before = sandbox.rev_parse(&checkout, "HEAD");
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+    sandbox.run(&["pull"]);
+    let after = sandbox.rev_parse(&checkout, "HEAD");
+
+    let stdout = sandbox.run(&["history"]);
+    assert!(stdout.contains("last pull at"), "{}", stdout);
+    assert!(!stdout.contains("failing"), "{}", stdout);
+    let stdout = sandbox.run(&["history", "checkout"]);
+    let runs: Vec<_> = stdout.lines().filter(|l| !l.starts_with(' ')).collect();
+    assert_eq!(runs.len(), 2, "{}", stdout);
+    assert!(runs[0].contains("pull"), "{}", stdout);
+    assert!(runs[1].contains("clone"), "{}", stdout);
+    assert!(
+        stdout.contains(&format!("{}..{}", &before[..7], &after[..7])),
+        "{}",
+        stdout
+    );
+    assert!(!sandbox.repoteer(&["history", "typo"]).status.success());
+
+    sandbox.run(&["--dry-run", "pull"]);
+    let stdout = sandbox.run(&["history", "checkout"]);
+    assert_eq!(stdout.lines().filter(|l| !l.starts_with(' ')).count(), 2);
+}
+
+/// Waits up to ten seconds for `condition` to hold
+fn wait_for(condition: impl Fn() -> bool) -> bool {
+    for _ in 0..100 {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+#[test]
+fn watch_pushes_commits_and_stops_on_sigterm() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    let mut watch = sandbox.spawn(&[
+        "watch",
+        "--interval",
+        "1h",
+        "--push-on-commit",
+        "--push-delay",
+        "200ms",
+    ]);
+    let checkout = sandbox.path("checkout");
+    assert!(wait_for(|| checkout.join("README").exists()));
+    // give the initial sync time to finish and the branches to be watched
+    thread::sleep(Duration::from_secs(1));
+
+    sandbox.commit(&checkout, "local.txt", "local\n");
+    let head = sandbox.rev_parse(&checkout, "HEAD");
+    let remote = sandbox.path("origin.git");
+    assert!(wait_for(|| sandbox.rev_parse(&remote, "main") == head));
+
+    let killed = std::process::Command::new("kill")
+        .args(["-TERM", &watch.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    let status = watch.wait().unwrap();
+    let mut stdout = String::new();
+    watch
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+    assert!(status.success(), "{}", stdout);
+    assert!(stdout.contains("Running sync on 1 repos"), "{}", stdout);
+    assert!(stdout.contains("Running push on 1 repos"), "{}", stdout);
+}
+
+#[test]
+fn fetch_reports_incoming_commits_without_pulling() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    let head = sandbox.rev_parse(&checkout, "HEAD");
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+
+    let stdout = sandbox.run(&["fetch"]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("0 ahead, 1 behind origin"), "{}", stdout);
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), head);
+}
+
+#[test]
+fn bash_completions_suggest_repos_and_tags_from_the_manifest() {
+    let sandbox = Sandbox::new();
+    sandbox.manifest(
+        "[[repos]]\nurl = \"https://example.com/api.git\"\npath = \"/tmp/work/api\"\ntags = [\"work\"]\n\n\
+         [[repos]]\nurl = \"https://example.com/web.git\"\npath = \"/tmp/work/web\"\ntags = [\"work\", \"frontend\"]\n",
+    );
+    assert_eq!(sandbox.run(&["__complete-repos"]), "api\nweb\n");
+    assert_eq!(
+        sandbox.run(&["__complete-repos", "--tags"]),
+        "frontend\nwork\n"
+    );
+
+    let script = sandbox.path("repoteer.bash");
+    fs::write(&script, sandbox.run(&["completions", "bash"])).unwrap();
+    let complete = |words: &str| {
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(format!(
+                "source {:?}; COMP_WORDS=({}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1)); \
+                 _repoteer_with_repos repoteer; echo \"${{COMPREPLY[*]}}\"",
+                script, words
+            ))
+            .env(
+                "PATH",
+                format!(
+                    "{}:{}",
+                    std::path::Path::new(env!("CARGO_BIN_EXE_repoteer"))
+                        .parent()
+                        .unwrap()
+                        .display(),
+                    std::env::var("PATH").unwrap()
+                ),
+            )
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    let manifest = sandbox.path("manifest.toml");
+    let manifest = manifest.to_str().unwrap();
+    assert_eq!(
+        complete(&format!("repoteer -m {} pull --repo w", manifest)),
+        "web"
+    );
+    assert_eq!(
+        complete(&format!("repoteer -m {} --tag ''", manifest)),
+        "frontend work"
+    );
+    assert!(complete("repoteer sta").contains("status"));
+}
+
+#[test]
+fn man_page_is_roff() {
+    let sandbox = Sandbox::new();
+    let man = sandbox.run(&["man"]);
+    assert!(man.contains(".TH REPOTEER 1"), "{}", man);
+    assert!(man.contains(".SH COMMANDS"), "{}", man);
+}
+
+/// Answers a single HTTP request on `listener` with `200 OK`, and returns its body
+fn receive_post(listener: TcpListener) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = vec![];
+        let mut buf = [0; 4096];
+        let body_start = loop {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+            if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i + 4;
+            }
+        };
+        let headers = String::from_utf8_lossy(&request[..body_start]).to_lowercase();
+        let length: usize = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length: "))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        while request.len() < body_start + length {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        assert!(headers.starts_with("post "), "{}", headers);
+        String::from_utf8_lossy(&request[body_start..]).to_string()
+    })
+}
+
+#[test]
+fn webhook_receives_diverged_and_incoming_branches() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let request = receive_post(listener);
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    let config = sandbox.path("config.toml");
+    fs::write(
+        &config,
+        format!(
+            "[[notifications]]\nkind = \"webhook\"\nurl = \"http://127.0.0.1:{}/hook\"\n",
+            port
+        ),
+    )
+    .unwrap();
+    let config = config.to_str().unwrap();
+    sandbox.run(&["--config", config, "clone"]);
+    sandbox.push_upstream("origin", "main", "upstream.txt", "upstream\n");
+    sandbox.commit(&sandbox.path("checkout"), "local.txt", "local\n");
+
+    sandbox.run(&["--config", config, "fetch"]);
+    let summary: serde_json::Value = serde_json::from_str(&request.join().unwrap()).unwrap();
+    assert_eq!(summary["command"], "fetch");
+    assert_eq!(
+        summary["triggers"],
+        serde_json::json!(["divergence", "incoming"])
+    );
+    let repo = &summary["repos"][0];
+    assert_eq!(repo["incoming"], serde_json::json!(["main"]));
+    assert_eq!(
+        repo["diverged"],
+        serde_json::json!([{"branch": "main", "ahead": 1, "behind": 1}])
+    );
+}
+
+#[test]
+fn failed_notifications_do_not_fail_the_run() {
+    let sandbox = Sandbox::new();
+    plain_manifest(&sandbox, "/nonexistent/origin.git");
+    let config = sandbox.path("config.toml");
+    fs::write(
+        &config,
+        "[[notifications]]\nkind = \"command\"\ncommand = \"echo unreachable >&2; exit 1\"\n",
+    )
+    .unwrap();
+    let output = sandbox.repoteer(&["--config", config.to_str().unwrap(), "clone"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Failed to notify!"), "{}", stderr);
+    assert!(stderr.contains("unreachable"), "{}", stderr);
+}
+
+#[test]
+fn schedule_writes_systemd_units() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\ntags = [\"work\"]\n",
+        url,
+        sandbox.path("checkout").display()
+    ));
+    let units = sandbox.path("units");
+    let unit_dir = units.to_str().unwrap();
+    sandbox.run(&[
+        "schedule",
+        "install",
+        "--every",
+        "30m",
+        "--command",
+        "fetch",
+        "--tag",
+        "work",
+        "--autostash",
+        "tracked",
+        "--prune",
+        "--unit-dir",
+        unit_dir,
+    ]);
+    let service = fs::read_to_string(units.join("repoteer-fetch.service")).unwrap();
+    let exec_start = service
+        .lines()
+        .find_map(|line| line.strip_prefix("ExecStart="))
+        .unwrap();
+    assert!(
+        exec_start.ends_with(
+            "--tag work --backend cli --autostash tracked --prune --retries 2 \
+             --retry-backoff 1s --interactive never fetch"
+        ),
+        "{}",
+        service
+    );
+    assert!(exec_start.contains(sandbox.path("manifest.toml").to_str().unwrap()));
+    let timer = fs::read_to_string(units.join("repoteer-fetch.timer")).unwrap();
+    assert!(timer.contains("OnUnitActiveSec=30m"), "{}", timer);
+
+    let stdout = sandbox.run(&["schedule", "status", "--unit-dir", unit_dir]);
+    assert!(stdout.contains("fetch: every 30m"), "{}", stdout);
+    let dry_run = sandbox.repoteer(&[
+        "schedule",
+        "install",
+        "--every",
+        "1h",
+        "--dry-run",
+        "--unit-dir",
+        unit_dir,
+    ]);
+    assert!(!dry_run.status.success());
+    let typo = sandbox.repoteer(&[
+        "schedule",
+        "install",
+        "--every",
+        "1h",
+        "--tag",
+        "work",
+        "--repo",
+        "typo",
+        "--unit-dir",
+        unit_dir,
+    ]);
+    assert!(!typo.status.success());
+
+    sandbox.run(&["schedule", "remove", "fetch", "--unit-dir", unit_dir]);
+    assert!(!units.join("repoteer-fetch.timer").exists());
+    assert!(sandbox
+        .run(&["schedule", "status", "--unit-dir", unit_dir])
+        .contains("No schedules are installed"));
+    assert!(!sandbox
+        .repoteer(&["schedule", "remove", "fetch", "--unit-dir", unit_dir])
+        .status
+        .success());
+}
+
+#[test]
+fn invalid_manifest_fails() {
+    let sandbox = Sandbox::new();
+    sandbox.manifest("[[repos]]\nurl = \"file:///nowhere\"\n");
+
+    let output = sandbox.repoteer(&["sync"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("path"));
+}
+
+#[test]
+fn repo_and_tag_filters_select_repos() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"{url}\"\npath = \"{}\"\ntags = [\"work\"]\n\n\
+         [[repos]]\nurl = \"{url}\"\npath = \"{}\"\nname = \"other\"\n",
+        sandbox.path("first").display(),
+        sandbox.path("second").display(),
+    ));
+
+    sandbox.run(&["--repo", "other", "clone"]);
+    assert!(!sandbox.path("first").exists());
+    assert!(sandbox.path("second").exists());
+
+    sandbox.run(&["--tag", "work", "clone"]);
+    assert!(sandbox.path("first").exists());
+
+    let output = sandbox.repoteer(&["--repo", "typo", "clone"]);
+    assert!(!output.status.success());
+}
+
+#[tokio::test]
+async fn library_reports_outcome_per_repo() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    let manifest = Manifest::from_toml_str(&format!(
+        "[[repos]]\nurl = \"{}\"\npath = \"{}\"\n\n[[repos]]\nurl = \"{}\"\npath = \"{}\"\n",
+        url,
+        sandbox.path("checkout").display(),
+        format_args!("file://{}", sandbox.path("missing.git").display()),
+        sandbox.path("broken").display(),
+    ))
+    .unwrap();
+
+    let reports = run_operations(Command::Clone, manifest, Options::default())
+        .await
+        .unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].name, "checkout");
+    assert!(reports[0].is_success());
+    assert_eq!(reports[1].name, "broken");
+    assert!(matches!(reports[1].outcome, Outcome::Error(_)));
+}
+
+#[tokio::test]
+async fn unreachable_remote_is_retried() {
+    assert_unreachable_remote_is_retried(BackendKind::Cli).await;
+}
+
+#[cfg(feature = "libgit2")]
+#[tokio::test]
+async fn unreachable_remote_is_retried_by_libgit2() {
+    assert_unreachable_remote_is_retried(BackendKind::Libgit2).await;
+}
+
+async fn assert_unreachable_remote_is_retried(backend: BackendKind) {
+    let sandbox = Sandbox::new();
+    let manifest = Manifest::from_toml_str(&format!(
+        "[[repos]]\nurl = \"http://127.0.0.1:1/origin.git\"\npath = \"{}\"\n",
+        sandbox.path("checkout").display(),
+    ))
+    .unwrap();
+    let options = Options {
+        retry: RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(10),
+        },
+        backend,
+        ..Default::default()
+    };
+
+    let reports = run_operations(Command::Clone, manifest, options)
+        .await
+        .unwrap();
+    assert!(!reports[0].is_success());
+    let attempts: Vec<u32> = reports[0].retries.iter().map(|r| r.attempt).collect();
+    assert_eq!(attempts, [1, 2]);
+    assert_eq!(reports[0].retries[1].delay, Duration::from_millis(20));
+    assert_eq!(reports[0].retries[0].argv[1], "clone");
+}
+
+#[tokio::test]
+async fn rejected_credentials_are_reported_without_prompting() {
+    assert_rejected_credentials_are_reported(BackendKind::Cli).await;
+}
+
+#[cfg(feature = "libgit2")]
+#[tokio::test]
+async fn rejected_credentials_are_reported_by_libgit2() {
+    assert_rejected_credentials_are_reported(BackendKind::Libgit2).await;
+}
+
+async fn assert_rejected_credentials_are_reported(backend: BackendKind) {
+    // answers every request by asking for credentials, like a private repo on a git host
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let _ = stream.read(&mut [0; 4096]);
+            let _ = stream.write_all(
+                b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"git\"\r\n\
+                  Content-Length: 0\r\nConnection: close\r\n\r\n",
+            );
+        }
+    });
+    let sandbox = Sandbox::new();
+    let manifest = Manifest::from_toml_str(&format!(
+        "[[repos]]\nurl = \"http://127.0.0.1:{}/private.git\"\npath = \"{}\"\n",
+        port,
+        sandbox.path("checkout").display(),
+    ))
+    .unwrap();
+
+    // rejected credentials are not retried
+    let options = Options {
+        retry: RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(10),
+        },
+        backend,
+        ..Default::default()
+    };
+
+    let reports = run_operations(Command::Clone, manifest, options)
+        .await
+        .unwrap();
+    assert!(
+        matches!(reports[0].outcome, Outcome::AuthFailed(_)),
+        "{:?}",
+        reports[0].outcome
+    );
+    assert!(reports[0].retries.is_empty());
+}
+
+#[test]
+fn json_output_emits_typed_events() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+
+    let stdout = sandbox.run(&["pull", "--output", "json"]);
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(events.first().unwrap()["event"], "repo_started");
+    assert!(events
+        .iter()
+        .any(|e| e["event"] == "phase_started" && e["op"] == "Pull" && e["branch"] == "main"));
+    assert!(events.iter().any(|e| e["event"] == "git_command_finished"
+        && e["argv"] == serde_json::json!(["git", "pull", "origin", "main"])
+        && e["status"] == 0));
+    let last = events.last().unwrap();
+    assert_eq!(last["event"], "repo_finished");
+    assert_eq!(last["outcome"]["kind"], "success");
+}
+
+#[test]
+fn dry_run_does_not_change_repos() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+
+    let stdout = sandbox.run(&["--dry-run", "sync"]);
+    assert_no_failures(&stdout);
+    assert!(stdout.contains("git clone"));
+    assert!(!sandbox.path("checkout").exists());
+
+    sandbox.run(&["clone"]);
+    let checkout = sandbox.path("checkout");
+    let head = sandbox.rev_parse(&checkout, "HEAD");
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+
+    let stdout = sandbox.run(&["--output", "json", "pull", "--dry-run"]);
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(events.iter().any(|e| e["event"] == "command_skipped"
+        && e["argv"] == serde_json::json!(["git", "pull", "origin", "main"])));
+    assert_eq!(events.last().unwrap()["outcome"]["kind"], "success");
+    assert_eq!(sandbox.rev_parse(&checkout, "HEAD"), head);
+}
+
+#[test]
+fn hung_git_command_times_out() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+    sandbox.run(&["clone"]);
+    sandbox.push_upstream("origin", "main", "new.txt", "upstream\n");
+    let hook = sandbox.path("checkout").join(".git/hooks/post-merge");
+    std::fs::write(&hook, "#!/bin/sh\nsleep 30\n").unwrap();
+    std::fs::set_permissions(&hook, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+    let started = std::time::Instant::now();
+    let stdout = sandbox.run_failing(&["--output", "json", "pull", "--timeout", "1s"]);
+    assert!(started.elapsed() < std::time::Duration::from_secs(20));
+    let last: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(last["event"], "repo_finished");
+    assert_eq!(last["outcome"]["kind"], "timed_out");
+    assert_eq!(last["outcome"]["message"], "git command timed out after 1s");
+}
+
+#[test]
+fn timed_out_git_command_is_killed_with_its_children() {
+    let sandbox = Sandbox::new();
+    let pid_file = sandbox.path("ssh.pid");
+    let ssh = sandbox.path("fake-ssh");
+    // stands in for an ssh connection that hangs, as a grandchild of repoteer
+    fs::write(
+        &ssh,
+        format!(
+            "#!/bin/sh\nsleep 30 &\necho $! > '{}'\nwait\n",
+            pid_file.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&ssh, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    sandbox.manifest(&format!(
+        "[[repos]]\nurl = \"ssh://git@example.invalid/repo.git\"\npath = \"{}\"\n\
+         env = {{ GIT_SSH_COMMAND = \"{}\" }}\n",
+        sandbox.path("checkout").display(),
+        ssh.display()
+    ));
+
+    let started = std::time::Instant::now();
+    sandbox.repoteer(&["--timeout", "1s", "clone"]);
+    assert!(started.elapsed() < std::time::Duration::from_secs(20));
+    let pid = fs::read_to_string(&pid_file).unwrap().trim().to_string();
+    // the killed sleeper may linger as a zombie until it is reaped
+    let alive = || {
+        fs::read_to_string(format!("/proc/{}/stat", pid))
+            .is_ok_and(|stat| !stat.rsplit(')').next().unwrap().trim().starts_with('Z'))
+    };
+    for _ in 0..50 {
+        if !alive() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(
+        !alive(),
+        "ssh child {} outlived the timed out git command",
+        pid
+    );
+}
+
+#[test]
+fn quiet_output_only_reports_failures() {
+    let sandbox = Sandbox::new();
+    let url = sandbox.remote("origin", &[]);
+    plain_manifest(&sandbox, &url);
+
+    let output = sandbox.repoteer(&["--output", "quiet", "clone"]);
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+
+    let output = sandbox.repoteer(&["--output", "quiet", "clone"]);
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("checkout: Error!"));
+}
+
+/// Declares the test module `$name`, which runs the scenarios every backend has to pass with
+/// `$backend`
+macro_rules! backend_tests {
+    ($name:ident, $backend:expr) => {
+        backend_tests!(
+            $name,
+            $backend,
+            [
+                clone_creates_checkout,
+                sync_clones_and_then_updates_existing_checkout,
+                pull_fast_forwards_checkout,
+                pull_leaves_branches_that_are_not_checked_out_alone,
+                push_publishes_local_commits,
+                credentials_come_from_the_credential_helper,
+                bare_repo_worktrees_are_created_and_pulled,
+                sync_adds_worktrees_declared_later,
+                sync_adds_worktrees_of_branches_created_upstream_after_the_clone,
+                fetch_reports_bare_worktrees_behind_origin,
+                sync_prune_removes_worktrees_of_deleted_branches,
+                sync_prune_keeps_worktrees_of_unpushed_branches,
+                dirty_tree_skips_pull,
+                dirty_tree_is_pulled_with_autostash,
+                diverged_branch_is_reported_and_left_alone,
+                missing_remote_does_not_affect_other_repos,
+                status_reports_local_changes,
+            ]
+        );
+    };
+    ($name:ident, $backend:expr, [$($scenario:ident),* $(,)?]) => {
+        mod $name {
+            $(
+                #[test]
+                fn $scenario() {
+                    super::$scenario($backend);
+                }
+            )*
+        }
+    };
+}
+
+backend_tests!(cli, "cli");
+#[cfg(feature = "libgit2")]
+backend_tests!(libgit2, "libgit2");
@@ -0,0 +1,353 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
+    thread,
+};
+use tempfile::TempDir;
+
+/// A temporary directory holding bare remotes, checkouts and a manifest, plus an isolated git
+/// configuration, so that tests never touch the user's repositories or settings
+pub struct Sandbox {
+    dir: TempDir,
+    backend: &'static str,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        Self::with_backend("cli")
+    }
+
+    /// Creates a sandbox whose repoteer runs use `backend`, like `cli` or `libgit2`
+    pub fn with_backend(backend: &'static str) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("gitconfig"),
+            "[user]\n\tname = repoteer\n\temail = repoteer@localhost\n\
+             [init]\n\tdefaultBranch = main\n\
+             [protocol \"file\"]\n\tallow = always\n\
+             [pull]\n\tff = only\n",
+        )
+        .unwrap();
+        // libgit2 reads the global config from $HOME, and does not know about GIT_CONFIG_GLOBAL
+        fs::write(
+            dir.path().join(".gitconfig"),
+            format!(
+                "[include]\n\tpath = {}\n",
+                dir.path().join("gitconfig").display()
+            ),
+        )
+        .unwrap();
+        Sandbox { dir, backend }
+    }
+
+    /// Returns the absolute path of `name` inside the sandbox
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// Runs git with the sandbox's configuration in `dir`, and returns its trimmed stdout
+    ///
+    /// Panics if git fails.
+    pub fn git(&self, dir: &Path, args: &[&str]) -> String {
+        let output = self.git_command(dir).args(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} in {} failed: {}",
+            args,
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    fn git_command(&self, dir: &Path) -> Command {
+        let mut command = Command::new("git");
+        command
+            .current_dir(dir)
+            .env("GIT_CONFIG_GLOBAL", self.path("gitconfig"))
+            .env("GIT_CONFIG_NOSYSTEM", "1");
+        command
+    }
+
+    /// Creates the bare repository `<name>.git` with a single commit on `main`, and the given
+    /// extra branches pointing at the same commit, and returns its `file://` URL
+    pub fn remote(&self, name: &str, branches: &[&str]) -> String {
+        let remote = self.path(&format!("{}.git", name));
+        self.git(
+            self.dir.path(),
+            &["init", "--bare", remote.to_str().unwrap()],
+        );
+        let seed = self.path(&format!("{}-seed", name));
+        self.git(
+            self.dir.path(),
+            &["clone", remote.to_str().unwrap(), seed.to_str().unwrap()],
+        );
+        fs::write(seed.join("README"), "initial\n").unwrap();
+        self.git(&seed, &["add", "README"]);
+        self.git(&seed, &["commit", "-m", "initial"]);
+        self.git(&seed, &["push", "origin", "main"]);
+        for branch in branches {
+            self.git(&seed, &["push", "origin", &format!("main:{}", branch)]);
+        }
+        format!("file://{}", remote.display())
+    }
+
+    /// Commits `content` to `file` on `branch` of the remote `<name>.git`, as if someone else
+    /// pushed to it
+    pub fn push_upstream(&self, name: &str, branch: &str, file: &str, content: &str) {
+        let seed = self.path(&format!("{}-seed", name));
+        self.git(&seed, &["fetch", "origin"]);
+        self.git(
+            &seed,
+            &["checkout", "-B", branch, &format!("origin/{}", branch)],
+        );
+        fs::write(seed.join(file), content).unwrap();
+        self.git(&seed, &["add", file]);
+        self.git(&seed, &["commit", "-m", &format!("update {}", file)]);
+        self.git(&seed, &["push", "origin", branch]);
+    }
+
+    /// Serves the bare remote `<name>.git` over HTTP with `git http-backend`, answering only
+    /// requests authenticated as `user` with `password`, and returns its URL
+    pub fn serve_with_credentials(&self, name: &str, user: &str, password: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let root = self.dir.path().to_path_buf();
+        let authorization = format!("Basic {}", base64(&format!("{}:{}", user, password)));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = serve_git(stream, &root, &authorization);
+            }
+        });
+        format!("http://localhost:{}/{}.git", port, name)
+    }
+
+    /// Configures `helper` as the credential helper of the sandbox's git configuration
+    pub fn credential_helper(&self, helper: &str) {
+        let config = self.path("gitconfig");
+        self.git(
+            self.dir.path(),
+            &[
+                "config",
+                "--file",
+                config.to_str().unwrap(),
+                "credential.helper",
+                helper,
+            ],
+        );
+    }
+
+    /// Returns the commit `reference` points to in the repository at `dir`
+    pub fn rev_parse(&self, dir: &Path, reference: &str) -> String {
+        self.git(dir, &["rev-parse", reference])
+    }
+
+    /// Commits `content` to `file` in the checkout at `dir`
+    pub fn commit(&self, dir: &Path, file: &str, content: &str) {
+        fs::write(dir.join(file), content).unwrap();
+        self.git(dir, &["add", file]);
+        self.git(dir, &["commit", "-m", &format!("local {}", file)]);
+    }
+
+    /// Writes `toml` to the sandbox's manifest
+    pub fn manifest(&self, toml: &str) {
+        fs::write(self.path("manifest.toml"), toml).unwrap();
+    }
+
+    /// Runs repoteer with the sandbox's manifest and `args`, and returns its `Output`
+    pub fn repoteer(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Starts repoteer like `repoteer` in the background, with its stdout piped, and returns the
+    /// running `Child`
+    pub fn spawn(&self, args: &[&str]) -> Child {
+        self.command(args).stdout(Stdio::piped()).spawn().unwrap()
+    }
+
+    /// Returns the `Command` running repoteer with the sandbox's manifest and `args`
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_repoteer"));
+        command
+            .current_dir(self.dir.path())
+            .env("GIT_CONFIG_GLOBAL", self.path("gitconfig"))
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("HOME", self.dir.path())
+            .env("XDG_STATE_HOME", self.path("state"))
+            .env("NO_COLOR", "1")
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .arg("-m")
+            .arg(self.path("manifest.toml"))
+            .args(["--backend", self.backend])
+            .args(args);
+        command
+    }
+
+    /// Runs repoteer like `repoteer`, asserts that it exited successfully, and returns its stdout
+    pub fn run(&self, args: &[&str]) -> String {
+        let output = self.repoteer(args);
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(
+            output.status.success(),
+            "repoteer {:?} failed:\n{}\n{}",
+            args,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        stdout
+    }
+
+    /// Runs repoteer like `repoteer`, asserts that it exited with status 1 because repos failed,
+    /// and returns its stdout
+    pub fn run_failing(&self, args: &[&str]) -> String {
+        let output = self.repoteer(args);
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        assert_eq!(
+            output.status.code(),
+            Some(1),
+            "repoteer {:?} did not fail:\n{}\n{}",
+            args,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        stdout
+    }
+}
+
+/// Asserts that the output of a repoteer run does not report any failures
+pub fn assert_no_failures(stdout: &str) {
+    assert!(
+        !stdout.contains("Failure!") && !stdout.contains("Error!"),
+        "unexpected failure in output:\n{}",
+        stdout
+    );
+}
+
+/// Answers a single HTTP request on `stream` by running `git http-backend` on the repositories in
+/// `root`, if it carries the `authorization`, and asks for credentials otherwise
+fn serve_git(stream: TcpStream, root: &Path, authorization: &str) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        match line.trim_end().split_once(':') {
+            Some((key, value)) => {
+                headers.push((key.trim().to_lowercase(), value.trim().to_string()))
+            }
+            None => break,
+        }
+    }
+    let header = |key: &str| {
+        headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    };
+    let mut stream = stream;
+    if header("authorization").as_deref() != Some(authorization) {
+        return stream.write_all(
+            b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"git\"\r\n\
+              Content-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+    }
+    let mut body = vec![];
+    if header("transfer-encoding").is_some_and(|e| e.eq_ignore_ascii_case("chunked")) {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size)?;
+            let size = usize::from_str_radix(size.trim(), 16).unwrap_or(0);
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk)?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else if let Some(length) = header("content-length").and_then(|l| l.parse().ok()) {
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    }
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let mut backend = Command::new("git")
+        .arg("http-backend")
+        .env("GIT_PROJECT_ROOT", root)
+        .env("GIT_HTTP_EXPORT_ALL", "1")
+        .env("REQUEST_METHOD", &method)
+        .env("PATH_INFO", path)
+        .env("QUERY_STRING", query)
+        .env("CONTENT_TYPE", header("content-type").unwrap_or_default())
+        .env("CONTENT_LENGTH", body.len().to_string())
+        .env(
+            "HTTP_CONTENT_ENCODING",
+            header("content-encoding").unwrap_or_default(),
+        )
+        .env(
+            "GIT_HTTP_PROTOCOL",
+            header("git-protocol").unwrap_or_default(),
+        )
+        .env("REMOTE_USER", "repoteer")
+        .env("REMOTE_ADDR", "127.0.0.1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    backend.stdin.take().unwrap().write_all(&body)?;
+    let output = backend.wait_with_output()?;
+    // the CGI response starts with its headers, among them an optional `Status`
+    let split = output
+        .stdout
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|i| (i, i + 4))
+        .unwrap_or((output.stdout.len(), output.stdout.len()));
+    let cgi_headers = String::from_utf8_lossy(&output.stdout[..split.0]).to_string();
+    let mut status = "200 OK".to_string();
+    let mut response = vec![];
+    for line in cgi_headers.lines() {
+        match line.strip_prefix("Status:") {
+            Some(s) => status = s.trim().to_string(),
+            None => response.push(line.to_string()),
+        }
+    }
+    let body = &output.stdout[split.1..];
+    stream.write_all(
+        format!(
+            "HTTP/1.1 {}\r\n{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            response.join("\r\n"),
+            body.len()
+        )
+        .as_bytes(),
+    )?;
+    stream.write_all(body)
+}
+
+/// Encodes `text` in base64, for HTTP basic authentication
+fn base64(text: &str) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in text.as_bytes().chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}